@@ -0,0 +1,101 @@
+use rusqlite::{OptionalExtension, ToSql, params};
+
+use crate::library::Context;
+use crate::library::album::{Album, AlbumField, OTHER_MEDIA_TYPE};
+use crate::library::filter::{Expr, Sort};
+use super::SqlLibrary;
+use super::{Error, generate_sql};
+
+impl SqlLibrary{
+    /// album of the folder `path`, created with the folder name as title the first time
+    pub fn folder_album(&self, path: &str) -> Result<u64, Error>{
+        let conn = self.writer()?;
+        let title = path.rsplit('/').next().unwrap_or_default();
+        conn.execute(
+            "INSERT INTO Albums (
+                path,
+                title,
+                date,
+                thumbnail_path) values (?1, ?2, '', '')
+            ON CONFLICT(path) DO NOTHING",
+            params![path, title],
+        )?;
+        Ok(conn.query_row("SELECT id FROM Albums WHERE path = ?1", [path], |row| row.get(0))?)
+    }
+
+    pub fn update_album(&self, album: &Album) -> Result<(), Error>{
+        let conn = self.writer()?;
+        conn.execute(
+            "UPDATE Albums SET title = ?2, date = ?3, thumbnail_path = ?4 WHERE id = ?1",
+            params![album.id, album.title, album.date, album.thumbnail_path],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_album(&self, album_id: u64) -> Result<(), Error>{
+        let mut conn = self.writer()?;
+        let tx = conn.transaction()?;
+
+        tx.execute("UPDATE Videos SET media_id = NULL WHERE media_type = ?1 AND media_id = ?2", params![OTHER_MEDIA_TYPE, album_id])?;
+        tx.execute("DELETE FROM Albums WHERE id = ?1", [album_id])?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// true when no video is left in the album
+    pub fn album_is_empty(&self, album_id: u64) -> Result<bool, Error>{
+        let conn = self.reader()?;
+        let video: Option<u64> = conn.query_row(
+            "SELECT id FROM Videos WHERE media_type = ?1 AND media_id = ?2 LIMIT 1",
+            params![OTHER_MEDIA_TYPE, album_id], |row| row.get(0)).optional()?;
+        Ok(video.is_none())
+    }
+
+    fn albums_query<'a>(parameters: &'a [Expr<AlbumField>], order_by: &[Sort<AlbumField>],
+                    limit: Option<u64>, offset: Option<u64>) -> (String, Vec<&'a dyn ToSql>){
+        generate_sql(&format!("SELECT
+                                                    Albums.id,
+                                                    Albums.path,
+                                                    Albums.title,
+                                                    Albums.date,
+                                                    Albums.thumbnail_path,
+                                                    COUNT(Videos.id),
+                                                    COALESCE(MAX(Videos.adding), '')
+                                                FROM Albums
+                                                LEFT OUTER JOIN Videos ON Videos.media_type = {} AND Videos.media_id = Albums.id
+                                                ", OTHER_MEDIA_TYPE), parameters, None, Some("Albums.id"), order_by, limit, offset)
+    }
+
+    pub fn get_albums(&self, ctx: &Context, user: &String, parameters: &[Expr<AlbumField>], order_by: &[Sort<AlbumField>], limit: Option<u64>, offset: Option<u64>) -> Result<Vec<Album>, Error>{
+        let (sql, param) = SqlLibrary::albums_query(parameters, order_by, limit, offset);
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(&sql)?;
+
+        let rows = stmt.query_map(param.as_slice(), |row| {
+            Ok(Album{
+                user: user.clone(),
+                ctx: ctx.clone(),
+                id: row.get(0)?,
+                path: row.get(1)?,
+                title: row.get(2)?,
+                date: row.get(3)?,
+                thumbnail_path: row.get(4)?,
+                video_count: row.get(5)?,
+                adding: row.get(6)?,
+                video: Vec::new(),
+            })
+        })?;
+
+        let mut result = Vec::new();
+        for row in rows{
+            result.push(row?);
+        }
+        Ok(result)
+    }
+
+    pub fn count_albums(&self, parameters: &[Expr<AlbumField>]) -> Result<u64, Error>{
+        let (sql, param) = SqlLibrary::albums_query(parameters, &[], None, None);
+        self.count(&sql, &param)
+    }
+}
@@ -1,14 +1,155 @@
-use std::collections::HashMap;
-
-use crate::library::collection::{Collection, CollectionResult};
+use rusqlite::{params, Connection, ToSql};
+use crate::library::collection::{Collection, CollectionResult, FranchisePart};
+use crate::rustmdb::model::CollectionDetails;
 
+use crate::library::Context;
+use crate::library::filter::{Expr, Sort};
+use crate::library::collection::CollectionField;
 use super::SqlLibrary;
+use super::search::{self, Document};
 use super::{Error, generate_sql};
 
+/// system owned collections have no creator
+pub const FRANCHISE_CREATOR: &str = "";
+
+/// link every movie of the library in the TMDB collection `tmdb_id` to its franchise collection, if there is one
+pub fn link_franchise(conn: &Connection, tmdb_id: u64) -> Result<(), rusqlite::Error>{
+    conn.execute("DELETE FROM MovieCollectionLinks WHERE collection_id IN (SELECT id FROM Collections WHERE tmdb_id = ?1)", [tmdb_id])?;
+    conn.execute(
+        "INSERT OR IGNORE INTO MovieCollectionLinks (movie_id, collection_id)
+            SELECT Movies.id, Collections.id FROM Movies
+            INNER JOIN Collections ON Collections.tmdb_id = Movies.belongs_to_collection
+            WHERE Movies.belongs_to_collection = ?1",
+        [tmdb_id],
+    )?;
+    Ok(())
+}
+
+/// franchises left without any movie of the library
+pub fn delete_empty_franchises(conn: &Connection) -> Result<(), rusqlite::Error>{
+    let mut stmt = conn.prepare("SELECT id FROM Collections WHERE tmdb_id IS NOT NULL
+                                    AND id NOT IN (SELECT collection_id FROM MovieCollectionLinks)")?;
+    let ids = stmt.query_map([], |row| row.get(0))?.collect::<Result<Vec<u64>, _>>()?;
+    for id in ids{
+        conn.execute("DELETE FROM Collections WHERE id = ?1", [id])?;
+        search::unindex(conn, Document::Collection, id)?;
+    }
+    Ok(())
+}
+
 impl SqlLibrary{
-    pub fn create_collection(&self, user: &String, name: String)  -> Result<Collection, Error>{
-        let m_conn = self.conn.lock().unwrap();
-        m_conn.as_ref().unwrap().execute(
+    /// store a TMDB collection and keep its franchise collection up to date, returns the images to download
+    pub fn update_franchise(&self, collection: &CollectionDetails) -> Result<Vec<String>, Error>{
+        let mut conn = self.writer()?;
+        let tx = conn.transaction()?;
+
+        let overview = collection.overview.as_deref().unwrap_or_default();
+        let poster_path = collection.poster_path.as_deref().unwrap_or_default();
+        tx.execute(
+            "INSERT INTO TmdbCollections (
+                id,
+                name,
+                overview,
+                poster_path,
+                backdrop_path,
+                updated) values (?1, ?2, ?3, ?4, ?5, datetime('now'))
+            ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                overview = excluded.overview,
+                poster_path = excluded.poster_path,
+                backdrop_path = excluded.backdrop_path,
+                updated = excluded.updated",
+            params![collection.id, collection.name, overview, poster_path, collection.backdrop_path.as_deref().unwrap_or_default()],
+        )?;
+
+        tx.execute("DELETE FROM TmdbCollectionParts WHERE collection_id = ?1", [collection.id])?;
+        for part in &collection.parts{
+            tx.execute(
+                "INSERT OR REPLACE INTO TmdbCollectionParts (
+                    collection_id,
+                    movie_id,
+                    title,
+                    release_date,
+                    poster_path) values (?1, ?2, ?3, ?4, ?5)",
+                params![collection.id, part.id, part.title, part.release_date.as_deref().unwrap_or_default(),
+                        part.poster_path.as_deref().unwrap_or_default()],
+            )?;
+        }
+
+        tx.execute(
+            "INSERT INTO Collections (
+                name,
+                description,
+                creator,
+                creation_date,
+                poster_path,
+                tmdb_id) values (?1, ?2, ?3, datetime('now'), ?4, ?5)
+            ON CONFLICT(tmdb_id) DO UPDATE SET
+                name = excluded.name,
+                description = excluded.description,
+                poster_path = excluded.poster_path",
+            params![collection.name, overview, FRANCHISE_CREATOR, poster_path, collection.id],
+        )?;
+        let id: u64 = tx.query_row("SELECT id FROM Collections WHERE tmdb_id = ?1", [collection.id], |row| row.get(0))?;
+        search::index(&tx, Document::Collection, id)?;
+
+        link_franchise(&tx, collection.id)?;
+        delete_empty_franchises(&tx)?;
+
+        tx.commit()?;
+
+        Ok(collection.poster_path.iter().chain(collection.backdrop_path.iter()).cloned().collect())
+    }
+
+    /// the franchise collection of `tmdb_id` exists, `create_movie` links its movies
+    pub fn has_franchise(&self, tmdb_id: u64) -> Result<bool, Error>{
+        let conn = self.reader()?;
+        Ok(conn.query_row("SELECT COUNT(*) FROM Collections WHERE tmdb_id = ?1", [tmdb_id], |row| row.get::<_, u64>(0))? > 0)
+    }
+
+    /// TMDB ids of every franchise of the library
+    pub fn franchise_ids(&self) -> Result<Vec<u64>, Error>{
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare("SELECT tmdb_id FROM Collections WHERE tmdb_id IS NOT NULL")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        let mut result = Vec::new();
+        for row in rows{
+            result.push(row?);
+        }
+        Ok(result)
+    }
+
+    /// parts of the franchise without a video in the library, by release date
+    pub fn missing_franchise_parts(&self, tmdb_id: u64) -> Result<Vec<FranchisePart>, Error>{
+        let sql = "SELECT
+                            movie_id,
+                            title,
+                            release_date,
+                            poster_path
+                        FROM TmdbCollectionParts
+                        WHERE collection_id = ?1
+                        AND movie_id NOT IN (SELECT media_id FROM Videos WHERE media_type = 0 AND media_id IS NOT NULL)
+                        ORDER BY release_date = '', release_date";
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map([tmdb_id], |row| {
+            Ok(FranchisePart{
+                id: row.get(0)?,
+                title: row.get(1)?,
+                release_date: row.get(2)?,
+                poster_path: row.get(3)?,
+            })
+        })?;
+        let mut result = Vec::new();
+        for row in rows{
+            result.push(row?);
+        }
+        Ok(result)
+    }
+
+    pub fn create_collection(&self, ctx: &Context, user: &String, name: String)  -> Result<Collection, Error>{
+        let conn = self.writer()?;
+        conn.execute(
             "INSERT INTO Collections (
                 name,
                 description,
@@ -18,23 +159,30 @@ impl SqlLibrary{
             &[&name, user] 
         )?;
 
-        let id = m_conn.as_ref().unwrap().last_insert_rowid() as u64;
+        let id = conn.last_insert_rowid() as u64;
+        search::index(&conn, Document::Collection, id)?;
 
-        drop(m_conn);
+        drop(conn);
 
-        Ok(self.get_collection(user, id)?.unwrap())
+        Ok(self.get_collection(ctx, user, id)?.unwrap())
     }
 
-    pub fn update_collection(&self, user: &String, collection: &Collection)  -> Result<Collection, Error>{
-        let m_conn = self.conn.lock().unwrap();
-        m_conn.as_ref().unwrap().execute(
-            "INSERT OR REPLACE INTO Collections (
+    pub fn update_collection(&self, ctx: &Context, user: &String, collection: &Collection)  -> Result<Collection, Error>{
+        let conn = self.writer()?;
+        conn.execute(
+            "INSERT INTO Collections (
                 id,
                 name,
                 description,
                 creator,
                 creation_date,
-                poster_path) values (?1, ?2, ?3, ?4, ?5, ?6)",
+                poster_path) values (?1, ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                description = excluded.description,
+                creator = excluded.creator,
+                creation_date = excluded.creation_date,
+                poster_path = excluded.poster_path",
             &[&collection.id.to_string(), 
                     &collection.name,
                     &collection.description,
@@ -42,16 +190,17 @@ impl SqlLibrary{
                     &collection.creation_date,
                     &collection.poster_path,] 
         )?;
-        let id = m_conn.as_ref().unwrap().last_insert_rowid() as u64;
+        let id = conn.last_insert_rowid() as u64;
+        search::index(&conn, Document::Collection, id)?;
 
-        drop(m_conn);
+        drop(conn);
 
-        Ok(self.get_collection(user, id)?.unwrap())
+        Ok(self.get_collection(ctx, user, id)?.unwrap())
     }
 
     pub fn add_movie_collection(&self, collection_id: u64, movie_id: u64)  -> Result<(), Error>{
-        let m_conn = self.conn.lock().unwrap();
-        m_conn.as_ref().unwrap().execute(
+        let conn = self.writer()?;
+        conn.execute(
             "INSERT INTO MovieCollectionLinks (
                 movie_id,
                 collection_id) values (?1, ?2)",
@@ -61,8 +210,8 @@ impl SqlLibrary{
     }
 
     pub fn add_tv_collection(&self, collection_id: u64, tv_id: u64)  -> Result<(), Error>{
-        let m_conn = self.conn.lock().unwrap();
-        m_conn.as_ref().unwrap().execute(
+        let conn = self.writer()?;
+        conn.execute(
             "INSERT INTO TvCollectionLinks (
                 tv_id,
                 collection_id) values (?1, ?2)",
@@ -71,31 +220,33 @@ impl SqlLibrary{
         Ok(())
     }
 
-    pub fn get_collection(&self, user: &String, collection_id: u64)-> Result<Option<Collection>, Error>{
+    pub fn get_collection(&self, ctx: &Context, user: &String, collection_id: u64)-> Result<Option<Collection>, Error>{
         let sql = "SELECT 
                             id,
                             name,
                             description,
                             creator,
                             creation_date,
-                            poster_path
+                            poster_path,
+                            tmdb_id
                         FROM Collections
                         WHERE id= ?1";
         
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
         
         let rows = stmt.query_map(&[&collection_id.to_string()], |row| {
 
             Ok(Collection{
                 user: user.clone(),
+                ctx: ctx.clone(),
                 id: row.get(0)?,
                 name: row.get(1)?,
                 description: row.get(2)?,
                 creator: row.get(3)?,
                 creation_date: row.get(4)?,
                 poster_path: row.get(5)?,
+                tmdb_id: row.get(6)?,
                 movie: Vec::new(),
                 tv: Vec::new(),
             })
@@ -108,31 +259,37 @@ impl SqlLibrary{
         Ok(None)
     }
 
-    pub fn get_collections(&self, user: &String, parameters: &HashMap<String, 
-                        Option<(String, String)>>, order_by: &Option<String>, limit: Option<u64>, offset: Option<u64>) -> Result<Vec<CollectionResult>, Error>{
-        let (sql, param) = generate_sql("SELECT 
+    fn collections_query<'a>(parameters: &'a [Expr<CollectionField>], order_by: &[Sort<CollectionField>],
+                    limit: Option<u64>, offset: Option<u64>) -> (String, Vec<&'a dyn ToSql>){
+        generate_sql("SELECT 
                                                     Collections.id,
                                                     Collections.name,
                                                     Collections.creator,
                                                     Collections.creation_date,
-                                                    Collections.poster_path
+                                                    Collections.poster_path,
+                                                    Collections.tmdb_id
                                                 FROM Collections
                                                 LEFT OUTER JOIN MovieCollectionLinks ON Collections.id = MovieCollectionLinks.collection_id
                                                 LEFT OUTER JOIN TvCollectionLinks ON Collections.id = TvCollectionLinks.collection_id
-                                                ", &parameters, None, Some("Collections.id"), order_by, limit, offset);
+                                                ", parameters, None, Some("Collections.id"), order_by, limit, offset)
+    }
+
+    pub fn get_collections(&self, ctx: &Context, user: &String, parameters: &[Expr<CollectionField>], order_by: &[Sort<CollectionField>], limit: Option<u64>, offset: Option<u64>) -> Result<Vec<CollectionResult>, Error>{
+        let (sql, param) = SqlLibrary::collections_query(parameters, order_by, limit, offset);
         // println!("sql: {}", &sql);
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
     
         let rows = stmt.query_map(param.as_slice(), |row| {
             Ok(CollectionResult{ 
                 user: user.clone(),
+                ctx: ctx.clone(),
                 id: row.get(0)?,
                 name: row.get(1)?,
                 creator: row.get(2)?,
                 creation_date: row.get(3)?,
                 poster_path: row.get(4)?, 
+                tmdb_id: row.get(5)?,
                 })
             
         })?;
@@ -144,14 +301,20 @@ impl SqlLibrary{
         Ok(result)
     }
 
+    pub fn count_collections(&self, parameters: &[Expr<CollectionField>]) -> Result<u64, Error>{
+        let (sql, param) = SqlLibrary::collections_query(parameters, &[], None, None);
+        self.count(&sql, &param)
+    }
+
     pub fn delete_collection(&self, collection_id: u64) -> Result<(), Error>{
-        let mut m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_mut().unwrap();
+        let mut conn = self.writer()?;
         let tx = conn.transaction()?;
 
         tx.execute("DELETE FROM Collections
                         WHERE id=?1", &[&collection_id.to_string()])?;
         
+        search::unindex(&tx, Document::Collection, collection_id)?;
+
         tx.commit()?;
         
         Ok(())
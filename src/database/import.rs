@@ -0,0 +1,95 @@
+use rusqlite::params;
+
+use crate::library::import::{Import, PendingImport};
+use super::{SqlLibrary, Error};
+
+impl SqlLibrary{
+    /// mark the import as running, it stays pending until `end_import`
+    pub fn begin_import(&self, import: &Import) -> Result<(), Error>{
+        let (kind, media_id, season_number, episode_number) = import.key();
+        let conn = self.writer()?;
+        conn.execute(
+            "INSERT INTO PendingImports (
+                kind,
+                media_id,
+                season_number,
+                episode_number,
+                error,
+                attempts,
+                updated) values (?1, ?2, ?3, ?4, NULL, 1, datetime('now'))
+            ON CONFLICT(kind, media_id, season_number, episode_number) DO UPDATE SET
+                error = NULL,
+                attempts = attempts + 1,
+                updated = excluded.updated",
+            params![kind, media_id, season_number, episode_number],
+        )?;
+        Ok(())
+    }
+
+    pub fn fail_import(&self, import: &Import, error: &str) -> Result<(), Error>{
+        let (kind, media_id, season_number, episode_number) = import.key();
+        let conn = self.writer()?;
+        conn.execute(
+            "UPDATE PendingImports SET error = ?5, updated = datetime('now')
+                WHERE kind = ?1 AND media_id = ?2 AND season_number = ?3 AND episode_number = ?4",
+            params![kind, media_id, season_number, episode_number, error],
+        )?;
+        Ok(())
+    }
+
+    pub fn end_import(&self, import: &Import) -> Result<(), Error>{
+        let (kind, media_id, season_number, episode_number) = import.key();
+        let conn = self.writer()?;
+        conn.execute(
+            "DELETE FROM PendingImports
+                WHERE kind = ?1 AND media_id = ?2 AND season_number = ?3 AND episode_number = ?4",
+            params![kind, media_id, season_number, episode_number],
+        )?;
+        Ok(())
+    }
+
+    pub fn is_pending(&self, import: &Import) -> Result<bool, Error>{
+        let (kind, media_id, season_number, episode_number) = import.key();
+        let conn = self.reader()?;
+        let count: u64 = conn.query_row(
+            "SELECT COUNT(*) FROM PendingImports
+                WHERE kind = ?1 AND media_id = ?2 AND season_number = ?3 AND episode_number = ?4",
+            params![kind, media_id, season_number, episode_number],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    pub fn pending_imports(&self) -> Result<Vec<PendingImport>, Error>{
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(
+            "SELECT
+                kind,
+                media_id,
+                season_number,
+                episode_number,
+                error,
+                attempts,
+                updated
+            FROM PendingImports
+            ORDER BY updated")?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok(PendingImport{
+                kind: row.get(0)?,
+                media_id: row.get(1)?,
+                season_number: row.get(2)?,
+                episode_number: row.get(3)?,
+                error: row.get(4)?,
+                attempts: row.get(5)?,
+                updated: row.get(6)?,
+            })
+        })?;
+
+        let mut result = Vec::new();
+        for row in rows{
+            result.push(row?);
+        }
+        Ok(result)
+    }
+}
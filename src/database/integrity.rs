@@ -0,0 +1,141 @@
+use rusqlite::{Connection, params};
+
+use crate::library::album::OTHER_MEDIA_TYPE;
+use crate::library::integrity::IntegrityReport;
+use super::search::{self, Document};
+use super::{SqlLibrary, Error};
+
+const ORPHAN_PERSONS: &str = "SELECT id FROM Persons WHERE id NOT IN (
+                                    SELECT person_id FROM MovieCasts
+                                    UNION SELECT person_id FROM MovieCrews
+                                    UNION SELECT person_id FROM TvCasts
+                                    UNION SELECT person_id FROM TvCrews
+                                    UNION SELECT person_id FROM TvCreators
+                                    UNION SELECT person_id FROM EpisodeCasts
+                                    UNION SELECT person_id FROM EpisodeCrews)";
+
+const ORPHAN_KEYWORDS: &str = "SELECT id FROM Keywords WHERE id NOT IN (
+                                    SELECT keyword_id FROM MovieKeywordLinks
+                                    UNION SELECT keyword_id FROM TvKeywordLinks)";
+
+const ORPHAN_COMPANIES: &str = "SELECT id FROM Companies WHERE id NOT IN (
+                                    SELECT company_id FROM MovieCompanyLinks
+                                    UNION SELECT company_id FROM TvCompanyLinks)";
+
+const ORPHAN_NETWORKS: &str = "SELECT id FROM Networks WHERE id NOT IN (SELECT network_id FROM TvNetworkLinks)";
+
+const EPISODES_WITHOUT_SEASON: &str = "SELECT id FROM Episodes WHERE season_id NOT IN (SELECT id FROM Seasons)";
+
+fn videos_without_media() -> String{
+    format!("SELECT id FROM Videos WHERE media_id IS NOT NULL AND (
+                (media_type = 0 AND media_id NOT IN (SELECT id FROM Movies))
+                OR (media_type = 1 AND media_id NOT IN (SELECT id FROM Episodes))
+                OR (media_type = {} AND media_id NOT IN (SELECT id FROM Albums)))", OTHER_MEDIA_TYPE)
+}
+
+const MOVIE_COLLECTION_LINKS: &str = "SELECT collection_id, movie_id FROM MovieCollectionLinks
+                                    WHERE movie_id NOT IN (SELECT id FROM Movies)
+                                    OR collection_id NOT IN (SELECT id FROM Collections)";
+
+const TV_COLLECTION_LINKS: &str = "SELECT collection_id, tv_id FROM TvCollectionLinks
+                                    WHERE tv_id NOT IN (SELECT id FROM Tvs)
+                                    OR collection_id NOT IN (SELECT id FROM Collections)";
+
+const IMAGES: &str = "SELECT poster_path FROM Movies
+                        UNION SELECT backdrop_path FROM Movies
+                        UNION SELECT poster_path FROM Tvs
+                        UNION SELECT backdrop_path FROM Tvs
+                        UNION SELECT poster_path FROM Seasons
+                        UNION SELECT still_path FROM Episodes
+                        UNION SELECT profile_path FROM Persons
+                        UNION SELECT poster_path FROM Collections
+                        UNION SELECT poster_path FROM TmdbCollections
+                        UNION SELECT backdrop_path FROM TmdbCollections
+                        UNION SELECT logo_path FROM Companies
+                        UNION SELECT logo_path FROM Networks";
+
+fn ids(conn: &Connection, sql: &str) -> Result<Vec<u64>, rusqlite::Error>{
+    conn.prepare(sql)?.query_map([], |row| row.get(0))?.collect()
+}
+
+fn pairs(conn: &Connection, sql: &str) -> Result<Vec<(u64, u64)>, rusqlite::Error>{
+    conn.prepare(sql)?.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect()
+}
+
+fn scan(conn: &Connection, repair: bool) -> Result<IntegrityReport, rusqlite::Error>{
+    Ok(IntegrityReport{
+        repaired: repair,
+        orphan_persons: ids(conn, ORPHAN_PERSONS)?,
+        orphan_keywords: ids(conn, ORPHAN_KEYWORDS)?,
+        orphan_companies: ids(conn, ORPHAN_COMPANIES)?,
+        orphan_networks: ids(conn, ORPHAN_NETWORKS)?,
+        episodes_without_season: ids(conn, EPISODES_WITHOUT_SEASON)?,
+        videos_without_media: ids(conn, &videos_without_media())?,
+        movie_collection_links: pairs(conn, MOVIE_COLLECTION_LINKS)?,
+        tv_collection_links: pairs(conn, TV_COLLECTION_LINKS)?,
+        ..Default::default()
+    })
+}
+
+impl SqlLibrary{
+    /// Look for rows left behind by partial imports and deletions, and remove or unlink them if `repair`.
+    /// Images are left to the caller, see `image_paths`.
+    pub fn check_integrity(&self, repair: bool) -> Result<IntegrityReport, Error>{
+        if !repair{
+            let conn = self.reader()?;
+            return Ok(scan(&conn, false)?)
+        }
+
+        let mut conn = self.writer()?;
+        let tx = conn.transaction()?;
+        let report = scan(&tx, true)?;
+
+        for person_id in &report.orphan_persons{
+            tx.execute("DELETE FROM Persons WHERE id = ?1", [person_id])?;
+            search::unindex(&tx, Document::Person, *person_id)?;
+        }
+        for keyword_id in &report.orphan_keywords{
+            tx.execute("DELETE FROM Keywords WHERE id = ?1", [keyword_id])?;
+        }
+        for company_id in &report.orphan_companies{
+            tx.execute("DELETE FROM Companies WHERE id = ?1", [company_id])?;
+        }
+        for network_id in &report.orphan_networks{
+            tx.execute("DELETE FROM Networks WHERE id = ?1", [network_id])?;
+        }
+        for episode_id in &report.episodes_without_season{
+            tx.execute("DELETE FROM Episodes WHERE id = ?1", [episode_id])?;
+            search::unindex(&tx, Document::Episode, *episode_id)?;
+        }
+        // after the episodes, so their videos come back as unknown and can be matched again
+        for video_id in ids(&tx, &videos_without_media())?{
+            tx.execute("UPDATE Videos SET media_id = NULL WHERE id = ?1", [video_id])?;
+        }
+        for (collection_id, movie_id) in &report.movie_collection_links{
+            tx.execute("DELETE FROM MovieCollectionLinks WHERE collection_id = ?1 AND movie_id = ?2", params![collection_id, movie_id])?;
+        }
+        for (collection_id, tv_id) in &report.tv_collection_links{
+            tx.execute("DELETE FROM TvCollectionLinks WHERE collection_id = ?1 AND tv_id = ?2", params![collection_id, tv_id])?;
+        }
+
+        tx.commit()?;
+        Ok(report)
+    }
+
+    /// every image path referenced by the database, relative to the resource folder
+    pub fn image_paths(&self) -> Result<Vec<String>, Error>{
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(IMAGES)?;
+        let rows = stmt.query_map([], |row| row.get::<_, Option<String>>(0))?;
+
+        let mut result = Vec::new();
+        for row in rows{
+            if let Some(path) = row?{
+                if !path.is_empty(){
+                    result.push(path);
+                }
+            }
+        }
+        Ok(result)
+    }
+}
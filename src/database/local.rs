@@ -0,0 +1,166 @@
+use rusqlite::{Connection, OptionalExtension, params};
+
+use crate::library::local::{LocalMedia, LOCAL_ID_BASE};
+use crate::library::overrides::Editable;
+use super::search::{self, Document};
+use super::{SqlLibrary, Error};
+
+fn link_genres(conn: &Connection, genres: &str, links: &str, column: &str, id: u64, names: &[String]) -> Result<(), rusqlite::Error>{
+    for name in names{
+        conn.execute(
+            &format!("INSERT OR IGNORE INTO {links} (genre_id, {column}) SELECT id, ?1 FROM {genres} WHERE name = ?2",
+                genres = genres, links = links, column = column),
+            params![id, name],
+        )?;
+    }
+    Ok(())
+}
+
+/// next free local id of `table`, read on the transaction that inserts it
+fn next_local_id(conn: &Connection, table: &str) -> Result<u64, rusqlite::Error>{
+    let id: Option<u64> = conn.query_row(&format!("SELECT MAX(id) FROM {} WHERE id >= ?1", table), [LOCAL_ID_BASE], |row| row.get(0))?;
+    Ok(id.map(|id| id + 1).unwrap_or(LOCAL_ID_BASE))
+}
+
+impl SqlLibrary{
+    /// returns the id of the movie, its images are copied by the caller once it exists, see `LocalMedia::import_images`
+    pub fn create_local_movie(&self, movie: &LocalMedia) -> Result<u64, Error>{
+        let mut conn = self.writer()?;
+        let tx = conn.transaction()?;
+        let id = next_local_id(&tx, "Movies")?;
+        let (poster_path, backdrop_path) = movie.image_paths(Editable::Movie, id);
+
+        tx.execute(
+            "INSERT INTO Movies (
+                id,
+                original_title,
+                original_language,
+                title,
+                release_date,
+                overview,
+                popularity,
+                poster_path,
+                backdrop_path,
+                vote_average,
+                vote_count,
+                tagline,
+                status,
+                adult,
+                updated) values (?1, ?2, '', ?2, ?3, ?4, 0, ?5, ?6, 0, 0, '', 'Local', 0, datetime('now'))",
+            params![id, movie.title, movie.release_date, movie.overview, poster_path, backdrop_path],
+        )?;
+        link_genres(&tx, "MovieGenres", "MovieGenreLinks", "movie_id", id, &movie.genres)?;
+        search::index(&tx, Document::Movie, id)?;
+
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /// returns the id of the tv, see `create_local_movie`
+    pub fn create_local_tv(&self, tv: &LocalMedia) -> Result<u64, Error>{
+        let mut conn = self.writer()?;
+        let tx = conn.transaction()?;
+        let id = next_local_id(&tx, "Tvs")?;
+        let (poster_path, backdrop_path) = tv.image_paths(Editable::Tv, id);
+
+        tx.execute(
+            "INSERT INTO Tvs (
+                id,
+                original_title,
+                original_language,
+                title,
+                release_date,
+                overview,
+                popularity,
+                poster_path,
+                backdrop_path,
+                status,
+                vote_average,
+                vote_count,
+                in_production,
+                number_of_episodes,
+                number_of_seasons,
+                episode_run_time,
+                updated) values (?1, ?2, '', ?2, ?3, ?4, 0, ?5, ?6, 'Local', 0, 0, 0, 0, 0, 0, datetime('now'))",
+            params![id, tv.title, tv.release_date, tv.overview, poster_path, backdrop_path],
+        )?;
+        link_genres(&tx, "TvGenres", "TvGenreLinks", "tv_id", id, &tv.genres)?;
+        search::index(&tx, Document::Tv, id)?;
+
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /// add an episode to a local tv, its season is created with the first of its episodes
+    pub fn create_local_episode(&self, tv_id: u64, season_number: u64, episode_number: u64,
+                                title: &str, release_date: &str, overview: &str) -> Result<u64, Error>{
+        let mut conn = self.writer()?;
+        let tx = conn.transaction()?;
+
+        let season_id = tx.query_row("SELECT id FROM Seasons WHERE tv_id = ?1 AND season_number = ?2",
+            params![tv_id, season_number], |row| row.get(0)).optional()?;
+        let season_id = match season_id{
+            Some(season_id) => season_id,
+            None => {
+                // no ON CONFLICT: an id already taken by another season fails the episode
+                let season_id = next_local_id(&tx, "Seasons")?;
+                tx.execute(
+                    "INSERT INTO Seasons (
+                        id,
+                        tv_id,
+                        season_number,
+                        episode_count,
+                        title,
+                        overview,
+                        poster_path,
+                        release_date,
+                        updated) values (?1, ?2, ?3, 0, ?4, '', '', ?5, datetime('now'))",
+                    params![season_id, tv_id, season_number, format!("Season {}", season_number), release_date],
+                )?;
+                season_id
+            },
+        };
+        let episode_id = next_local_id(&tx, "Episodes")?;
+        tx.execute(
+            "INSERT INTO Episodes (
+                id,
+                season_id,
+                tv_id,
+                season_number,
+                episode_number,
+                release_date,
+                title,
+                overview,
+                vote_average,
+                vote_count,
+                updated) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, 0, 0, datetime('now'))",
+            params![episode_id, season_id, tv_id, season_number, episode_number, release_date, title, overview],
+        )?;
+        tx.execute("UPDATE Seasons SET episode_count = episode_count + 1 WHERE id = ?1", [season_id])?;
+        tx.execute("UPDATE Tvs SET
+                        number_of_episodes = (SELECT COUNT(*) FROM Episodes WHERE tv_id = ?1),
+                        number_of_seasons = (SELECT COUNT(*) FROM Seasons WHERE tv_id = ?1)
+                    WHERE id = ?1", [tv_id])?;
+        search::index(&tx, Document::Episode, episode_id)?;
+
+        tx.commit()?;
+        Ok(episode_id)
+    }
+
+    /// local movie or tv of `table` titled `title`, so entries read from files are only created once
+    pub fn find_local(&self, table: &str, title: &str) -> Result<Option<u64>, Error>{
+        let conn = self.reader()?;
+        Ok(conn.query_row(
+            &format!("SELECT id FROM {} WHERE id >= ?1 AND title = ?2 ORDER BY id LIMIT 1", table),
+            params![LOCAL_ID_BASE, title], |row| row.get(0)).optional()?)
+    }
+
+    /// id of an episode already in the database, whether it has videos or not
+    pub fn get_episode_id(&self, tv_id: u64, season_number: u64, episode_number: u64) -> Result<Option<u64>, Error>{
+        let conn = self.reader()?;
+        Ok(conn.query_row(
+            "SELECT id from Episodes
+             WHERE tv_id = ?1 and season_number = ?2 and episode_number = ?3",
+            params![tv_id, season_number, episode_number], |row| row.get(0)).optional()?)
+    }
+}
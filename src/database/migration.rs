@@ -0,0 +1,1204 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{Connection, Transaction};
+
+use super::{Error, ErrorKind, search};
+
+/// One ordered step of the schema, applied in its own transaction.
+/// The schema version of a database is the number of steps already applied.
+struct Migration{
+    description: &'static str,
+    up: fn(&Transaction) -> Result<(), rusqlite::Error>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration{ description: "initial schema", up: initial_schema },
+    Migration{ description: "full text search index", up: search_index },
+    Migration{ description: "foreign keys and indexes", up: foreign_keys },
+    Migration{ description: "pending imports", up: pending_imports },
+    Migration{ description: "sync state", up: sync_state },
+    Migration{ description: "metadata overrides", up: overrides },
+    Migration{ description: "albums", up: albums },
+    Migration{ description: "companies, networks, countries and languages", up: production },
+    Migration{ description: "movie runtime, budget, revenue, homepage and franchise", up: movie_details },
+    Migration{ description: "franchise collections", up: franchises },
+    Migration{ description: "tv creators, guest stars and episode stills", up: guest_stars },
+    Migration{ description: "person details", up: person_details },
+    Migration{ description: "person foreign keys", up: person_foreign_keys },
+];
+
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+pub fn schema_version(conn: &Connection) -> Result<u32, Error>{
+    Ok(conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+}
+
+pub fn migrate(conn: &mut Connection, path: &str) -> Result<(), Error>{
+    let version = schema_version(conn)?;
+
+    if version > SCHEMA_VERSION{
+        return Err(Error::new(ErrorKind::Version,
+            format!("database schema version {} is newer than supported version {}", version, SCHEMA_VERSION),
+            &format!("migrate({})", path)))
+    }
+
+    if version == SCHEMA_VERSION{
+        return Ok(())
+    }
+
+    let table_count: u64 = conn.query_row("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'", [], |row| row.get(0))?;
+    if table_count > 0{
+        backup(conn, path, version)?;
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize){
+        let target = index as u32 + 1;
+        let tx = conn.transaction()?;
+        (migration.up)(&tx).map_err(|e| Error::new(ErrorKind::Migration, e.to_string(),
+            &format!("migration {} ({})", target, migration.description)))?;
+        if target == SCHEMA_VERSION{
+            create_views(&tx)?;
+            search::fill_index(&tx)?;
+        }
+        tx.pragma_update(None, "user_version", target)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+/// copy the database next to itself before touching its schema
+fn backup(conn: &Connection, path: &str, version: u32) -> Result<(), Error>{
+    if path.is_empty() || path == ":memory:"{
+        return Ok(())
+    }
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let backup_path = format!("{}.v{}-{}.bak", path, version, timestamp);
+    conn.execute("VACUUM INTO ?1", [&backup_path])
+        .map_err(|e| Error::new(ErrorKind::Backup, e.to_string(), &format!("backup({})", backup_path)))?;
+    Ok(())
+}
+
+fn initial_schema(conn: &Transaction) -> Result<(), rusqlite::Error>{
+    //video part
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS Videos (
+            id INTEGER PRIMARY KEY NOT NULL,
+            path TEXT NOT NULL UNIQUE,
+            media_type INTEGER,
+            media_id INTEGER,
+            duration INTEGER,
+            bit_rate INTEGER,
+            codec TEXT,
+            width INTEGER,
+            height INTEGER,
+            size INTEGER,
+            adding TEXT)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS WatchTimes (
+            video_id INTEGER NOT NULL,
+            user_name INTEGER NOT NULL,
+            watch_time INTEGER,
+            last_watch TEXT,
+            unique(video_id, user_name))",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS Audios (
+            video_id INTEGER NOT NULL,
+            language TEXT,
+            unique(video_id, language))",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS Subtitles (
+            video_id INTEGER NOT NULL,
+            language TEXT,
+            unique(video_id, language))",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS Chapters (
+            video_id INTEGER NOT NULL,
+            ord INTEGER NOT NULL,
+            start INTEGER NOT NULL,
+            title TEXT,
+            unique(video_id, ord))",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS VideoMarkers (
+            video_id INTEGER PRIMARY KEY NOT NULL,
+            intro_start INTEGER,
+            intro_end INTEGER,
+            credits_start INTEGER)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS SeasonMarkers (
+            tv_id INTEGER NOT NULL,
+            season_number INTEGER NOT NULL,
+            intro_start INTEGER,
+            intro_end INTEGER,
+            credits_start INTEGER,
+            unique(tv_id, season_number))",
+        [],
+    )?;
+    // Movie Part
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS Movies (
+            id INTEGER PRIMARY KEY NOT NULL,
+            original_title TEXT,
+            original_language TEXT,
+            title TEXT,
+            release_date TEXT,
+            overview TEXT,
+            popularity FLOAT,
+            poster_path TEXT,
+            backdrop_path TEXT,
+            vote_average FLOAT,
+            vote_count INTEGER,
+            tagline TEXT,
+            status TEXT,
+            adult BOOL,
+            updated TEXT)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS MovieGenres (
+            id INTEGER PRIMARY KEY NOT NULL,
+            name TEXT)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS MovieGenreLinks (
+            movie_id INTEGER NOT NULL,
+            genre_id INTEGER NOT NULL,
+            unique(movie_id,genre_id))",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS MovieCollectionLinks (
+            movie_id INTEGER NOT NULL,
+            collection_id INTEGER NOT NULL,
+            unique(movie_id, collection_id))",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS MovieKeywordLinks (
+            movie_id INTEGER NOT NULL,
+            keyword_id INTEGER NOT NULL,
+            unique(movie_id,keyword_id))",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS MovieTrailers (
+            movie_id INTEGER NOT NULL,
+            name TEXT,
+            youtube_id TEXT,
+            unique(movie_id,youtube_id))",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS MovieCasts (
+            movie_id INTEGER NOT NULL,
+            person_id TEXT,
+            character TEXT,
+            ord INTEGER,
+            unique(movie_id,person_id,character))",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS MovieCrews (
+            movie_id INTEGER NOT NULL,
+            person_id TEXT,
+            job TEXT,
+            unique(movie_id,person_id,job))",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS MovieUserWatched (
+            movie_id INTEGER NOT NULL,
+            user_name TEXT,
+            watched INTEGER,
+            vote_user FLOAT,
+            unique(movie_id,user_name))",
+        [],
+    )?;
+    // Tv Part
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS Tvs (
+            id INTEGER PRIMARY KEY NOT NULL,
+            original_title TEXT,
+            original_language TEXT,
+            title TEXT,
+            release_date TEXT,
+            overview TEXT,
+            popularity FLOAT,
+            poster_path TEXT,
+            backdrop_path TEXT,
+            status TEXT,
+            vote_average FLOAT,
+            vote_count INTEGER,
+            in_production BOOL, 
+            number_of_episodes INTEGER,
+            number_of_seasons INTEGER,
+            episode_run_time INTEGER,
+            updated TEXT)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS TvGenres (
+            id INTEGER PRIMARY KEY NOT NULL,
+            name TEXT)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS TvGenreLinks (
+            tv_id INTEGER NOT NULL,
+            genre_id INTEGER NOT NULL,
+            unique(tv_id,genre_id))",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS TvCollectionLinks (
+            tv_id INTEGER NOT NULL,
+            collection_id INTEGER NOT NULL,
+            unique(tv_id, collection_id))",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS TvKeywordLinks (
+            tv_id INTEGER NOT NULL,
+            keyword_id INTEGER NOT NULL,
+            unique(tv_id,keyword_id))",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS TvTrailers (
+            tv_id INTEGER NOT NULL,
+            name TEXT,
+            youtube_id TEXT,
+            unique(tv_id,youtube_id))",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS TvCasts (
+            tv_id INTEGER NOT NULL,
+            person_id TEXT,
+            character TEXT,
+            ord INTEGER,
+            unique(tv_id, person_id, character))",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS TvCrews (
+            tv_id INTEGER NOT NULL,
+            person_id TEXT,
+            job TEXT,
+            unique(tv_id, person_id, job))",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS Seasons (
+            id INTEGER PRIMARY KEY NOT NULL,
+            tv_id INTEGER NOT NULL,
+            season_number INTEGER NOT NULL,
+            episode_count INTEGER,
+            title TEXT,
+            overview TEXT,
+            poster_path TEXT,
+            release_date TEXT,
+            updated TEXT)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS Episodes (
+            id INTEGER PRIMARY KEY NOT NULL,
+            season_id INTEGER NOT NULL,
+            tv_id INTEGER NOT NULL,
+            season_number INTEGER NOT NULL,
+            episode_number INTEGER NOT NULL,
+            release_date TEXT,
+            title TEXT,
+            overview TEXT,
+            vote_average FLOAT,
+            vote_count INTEGER,
+            updated TEXT)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS EpisodeCasts (
+            episode_id INTEGER NOT NULL,
+            person_id INTEGER NOT NULL,
+            character TEXT,
+            ord INTEGER,
+            unique(episode_id,person_id,character))",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS EpisodeCrews (
+            episode_id INTEGER NOT NULL,
+            person_id INTEGER NOT NULL,
+            job TEXT,
+            unique(episode_id,person_id,job))",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS EpisodesUserWatched (
+            episode_id INTEGER NOT NULL,
+            user_name TEXT,
+            watched INTEGER,
+            vote_user FLOAT,
+            unique(episode_id,user_name))",
+        [],
+    )?;
+    //Person Part
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS Persons (
+            id INTEGER PRIMARY KEY NOT NULL,
+            birthday TEXT,
+            known_for_department TEXT,
+            deathday TEXT,
+            name TEXT,
+            gender INTEGER,
+            biography TEXT,
+            popularity FLOAT,
+            place_of_birth TEXT,
+            profile_path TEXT)",
+        []
+    )?;
+
+    //keywords
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS Keywords (
+            id INTEGER PRIMARY KEY NOT NULL,
+            name TEXT)",
+        [],
+    )?;
+
+    //user
+
+    //Person Part
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS Collections (
+            id INTEGER PRIMARY KEY NOT NULL,
+            name TEXT,
+            description TEXT,
+            creator TEXT,
+            creation_date TEXT,
+            poster_path TEXT,
+            unique(name, creator))",
+        []
+    )?;
+
+    Ok(())
+}
+
+/// filled once the schema is up to date, see `migrate`
+fn search_index(conn: &Transaction) -> Result<(), rusqlite::Error>{
+    search::create_index(conn)
+}
+
+/// Rebuild `table` from `definition`, sqlite can't add a foreign key to an existing table.
+/// Rows pointing to a missing parent are left behind.
+fn rebuild(conn: &Transaction, table: &str, definition: &str, columns: &str, parents: &[(&str, &str)]) -> Result<(), rusqlite::Error>{
+    conn.execute(&format!("CREATE TABLE {}_new ({})", table, definition), [])?;
+    let mut sql = format!("INSERT OR IGNORE INTO {}_new ({}) SELECT {} FROM {}", table, columns, columns, table);
+    for (index, (column, parent)) in parents.iter().enumerate(){
+        sql += if index == 0 {" WHERE "} else {" AND "};
+        sql += &format!("{} IN (SELECT id FROM {})", column, parent);
+    }
+    conn.execute(&sql, [])?;
+    conn.execute(&format!("DROP TABLE {}", table), [])?;
+    conn.execute(&format!("ALTER TABLE {}_new RENAME TO {}", table, table), [])?;
+    Ok(())
+}
+
+/// Views would block the renames of `rebuild`, they are recreated after the last step.
+fn drop_views(conn: &Transaction) -> Result<(), rusqlite::Error>{
+    let views: Vec<String> = conn.prepare("SELECT name FROM sqlite_master WHERE type = 'view'")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    for view in views{
+        conn.execute(&format!("DROP VIEW {}", view), [])?;
+    }
+    Ok(())
+}
+
+/// Links cascade on the deletion of their movie, tv, episode, video or collection.
+/// The person_id of the credits is referenced later by `person_foreign_keys`.
+fn foreign_keys(conn: &Transaction) -> Result<(), rusqlite::Error>{
+    drop_views(conn)?;
+
+    //video part
+    rebuild(conn, "WatchTimes",
+        "video_id INTEGER NOT NULL REFERENCES Videos(id) ON DELETE CASCADE,
+        user_name TEXT NOT NULL,
+        watch_time INTEGER,
+        last_watch TEXT,
+        unique(video_id, user_name)",
+        "video_id, user_name, watch_time, last_watch", &[("video_id", "Videos")])?;
+
+    rebuild(conn, "Audios",
+        "video_id INTEGER NOT NULL REFERENCES Videos(id) ON DELETE CASCADE,
+        language TEXT,
+        unique(video_id, language)",
+        "video_id, language", &[("video_id", "Videos")])?;
+
+    rebuild(conn, "Subtitles",
+        "video_id INTEGER NOT NULL REFERENCES Videos(id) ON DELETE CASCADE,
+        language TEXT,
+        unique(video_id, language)",
+        "video_id, language", &[("video_id", "Videos")])?;
+
+    rebuild(conn, "Chapters",
+        "video_id INTEGER NOT NULL REFERENCES Videos(id) ON DELETE CASCADE,
+        ord INTEGER NOT NULL,
+        start INTEGER NOT NULL,
+        title TEXT,
+        unique(video_id, ord)",
+        "video_id, ord, start, title", &[("video_id", "Videos")])?;
+
+    rebuild(conn, "VideoMarkers",
+        "video_id INTEGER PRIMARY KEY NOT NULL REFERENCES Videos(id) ON DELETE CASCADE,
+        intro_start INTEGER,
+        intro_end INTEGER,
+        credits_start INTEGER",
+        "video_id, intro_start, intro_end, credits_start", &[("video_id", "Videos")])?;
+
+    // Movie Part
+    rebuild(conn, "MovieGenreLinks",
+        "movie_id INTEGER NOT NULL REFERENCES Movies(id) ON DELETE CASCADE,
+        genre_id INTEGER NOT NULL REFERENCES MovieGenres(id) ON DELETE CASCADE,
+        unique(movie_id, genre_id)",
+        "movie_id, genre_id", &[("movie_id", "Movies"), ("genre_id", "MovieGenres")])?;
+
+    rebuild(conn, "MovieCollectionLinks",
+        "movie_id INTEGER NOT NULL REFERENCES Movies(id) ON DELETE CASCADE,
+        collection_id INTEGER NOT NULL REFERENCES Collections(id) ON DELETE CASCADE,
+        unique(movie_id, collection_id)",
+        "movie_id, collection_id", &[("movie_id", "Movies"), ("collection_id", "Collections")])?;
+
+    rebuild(conn, "MovieKeywordLinks",
+        "movie_id INTEGER NOT NULL REFERENCES Movies(id) ON DELETE CASCADE,
+        keyword_id INTEGER NOT NULL REFERENCES Keywords(id) ON DELETE CASCADE,
+        unique(movie_id, keyword_id)",
+        "movie_id, keyword_id", &[("movie_id", "Movies"), ("keyword_id", "Keywords")])?;
+
+    rebuild(conn, "MovieTrailers",
+        "movie_id INTEGER NOT NULL REFERENCES Movies(id) ON DELETE CASCADE,
+        name TEXT,
+        youtube_id TEXT,
+        unique(movie_id, youtube_id)",
+        "movie_id, name, youtube_id", &[("movie_id", "Movies")])?;
+
+    rebuild(conn, "MovieCasts",
+        "movie_id INTEGER NOT NULL REFERENCES Movies(id) ON DELETE CASCADE,
+        person_id INTEGER NOT NULL,
+        character TEXT,
+        ord INTEGER,
+        unique(movie_id, person_id, character)",
+        "movie_id, person_id, character, ord", &[("movie_id", "Movies")])?;
+
+    rebuild(conn, "MovieCrews",
+        "movie_id INTEGER NOT NULL REFERENCES Movies(id) ON DELETE CASCADE,
+        person_id INTEGER NOT NULL,
+        job TEXT,
+        unique(movie_id, person_id, job)",
+        "movie_id, person_id, job", &[("movie_id", "Movies")])?;
+
+    rebuild(conn, "MovieUserWatched",
+        "movie_id INTEGER NOT NULL REFERENCES Movies(id) ON DELETE CASCADE,
+        user_name TEXT,
+        watched INTEGER,
+        vote_user FLOAT,
+        unique(movie_id, user_name)",
+        "movie_id, user_name, watched, vote_user", &[("movie_id", "Movies")])?;
+
+    // Tv Part
+    rebuild(conn, "TvGenreLinks",
+        "tv_id INTEGER NOT NULL REFERENCES Tvs(id) ON DELETE CASCADE,
+        genre_id INTEGER NOT NULL REFERENCES TvGenres(id) ON DELETE CASCADE,
+        unique(tv_id, genre_id)",
+        "tv_id, genre_id", &[("tv_id", "Tvs"), ("genre_id", "TvGenres")])?;
+
+    rebuild(conn, "TvCollectionLinks",
+        "tv_id INTEGER NOT NULL REFERENCES Tvs(id) ON DELETE CASCADE,
+        collection_id INTEGER NOT NULL REFERENCES Collections(id) ON DELETE CASCADE,
+        unique(tv_id, collection_id)",
+        "tv_id, collection_id", &[("tv_id", "Tvs"), ("collection_id", "Collections")])?;
+
+    rebuild(conn, "TvKeywordLinks",
+        "tv_id INTEGER NOT NULL REFERENCES Tvs(id) ON DELETE CASCADE,
+        keyword_id INTEGER NOT NULL REFERENCES Keywords(id) ON DELETE CASCADE,
+        unique(tv_id, keyword_id)",
+        "tv_id, keyword_id", &[("tv_id", "Tvs"), ("keyword_id", "Keywords")])?;
+
+    rebuild(conn, "TvTrailers",
+        "tv_id INTEGER NOT NULL REFERENCES Tvs(id) ON DELETE CASCADE,
+        name TEXT,
+        youtube_id TEXT,
+        unique(tv_id, youtube_id)",
+        "tv_id, name, youtube_id", &[("tv_id", "Tvs")])?;
+
+    rebuild(conn, "TvCasts",
+        "tv_id INTEGER NOT NULL REFERENCES Tvs(id) ON DELETE CASCADE,
+        person_id INTEGER NOT NULL,
+        character TEXT,
+        ord INTEGER,
+        unique(tv_id, person_id, character)",
+        "tv_id, person_id, character, ord", &[("tv_id", "Tvs")])?;
+
+    rebuild(conn, "TvCrews",
+        "tv_id INTEGER NOT NULL REFERENCES Tvs(id) ON DELETE CASCADE,
+        person_id INTEGER NOT NULL,
+        job TEXT,
+        unique(tv_id, person_id, job)",
+        "tv_id, person_id, job", &[("tv_id", "Tvs")])?;
+
+    rebuild(conn, "Seasons",
+        "id INTEGER PRIMARY KEY NOT NULL,
+        tv_id INTEGER NOT NULL REFERENCES Tvs(id) ON DELETE CASCADE,
+        season_number INTEGER NOT NULL,
+        episode_count INTEGER,
+        title TEXT,
+        overview TEXT,
+        poster_path TEXT,
+        release_date TEXT,
+        updated TEXT",
+        "id, tv_id, season_number, episode_count, title, overview, poster_path, release_date, updated", &[("tv_id", "Tvs")])?;
+
+    rebuild(conn, "SeasonMarkers",
+        "tv_id INTEGER NOT NULL REFERENCES Tvs(id) ON DELETE CASCADE,
+        season_number INTEGER NOT NULL,
+        intro_start INTEGER,
+        intro_end INTEGER,
+        credits_start INTEGER,
+        unique(tv_id, season_number)",
+        "tv_id, season_number, intro_start, intro_end, credits_start", &[("tv_id", "Tvs")])?;
+
+    rebuild(conn, "Episodes",
+        "id INTEGER PRIMARY KEY NOT NULL,
+        season_id INTEGER NOT NULL REFERENCES Seasons(id) ON DELETE CASCADE,
+        tv_id INTEGER NOT NULL REFERENCES Tvs(id) ON DELETE CASCADE,
+        season_number INTEGER NOT NULL,
+        episode_number INTEGER NOT NULL,
+        release_date TEXT,
+        title TEXT,
+        overview TEXT,
+        vote_average FLOAT,
+        vote_count INTEGER,
+        updated TEXT",
+        "id, season_id, tv_id, season_number, episode_number, release_date, title, overview, vote_average, vote_count, updated",
+        &[("season_id", "Seasons"), ("tv_id", "Tvs")])?;
+
+    rebuild(conn, "EpisodeCasts",
+        "episode_id INTEGER NOT NULL REFERENCES Episodes(id) ON DELETE CASCADE,
+        person_id INTEGER NOT NULL,
+        character TEXT,
+        ord INTEGER,
+        unique(episode_id, person_id, character)",
+        "episode_id, person_id, character, ord", &[("episode_id", "Episodes")])?;
+
+    rebuild(conn, "EpisodeCrews",
+        "episode_id INTEGER NOT NULL REFERENCES Episodes(id) ON DELETE CASCADE,
+        person_id INTEGER NOT NULL,
+        job TEXT,
+        unique(episode_id, person_id, job)",
+        "episode_id, person_id, job", &[("episode_id", "Episodes")])?;
+
+    rebuild(conn, "EpisodesUserWatched",
+        "episode_id INTEGER NOT NULL REFERENCES Episodes(id) ON DELETE CASCADE,
+        user_name TEXT,
+        watched INTEGER,
+        vote_user FLOAT,
+        unique(episode_id, user_name)",
+        "episode_id, user_name, watched, vote_user", &[("episode_id", "Episodes")])?;
+
+    // join columns of the searches, the first column of each link is already covered by its unique index
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS VideosMediaIndex ON Videos(media_type, media_id);
+        CREATE INDEX IF NOT EXISTS MovieGenreLinksGenreIndex ON MovieGenreLinks(genre_id);
+        CREATE INDEX IF NOT EXISTS MovieCollectionLinksCollectionIndex ON MovieCollectionLinks(collection_id);
+        CREATE INDEX IF NOT EXISTS MovieKeywordLinksKeywordIndex ON MovieKeywordLinks(keyword_id);
+        CREATE INDEX IF NOT EXISTS MovieCastsPersonIndex ON MovieCasts(person_id);
+        CREATE INDEX IF NOT EXISTS MovieCrewsPersonIndex ON MovieCrews(person_id);
+        CREATE INDEX IF NOT EXISTS TvGenreLinksGenreIndex ON TvGenreLinks(genre_id);
+        CREATE INDEX IF NOT EXISTS TvCollectionLinksCollectionIndex ON TvCollectionLinks(collection_id);
+        CREATE INDEX IF NOT EXISTS TvKeywordLinksKeywordIndex ON TvKeywordLinks(keyword_id);
+        CREATE INDEX IF NOT EXISTS TvCastsPersonIndex ON TvCasts(person_id);
+        CREATE INDEX IF NOT EXISTS TvCrewsPersonIndex ON TvCrews(person_id);
+        CREATE INDEX IF NOT EXISTS SeasonsTvIndex ON Seasons(tv_id, season_number);
+        CREATE INDEX IF NOT EXISTS EpisodesTvIndex ON Episodes(tv_id, season_number, episode_number);
+        CREATE INDEX IF NOT EXISTS EpisodesSeasonIndex ON Episodes(season_id);
+        CREATE INDEX IF NOT EXISTS EpisodeCastsPersonIndex ON EpisodeCasts(person_id);
+        CREATE INDEX IF NOT EXISTS EpisodeCrewsPersonIndex ON EpisodeCrews(person_id);"
+    )?;
+    Ok(())
+}
+
+fn pending_imports(conn: &Transaction) -> Result<(), rusqlite::Error>{
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS PendingImports (
+            kind TEXT NOT NULL,
+            media_id INTEGER NOT NULL,
+            season_number INTEGER NOT NULL DEFAULT 0,
+            episode_number INTEGER NOT NULL DEFAULT 0,
+            error TEXT,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            updated TEXT,
+            unique(kind, media_id, season_number, episode_number))",
+        [],
+    )?;
+    Ok(())
+}
+
+fn sync_state(conn: &Transaction) -> Result<(), rusqlite::Error>{
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS SyncState (
+            name TEXT PRIMARY KEY,
+            value TEXT)",
+        [],
+    )?;
+    Ok(())
+}
+
+fn overrides(conn: &Transaction) -> Result<(), rusqlite::Error>{
+    conn.execute("ALTER TABLE Movies ADD COLUMN sort_title TEXT", [])?;
+    conn.execute("ALTER TABLE Tvs ADD COLUMN sort_title TEXT", [])?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS Overrides (
+            kind TEXT NOT NULL,
+            media_id INTEGER NOT NULL,
+            field TEXT NOT NULL,
+            value TEXT,
+            provider_value TEXT,
+            unique(kind, media_id, field))",
+        [],
+    )?;
+    Ok(())
+}
+
+fn albums(conn: &Transaction) -> Result<(), rusqlite::Error>{
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS Albums (
+            id INTEGER PRIMARY KEY NOT NULL,
+            path TEXT NOT NULL UNIQUE,
+            title TEXT,
+            date TEXT,
+            thumbnail_path TEXT)",
+        [],
+    )?;
+    Ok(())
+}
+
+fn production(conn: &Transaction) -> Result<(), rusqlite::Error>{
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS Companies (
+            id INTEGER PRIMARY KEY NOT NULL,
+            name TEXT,
+            logo_path TEXT,
+            origin_country TEXT);
+        CREATE TABLE IF NOT EXISTS Networks (
+            id INTEGER PRIMARY KEY NOT NULL,
+            name TEXT,
+            logo_path TEXT,
+            origin_country TEXT);
+        CREATE TABLE IF NOT EXISTS Countries (
+            code TEXT PRIMARY KEY NOT NULL,
+            name TEXT);
+        CREATE TABLE IF NOT EXISTS Languages (
+            code TEXT PRIMARY KEY NOT NULL,
+            name TEXT);
+        CREATE TABLE IF NOT EXISTS MovieCompanyLinks (
+            movie_id INTEGER NOT NULL REFERENCES Movies(id) ON DELETE CASCADE,
+            company_id INTEGER NOT NULL REFERENCES Companies(id) ON DELETE CASCADE,
+            unique(movie_id, company_id));
+        CREATE TABLE IF NOT EXISTS MovieCountryLinks (
+            movie_id INTEGER NOT NULL REFERENCES Movies(id) ON DELETE CASCADE,
+            country_code TEXT NOT NULL REFERENCES Countries(code) ON DELETE CASCADE,
+            unique(movie_id, country_code));
+        CREATE TABLE IF NOT EXISTS MovieLanguageLinks (
+            movie_id INTEGER NOT NULL REFERENCES Movies(id) ON DELETE CASCADE,
+            language_code TEXT NOT NULL REFERENCES Languages(code) ON DELETE CASCADE,
+            unique(movie_id, language_code));
+        CREATE TABLE IF NOT EXISTS TvCompanyLinks (
+            tv_id INTEGER NOT NULL REFERENCES Tvs(id) ON DELETE CASCADE,
+            company_id INTEGER NOT NULL REFERENCES Companies(id) ON DELETE CASCADE,
+            unique(tv_id, company_id));
+        CREATE TABLE IF NOT EXISTS TvNetworkLinks (
+            tv_id INTEGER NOT NULL REFERENCES Tvs(id) ON DELETE CASCADE,
+            network_id INTEGER NOT NULL REFERENCES Networks(id) ON DELETE CASCADE,
+            unique(tv_id, network_id));
+        CREATE TABLE IF NOT EXISTS TvCountryLinks (
+            tv_id INTEGER NOT NULL REFERENCES Tvs(id) ON DELETE CASCADE,
+            country_code TEXT NOT NULL REFERENCES Countries(code) ON DELETE CASCADE,
+            unique(tv_id, country_code));
+        CREATE TABLE IF NOT EXISTS TvLanguageLinks (
+            tv_id INTEGER NOT NULL REFERENCES Tvs(id) ON DELETE CASCADE,
+            language_code TEXT NOT NULL REFERENCES Languages(code) ON DELETE CASCADE,
+            unique(tv_id, language_code));
+        CREATE INDEX IF NOT EXISTS MovieCompanyLinksCompanyIndex ON MovieCompanyLinks(company_id);
+        CREATE INDEX IF NOT EXISTS MovieCountryLinksCountryIndex ON MovieCountryLinks(country_code);
+        CREATE INDEX IF NOT EXISTS MovieLanguageLinksLanguageIndex ON MovieLanguageLinks(language_code);
+        CREATE INDEX IF NOT EXISTS TvCompanyLinksCompanyIndex ON TvCompanyLinks(company_id);
+        CREATE INDEX IF NOT EXISTS TvNetworkLinksNetworkIndex ON TvNetworkLinks(network_id);
+        CREATE INDEX IF NOT EXISTS TvCountryLinksCountryIndex ON TvCountryLinks(country_code);
+        CREATE INDEX IF NOT EXISTS TvLanguageLinksLanguageIndex ON TvLanguageLinks(language_code);"
+    )?;
+    Ok(())
+}
+
+fn movie_details(conn: &Transaction) -> Result<(), rusqlite::Error>{
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS TmdbCollections (
+            id INTEGER PRIMARY KEY NOT NULL,
+            name TEXT,
+            poster_path TEXT,
+            backdrop_path TEXT);
+        ALTER TABLE Movies ADD COLUMN runtime INTEGER;
+        ALTER TABLE Movies ADD COLUMN budget INTEGER;
+        ALTER TABLE Movies ADD COLUMN revenue INTEGER;
+        ALTER TABLE Movies ADD COLUMN homepage TEXT;
+        ALTER TABLE Movies ADD COLUMN belongs_to_collection INTEGER REFERENCES TmdbCollections(id) ON DELETE SET NULL;
+        CREATE INDEX IF NOT EXISTS MoviesRuntimeIndex ON Movies(runtime);
+        CREATE INDEX IF NOT EXISTS MoviesTmdbCollectionIndex ON Movies(belongs_to_collection);"
+    )?;
+    Ok(())
+}
+
+fn franchises(conn: &Transaction) -> Result<(), rusqlite::Error>{
+    conn.execute_batch(
+        "ALTER TABLE TmdbCollections ADD COLUMN overview TEXT;
+        ALTER TABLE TmdbCollections ADD COLUMN updated TEXT;
+        CREATE TABLE IF NOT EXISTS TmdbCollectionParts (
+            collection_id INTEGER NOT NULL REFERENCES TmdbCollections(id) ON DELETE CASCADE,
+            movie_id INTEGER NOT NULL,
+            title TEXT,
+            release_date TEXT,
+            poster_path TEXT,
+            unique(collection_id, movie_id));
+        ALTER TABLE Collections ADD COLUMN tmdb_id INTEGER REFERENCES TmdbCollections(id) ON DELETE CASCADE;
+        CREATE UNIQUE INDEX IF NOT EXISTS CollectionsTmdbIndex ON Collections(tmdb_id);"
+    )?;
+    Ok(())
+}
+
+fn guest_stars(conn: &Transaction) -> Result<(), rusqlite::Error>{
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS TvCreators (
+            tv_id INTEGER NOT NULL REFERENCES Tvs(id) ON DELETE CASCADE,
+            person_id INTEGER NOT NULL,
+            unique(tv_id, person_id));
+        INSERT OR IGNORE INTO TvCreators (tv_id, person_id) SELECT tv_id, person_id FROM TvCrews WHERE job = 'Creator';
+        DELETE FROM TvCrews WHERE job = 'Creator';
+        CREATE INDEX IF NOT EXISTS TvCreatorsPersonIndex ON TvCreators(person_id);
+        ALTER TABLE EpisodeCasts ADD COLUMN guest INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE Episodes ADD COLUMN still_path TEXT;"
+    )?;
+    Ok(())
+}
+
+fn person_details(conn: &Transaction) -> Result<(), rusqlite::Error>{
+    conn.execute_batch(
+        "ALTER TABLE Persons ADD COLUMN imdb_id TEXT;
+        ALTER TABLE Persons ADD COLUMN homepage TEXT;
+        ALTER TABLE Persons ADD COLUMN adult INTEGER NOT NULL DEFAULT 0;
+        CREATE TABLE IF NOT EXISTS PersonAliases (
+            person_id INTEGER NOT NULL REFERENCES Persons(id) ON DELETE CASCADE,
+            name TEXT NOT NULL,
+            unique(person_id, name));
+        CREATE INDEX IF NOT EXISTS PersonsDepartmentIndex ON Persons(known_for_department);
+        CREATE INDEX IF NOT EXISTS PersonsPopularityIndex ON Persons(popularity);"
+    )?;
+    Ok(())
+}
+
+/// Credits cascade on the deletion of their person.
+/// The check is deferred to the commit: a media writes its credits before the persons fetched with it.
+/// Credits of persons that were never fetched are dropped.
+fn person_foreign_keys(conn: &Transaction) -> Result<(), rusqlite::Error>{
+    drop_views(conn)?;
+
+    rebuild(conn, "MovieCasts",
+        "movie_id INTEGER NOT NULL REFERENCES Movies(id) ON DELETE CASCADE,
+        person_id INTEGER NOT NULL REFERENCES Persons(id) ON DELETE CASCADE DEFERRABLE INITIALLY DEFERRED,
+        character TEXT,
+        ord INTEGER,
+        unique(movie_id, person_id, character)",
+        "movie_id, person_id, character, ord", &[("movie_id", "Movies"), ("person_id", "Persons")])?;
+
+    rebuild(conn, "MovieCrews",
+        "movie_id INTEGER NOT NULL REFERENCES Movies(id) ON DELETE CASCADE,
+        person_id INTEGER NOT NULL REFERENCES Persons(id) ON DELETE CASCADE DEFERRABLE INITIALLY DEFERRED,
+        job TEXT,
+        unique(movie_id, person_id, job)",
+        "movie_id, person_id, job", &[("movie_id", "Movies"), ("person_id", "Persons")])?;
+
+    rebuild(conn, "TvCasts",
+        "tv_id INTEGER NOT NULL REFERENCES Tvs(id) ON DELETE CASCADE,
+        person_id INTEGER NOT NULL REFERENCES Persons(id) ON DELETE CASCADE DEFERRABLE INITIALLY DEFERRED,
+        character TEXT,
+        ord INTEGER,
+        unique(tv_id, person_id, character)",
+        "tv_id, person_id, character, ord", &[("tv_id", "Tvs"), ("person_id", "Persons")])?;
+
+    rebuild(conn, "TvCrews",
+        "tv_id INTEGER NOT NULL REFERENCES Tvs(id) ON DELETE CASCADE,
+        person_id INTEGER NOT NULL REFERENCES Persons(id) ON DELETE CASCADE DEFERRABLE INITIALLY DEFERRED,
+        job TEXT,
+        unique(tv_id, person_id, job)",
+        "tv_id, person_id, job", &[("tv_id", "Tvs"), ("person_id", "Persons")])?;
+
+    rebuild(conn, "TvCreators",
+        "tv_id INTEGER NOT NULL REFERENCES Tvs(id) ON DELETE CASCADE,
+        person_id INTEGER NOT NULL REFERENCES Persons(id) ON DELETE CASCADE DEFERRABLE INITIALLY DEFERRED,
+        unique(tv_id, person_id)",
+        "tv_id, person_id", &[("tv_id", "Tvs"), ("person_id", "Persons")])?;
+
+    rebuild(conn, "EpisodeCasts",
+        "episode_id INTEGER NOT NULL REFERENCES Episodes(id) ON DELETE CASCADE,
+        person_id INTEGER NOT NULL REFERENCES Persons(id) ON DELETE CASCADE DEFERRABLE INITIALLY DEFERRED,
+        character TEXT,
+        ord INTEGER,
+        guest INTEGER NOT NULL DEFAULT 0,
+        unique(episode_id, person_id, character)",
+        "episode_id, person_id, character, ord, guest", &[("episode_id", "Episodes"), ("person_id", "Persons")])?;
+
+    rebuild(conn, "EpisodeCrews",
+        "episode_id INTEGER NOT NULL REFERENCES Episodes(id) ON DELETE CASCADE,
+        person_id INTEGER NOT NULL REFERENCES Persons(id) ON DELETE CASCADE DEFERRABLE INITIALLY DEFERRED,
+        job TEXT,
+        unique(episode_id, person_id, job)",
+        "episode_id, person_id, job", &[("episode_id", "Episodes"), ("person_id", "Persons")])?;
+
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS MovieCastsPersonIndex ON MovieCasts(person_id);
+        CREATE INDEX IF NOT EXISTS MovieCrewsPersonIndex ON MovieCrews(person_id);
+        CREATE INDEX IF NOT EXISTS TvCastsPersonIndex ON TvCasts(person_id);
+        CREATE INDEX IF NOT EXISTS TvCrewsPersonIndex ON TvCrews(person_id);
+        CREATE INDEX IF NOT EXISTS TvCreatorsPersonIndex ON TvCreators(person_id);
+        CREATE INDEX IF NOT EXISTS EpisodeCastsPersonIndex ON EpisodeCasts(person_id);
+        CREATE INDEX IF NOT EXISTS EpisodeCrewsPersonIndex ON EpisodeCrews(person_id);"
+    )?;
+    Ok(())
+}
+
+/// views only depend on the latest schema, they are rebuilt after the last migration step
+fn create_views(conn: &Transaction) -> Result<(), rusqlite::Error>{
+    conn.execute("DROP VIEW IF EXISTS VideosView",[])?;
+    conn.execute(
+        "CREATE VIEW VideosView
+            AS 
+            SELECT
+                Videos.id as id,
+                path,
+                media_type,
+                media_id,
+                duration,
+                bit_rate,
+                codec,
+                width,
+                height,
+                Movies.id as m_id,
+                Tvs.id as t_id,
+                Movies.title as m_title,
+                Tvs.title as t_title,
+                episode_number,
+                season_number,
+                Movies.release_date as release_date,
+                size,
+                adding,
+                GROUP_CONCAT(Subtitles.language) as subtitles,
+                GROUP_CONCAT(Audios.language) as audios
+            FROM
+                Videos
+            LEFT OUTER JOIN Audios ON Videos.id = Audios.video_id
+            LEFT OUTER JOIN Subtitles ON Videos.id = Subtitles.video_id
+            LEFT OUTER JOIN Movies ON Videos.media_type = 0 AND Videos.media_id = Movies.id
+            LEFT OUTER JOIN Episodes ON Videos.media_type = 1 AND Videos.media_id = Episodes.id
+            LEFT OUTER JOIN Tvs ON Episodes.tv_id = Tvs.id
+            GROUP BY videos.id",
+            []
+    )?;
+
+    conn.execute("DROP VIEW IF EXISTS MoviesView",[])?;
+    conn.execute(
+        "CREATE VIEW MoviesView
+            AS 
+            SELECT
+                Movies.id as id,
+                original_title,
+                original_language,
+                title,
+                release_date,
+                overview,
+                popularity,
+                poster_path,
+                backdrop_path,
+                vote_average,
+                vote_count,
+                tagline,
+                status,
+                updated,
+                COALESCE(Movies.sort_title, Movies.title) as sort_title,
+                runtime,
+                budget,
+                revenue,
+                homepage,
+                belongs_to_collection,
+                GROUP_CONCAT(DISTINCT MovieGenres.name) as genres,
+                MAX(Videos.adding) as adding
+            FROM
+                Movies
+            INNER JOIN Videos ON Movies.id = Videos.media_id AND Videos.media_type = 0
+            LEFT OUTER JOIN MovieGenreLinks ON Movies.id = MovieGenreLinks.movie_id
+            LEFT OUTER JOIN MovieGenres ON MovieGenreLinks.genre_id = MovieGenres.id
+             
+
+            GROUP BY Movies.id",
+            []
+    )?;
+
+    conn.execute("DROP VIEW IF EXISTS MovieCastsView",[])?;
+    conn.execute(
+        "CREATE VIEW MovieCastsView
+            AS 
+            SELECT
+                Persons.id as id,
+                character,
+                movie_id,
+                ord,
+                name,
+                profile_path
+            FROM
+                MovieCasts
+            LEFT OUTER JOIN Persons ON MovieCasts.person_id = Persons.id
+
+            ",
+            []
+    )?;
+
+    conn.execute("DROP VIEW IF EXISTS MovieCrewsView",[])?;
+    conn.execute(
+        "CREATE VIEW MovieCrewsView
+            AS 
+            SELECT
+                Persons.id as id,
+                job,
+                movie_id,
+                name,
+                profile_path
+            FROM
+                MovieCrews
+            LEFT OUTER JOIN Persons ON MovieCrews.person_id = Persons.id
+
+            ",
+            []
+    )?;
+
+    conn.execute("DROP VIEW IF EXISTS TvsView",[])?;
+    conn.execute(
+        "CREATE VIEW IF NOT EXISTS TvsView
+            AS 
+            SELECT
+                Tvs.id as id,
+                Tvs.original_title as original_title,
+                original_language,
+                Tvs.title as title,
+                Tvs.release_date as release_date,
+                Tvs.overview as overview,
+                popularity,
+                poster_path,
+                backdrop_path,
+                status,
+                Tvs.vote_average as vote_average,
+                Tvs.vote_count as vote_count,
+                number_of_episodes,
+                number_of_seasons,
+                episode_run_time,
+                Tvs.updated as updated,
+                COALESCE(Tvs.sort_title, Tvs.title) as sort_title,
+                GROUP_CONCAT(DISTINCT TvGenres.name) as genres,
+                MAX(Videos.adding) as adding
+            FROM
+                Tvs
+            LEFT OUTER JOIN TvGenreLinks ON Tvs.id = TvGenreLinks.tv_id
+            LEFT OUTER JOIN TvGenres ON TvGenreLinks.genre_id = TvGenres.id
+            LEFT OUTER JOIN Episodes ON Tvs.id = Episodes.tv_id
+            INNER JOIN Videos ON Videos.media_id = Episodes.id AND Videos.media_type = 1
+
+            GROUP BY Tvs.id",
+            []
+    )?;
+    conn.execute("DROP VIEW IF EXISTS SeasonsView",[])?;
+    conn.execute(
+        "CREATE VIEW SeasonsView
+            AS 
+            SELECT
+                Seasons.id as id,
+                Seasons.tv_id as tv_id,
+                Seasons.season_number as season_number,
+                Seasons.episode_count as episode_count,
+                Seasons.title as title,
+                Seasons.overview as overview,
+                Seasons.poster_path as poster_path,
+                Seasons.release_date as release_date,
+                Seasons.updated as updated
+            FROM
+                Seasons
+            INNER JOIN Episodes ON Episodes.season_id = Seasons.id
+            INNER JOIN Videos ON Videos.media_id = Episodes.id AND Videos.media_type = 1
+
+            GROUP BY Seasons.id",
+            []
+    )?;
+
+    conn.execute("DROP VIEW IF EXISTS EpisodesView",[])?;
+    conn.execute(
+        "CREATE VIEW EpisodesView
+            AS 
+            SELECT
+                Episodes.id as id,
+                tv_id,
+                season_number,
+                episode_number,
+                release_date,
+                title,
+                overview,
+                vote_average,
+                vote_count,
+                updated,
+                still_path
+            FROM
+                Episodes
+            INNER JOIN Videos ON Videos.media_id = Episodes.id AND Videos.media_type = 1
+            
+            GROUP BY Videos.id",
+            []
+    )?;
+
+    conn.execute("DROP VIEW IF EXISTS TvCastsView",[])?;
+    conn.execute(
+        "CREATE VIEW IF NOT EXISTS TvCastsView
+            AS 
+            SELECT
+                Persons.id as id,
+                character,
+                tv_id,
+                ord,
+                name,
+                profile_path
+            FROM
+                TvCasts
+            LEFT OUTER JOIN Persons ON TvCasts.person_id = Persons.id
+
+            ",
+            []
+    )?;
+    
+    conn.execute("DROP VIEW IF EXISTS TvCrewsView",[])?;
+    conn.execute(
+        "CREATE VIEW IF NOT EXISTS TvCrewsView
+            AS 
+            SELECT
+                Persons.id as id,
+                tv_id,
+                job,
+                name,
+                profile_path
+            FROM
+                TvCrews
+            LEFT OUTER JOIN Persons ON TvCrews.person_id = Persons.id
+
+            ",
+            []
+    )?;
+
+    conn.execute("DROP VIEW IF EXISTS TvCreatorsView",[])?;
+    conn.execute(
+        "CREATE VIEW IF NOT EXISTS TvCreatorsView
+            AS 
+            SELECT
+                Persons.id as id,
+                tv_id,
+                name,
+                profile_path
+            FROM
+                TvCreators
+            LEFT OUTER JOIN Persons ON TvCreators.person_id = Persons.id
+
+            ",
+            []
+    )?;
+
+    conn.execute("DROP VIEW IF EXISTS EpisodeCastsView",[])?;
+    conn.execute(
+        "CREATE VIEW IF NOT EXISTS EpisodeCastsView
+            AS 
+            SELECT
+                Persons.id as id,
+                character,
+                episode_id,
+                ord,
+                guest,
+                name,
+                profile_path
+            FROM
+                EpisodeCasts
+            LEFT OUTER JOIN Persons ON EpisodeCasts.person_id = Persons.id
+
+            ",
+            []
+    )?;
+    
+    conn.execute("DROP VIEW IF EXISTS EpisodeCrewsView",[])?;
+    conn.execute(
+        "CREATE VIEW IF NOT EXISTS EpisodeCrewsView
+            AS 
+            SELECT
+                Persons.id as id,
+                episode_id,
+                job,
+                name,
+                profile_path
+            FROM
+                EpisodeCrews
+            LEFT OUTER JOIN Persons ON EpisodeCrews.person_id = Persons.id
+
+            ",
+            []
+    )?;
+
+    Ok(())
+}
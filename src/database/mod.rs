@@ -1,4 +1,4 @@
-use std::{fmt, collections::HashMap, str::FromStr, sync::{Mutex, Arc}};
+use std::{fmt, str::FromStr, sync::{Mutex, MutexGuard}};
 
 use rusqlite::{Connection, ToSql};
 
@@ -7,610 +7,72 @@ mod movie;
 mod tv;
 mod person;
 mod collection;
-
-lazy_static! {
-    pub static ref DATABASE: Arc<SqlLibrary> = Arc::new(SqlLibrary::new());
-}
-
+mod migration;
+mod pool;
+mod search;
+mod integrity;
+mod import;
+mod sync;
+mod overrides;
+mod local;
+mod album;
+mod production;
+
+use self::pool::{PooledConnection, ReaderPool};
+use crate::library::filter::{Condition, Expr, Field, Operator, Sort};
+
+/// A single writing connection and a pool of readers over the same WAL database,
+/// so long reads never block the writer nor each other.
 #[derive(Debug)]
 pub struct SqlLibrary{
-    conn: Mutex<Option<Connection>>,
+    writer: Mutex<Connection>,
+    readers: ReaderPool,
 }
 
 impl SqlLibrary{
-    pub fn new() ->  SqlLibrary{
-        SqlLibrary{
-            conn: Mutex::new(None),
+    pub fn open(path: &str) -> Result<SqlLibrary, Error>{
+        // readers open the path again, each would get its own empty database
+        if in_memory(path){
+            return Err(Error::new(ErrorKind::Path,
+                format!("{:?} is not a database file, readers can't share an in memory database", path),
+                "SqlLibrary::open"))
         }
+        let mut conn = pool::open_writer(path)?;
+        migration::migrate(&mut conn, path)?;
+        // off while migrating, dropping a rebuilt table would cascade
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        Ok(SqlLibrary{
+            writer: Mutex::new(conn),
+            readers: ReaderPool::new(path),
+        })
+    }
+
+    fn writer(&self) -> Result<MutexGuard<'_, Connection>, Error>{
+        Ok(self.writer.lock().unwrap())
+    }
+
+    fn reader(&self) -> Result<PooledConnection<'_>, Error>{
+        self.readers.get()
     }
 
-    pub fn connect(&self, path: &str){
-        let mut conn = self.conn.lock().unwrap();
-        *conn = Some(Connection::open(path).unwrap());
-        drop(conn);
-        self.init_db().unwrap();
-        
+    /// number of rows returned by a query built with `generate_sql`
+    fn count(&self, sql: &str, param: &[&dyn ToSql]) -> Result<u64, Error>{
+        let conn = self.reader()?;
+        Ok(conn.query_row(&format!("SELECT COUNT(*) FROM ({})", sql), param, |row| row.get(0))?)
     }
+}
 
-    //video part
-
-    fn init_db(&self) -> Result<(), rusqlite::Error>{
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS Videos (
-                id INTEGER PRIMARY KEY NOT NULL,
-                path TEXT NOT NULL UNIQUE,
-                media_type INTEGER,
-                media_id INTEGER,
-                duration INTEGER,
-                bit_rate INTEGER,
-                codec TEXT,
-                width INTEGER,
-                height INTEGER,
-                size INTEGER,
-                adding TEXT)",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS WatchTimes (
-                video_id INTEGER NOT NULL,
-                user_name INTEGER NOT NULL,
-                watch_time INTEGER,
-                last_watch TEXT,
-                unique(video_id, user_name))",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS Audios (
-                video_id INTEGER NOT NULL,
-                language TEXT,
-                unique(video_id, language))",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS Subtitles (
-                video_id INTEGER NOT NULL,
-                language TEXT,
-                unique(video_id, language))",
-            [],
-        )?;
-
-        conn.execute("DROP VIEW IF EXISTS VideosView",[])?;
-        conn.execute(
-            "CREATE VIEW VideosView
-                AS 
-                SELECT
-                    Videos.id as id,
-                    path,
-                    media_type,
-                    media_id,
-                    duration,
-                    bit_rate,
-                    codec,
-                    width,
-                    height,
-                    Movies.id as m_id,
-                    Tvs.id as t_id,
-                    Movies.title as m_title,
-                    Tvs.title as t_title,
-                    episode_number,
-                    season_number,
-                    Movies.release_date as release_date,
-                    size,
-                    adding,
-                    GROUP_CONCAT(Subtitles.language) as subtitles,
-                    GROUP_CONCAT(Audios.language) as audios
-                FROM
-                    Videos
-                LEFT OUTER JOIN Audios ON Videos.id = Audios.video_id
-                LEFT OUTER JOIN Subtitles ON Videos.id = Subtitles.video_id
-                LEFT OUTER JOIN Movies ON Videos.media_type = 0 AND Videos.media_id = Movies.id
-                LEFT OUTER JOIN Episodes ON Videos.media_type = 1 AND Videos.media_id = Episodes.id
-                LEFT OUTER JOIN Tvs ON Episodes.tv_id = Tvs.id
-                GROUP BY videos.id",
-                []
-        )?;
-
-        // Movie Part
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS Movies (
-                id INTEGER PRIMARY KEY NOT NULL,
-                original_title TEXT,
-                original_language TEXT,
-                title TEXT,
-                release_date TEXT,
-                overview TEXT,
-                popularity FLOAT,
-                poster_path TEXT,
-                backdrop_path TEXT,
-                vote_average FLOAT,
-                vote_count INTEGER,
-                tagline TEXT,
-                status TEXT,
-                adult BOOL,
-                updated TEXT)",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS MovieGenres (
-                id INTEGER PRIMARY KEY NOT NULL,
-                name TEXT)",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS MovieGenreLinks (
-                movie_id INTEGER NOT NULL,
-                genre_id INTEGER NOT NULL,
-                unique(movie_id,genre_id))",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS MovieCollectionLinks (
-                movie_id INTEGER NOT NULL,
-                collection_id INTEGER NOT NULL,
-                unique(movie_id, collection_id))",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS MovieKeywordLinks (
-                movie_id INTEGER NOT NULL,
-                keyword_id INTEGER NOT NULL,
-                unique(movie_id,keyword_id))",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS MovieTrailers (
-                movie_id INTEGER NOT NULL,
-                name TEXT,
-                youtube_id TEXT,
-                unique(movie_id,youtube_id))",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS MovieCasts (
-                movie_id INTEGER NOT NULL,
-                person_id TEXT,
-                character TEXT,
-                ord INTEGER,
-                unique(movie_id,person_id,character))",
-            [],
-        )?;
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS MovieCrews (
-                movie_id INTEGER NOT NULL,
-                person_id TEXT,
-                job TEXT,
-                unique(movie_id,person_id,job))",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS MovieUserWatched (
-                movie_id INTEGER NOT NULL,
-                user_name TEXT,
-                watched INTEGER,
-                vote_user FLOAT,
-                unique(movie_id,user_name))",
-            [],
-        )?;
-
-        conn.execute("DROP VIEW IF EXISTS MoviesView",[])?;
-        conn.execute(
-            "CREATE VIEW MoviesView
-                AS 
-                SELECT
-                    Movies.id as id,
-                    original_title,
-                    original_language,
-                    title,
-                    release_date,
-                    overview,
-                    popularity,
-                    poster_path,
-                    backdrop_path,
-                    vote_average,
-                    vote_count,
-                    tagline,
-                    status,
-                    updated,
-                    GROUP_CONCAT(DISTINCT MovieGenres.name) as genres,
-                    MAX(Videos.adding) as adding
-                FROM
-                    Movies
-                INNER JOIN Videos ON Movies.id = Videos.media_id AND Videos.media_type = 0
-                LEFT OUTER JOIN MovieGenreLinks ON Movies.id = MovieGenreLinks.movie_id
-                LEFT OUTER JOIN MovieGenres ON MovieGenreLinks.genre_id = MovieGenres.id
-                 
-
-                GROUP BY Movies.id",
-                []
-        )?;
-
-        conn.execute("DROP VIEW IF EXISTS MovieCastsView",[])?;
-        conn.execute(
-            "CREATE VIEW MovieCastsView
-                AS 
-                SELECT
-                    Persons.id as id,
-                    character,
-                    movie_id,
-                    ord,
-                    name,
-                    profile_path
-                FROM
-                    MovieCasts
-                LEFT OUTER JOIN Persons ON MovieCasts.person_id = Persons.id
-
-                ",
-                []
-        )?;
-
-        conn.execute("DROP VIEW IF EXISTS MovieCrewsView",[])?;
-        conn.execute(
-            "CREATE VIEW MovieCrewsView
-                AS 
-                SELECT
-                    Persons.id as id,
-                    job,
-                    movie_id,
-                    name,
-                    profile_path
-                FROM
-                    MovieCrews
-                LEFT OUTER JOIN Persons ON MovieCrews.person_id = Persons.id
-
-                ",
-                []
-        )?;
-
-        // Tv Part
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS Tvs (
-                id INTEGER PRIMARY KEY NOT NULL,
-                original_title TEXT,
-                original_language TEXT,
-                title TEXT,
-                release_date TEXT,
-                overview TEXT,
-                popularity FLOAT,
-                poster_path TEXT,
-                backdrop_path TEXT,
-                status TEXT,
-                vote_average FLOAT,
-                vote_count INTEGER,
-                in_production BOOL, 
-                number_of_episodes INTEGER,
-                number_of_seasons INTEGER,
-                episode_run_time INTEGER,
-                updated TEXT)",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS TvGenres (
-                id INTEGER PRIMARY KEY NOT NULL,
-                name TEXT)",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS TvGenreLinks (
-                tv_id INTEGER NOT NULL,
-                genre_id INTEGER NOT NULL,
-                unique(tv_id,genre_id))",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS TvCollectionLinks (
-                tv_id INTEGER NOT NULL,
-                collection_id INTEGER NOT NULL,
-                unique(tv_id, collection_id))",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS TvKeywordLinks (
-                tv_id INTEGER NOT NULL,
-                keyword_id INTEGER NOT NULL,
-                unique(tv_id,keyword_id))",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS TvTrailers (
-                tv_id INTEGER NOT NULL,
-                name TEXT,
-                youtube_id TEXT,
-                unique(tv_id,youtube_id))",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS TvCasts (
-                tv_id INTEGER NOT NULL,
-                person_id TEXT,
-                character TEXT,
-                ord INTEGER,
-                unique(tv_id, person_id, character))",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS TvCrews (
-                tv_id INTEGER NOT NULL,
-                person_id TEXT,
-                job TEXT,
-                unique(tv_id, person_id, job))",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS Seasons (
-                id INTEGER PRIMARY KEY NOT NULL,
-                tv_id INTEGER NOT NULL,
-                season_number INTEGER NOT NULL,
-                episode_count INTEGER,
-                title TEXT,
-                overview TEXT,
-                poster_path TEXT,
-                release_date TEXT,
-                updated TEXT)",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS Episodes (
-                id INTEGER PRIMARY KEY NOT NULL,
-                season_id INTEGER NOT NULL,
-                tv_id INTEGER NOT NULL,
-                season_number INTEGER NOT NULL,
-                episode_number INTEGER NOT NULL,
-                release_date TEXT,
-                title TEXT,
-                overview TEXT,
-                vote_average FLOAT,
-                vote_count INTEGER,
-                updated TEXT)",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS EpisodeCasts (
-                episode_id INTEGER NOT NULL,
-                person_id INTEGER NOT NULL,
-                character TEXT,
-                ord INTEGER,
-                unique(episode_id,person_id,character))",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS EpisodeCrews (
-                episode_id INTEGER NOT NULL,
-                person_id INTEGER NOT NULL,
-                job TEXT,
-                unique(episode_id,person_id,job))",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS EpisodesUserWatched (
-                episode_id INTEGER NOT NULL,
-                user_name TEXT,
-                watched INTEGER,
-                vote_user FLOAT,
-                unique(episode_id,user_name))",
-            [],
-        )?;
-
-        conn.execute("DROP VIEW IF EXISTS TvsView",[])?;
-        conn.execute(
-            "CREATE VIEW IF NOT EXISTS TvsView
-                AS 
-                SELECT
-                    Tvs.id as id,
-                    Tvs.original_title as original_title,
-                    original_language,
-                    Tvs.title as title,
-                    Tvs.release_date as release_date,
-                    Tvs.overview as overview,
-                    popularity,
-                    poster_path,
-                    backdrop_path,
-                    status,
-                    Tvs.vote_average as vote_average,
-                    Tvs.vote_count as vote_count,
-                    number_of_episodes,
-                    number_of_seasons,
-                    episode_run_time,
-                    Tvs.updated as updated,
-                    GROUP_CONCAT(DISTINCT TvGenres.name) as genres,
-                    MAX(Videos.adding) as adding
-                FROM
-                    Tvs
-                LEFT OUTER JOIN TvGenreLinks ON Tvs.id = TvGenreLinks.tv_id
-                LEFT OUTER JOIN TvGenres ON TvGenreLinks.genre_id = TvGenres.id
-                LEFT OUTER JOIN Episodes ON Tvs.id = Episodes.tv_id
-                INNER JOIN Videos ON Videos.media_id = Episodes.id AND Videos.media_type = 1
-
-                GROUP BY Tvs.id",
-                []
-        )?;
-        conn.execute("DROP VIEW IF EXISTS SeasonsView",[])?;
-        conn.execute(
-            "CREATE VIEW SeasonsView
-                AS 
-                SELECT
-                    Seasons.id as id,
-                    Seasons.tv_id as tv_id,
-                    Seasons.season_number as season_number,
-                    Seasons.episode_count as episode_count,
-                    Seasons.title as title,
-                    Seasons.overview as overview,
-                    Seasons.poster_path as poster_path,
-                    Seasons.release_date as release_date,
-                    Seasons.updated as updated
-                FROM
-                    Seasons
-                INNER JOIN Episodes ON Episodes.season_id = Seasons.id
-                INNER JOIN Videos ON Videos.media_id = Episodes.id AND Videos.media_type = 1
-
-                GROUP BY Seasons.id",
-                []
-        )?;
-
-        conn.execute("DROP VIEW IF EXISTS EpisodesView",[])?;
-        conn.execute(
-            "CREATE VIEW EpisodesView
-                AS 
-                SELECT
-                    Episodes.id as id,
-                    tv_id,
-                    season_number,
-                    episode_number,
-                    release_date,
-                    title,
-                    overview,
-                    vote_average,
-                    vote_count,
-                    updated
-                FROM
-                    Episodes
-                INNER JOIN Videos ON Videos.media_id = Episodes.id AND Videos.media_type = 1
-                
-                GROUP BY Videos.id",
-                []
-        )?;
-
-        conn.execute("DROP VIEW IF EXISTS TvCastsView",[])?;
-        conn.execute(
-            "CREATE VIEW IF NOT EXISTS TvCastsView
-                AS 
-                SELECT
-                    Persons.id as id,
-                    character,
-                    tv_id,
-                    ord,
-                    name,
-                    profile_path
-                FROM
-                    TvCasts
-                LEFT OUTER JOIN Persons ON TvCasts.person_id = Persons.id
-
-                ",
-                []
-        )?;
-        
-        conn.execute("DROP VIEW IF EXISTS TvCrewsView",[])?;
-        conn.execute(
-            "CREATE VIEW IF NOT EXISTS TvCrewsView
-                AS 
-                SELECT
-                    Persons.id as id,
-                    tv_id,
-                    job,
-                    name,
-                    profile_path
-                FROM
-                    TvCrews
-                LEFT OUTER JOIN Persons ON TvCrews.person_id = Persons.id
-
-                ",
-                []
-        )?;
-
-        conn.execute("DROP VIEW IF EXISTS EpisodeCastsView",[])?;
-        conn.execute(
-            "CREATE VIEW IF NOT EXISTS EpisodeCastsView
-                AS 
-                SELECT
-                    Persons.id as id,
-                    character,
-                    episode_id,
-                    ord,
-                    name,
-                    profile_path
-                FROM
-                    EpisodeCasts
-                LEFT OUTER JOIN Persons ON EpisodeCasts.person_id = Persons.id
-
-                ",
-                []
-        )?;
-        
-        conn.execute("DROP VIEW IF EXISTS EpisodeCrewsView",[])?;
-        conn.execute(
-            "CREATE VIEW IF NOT EXISTS EpisodeCrewsView
-                AS 
-                SELECT
-                    Persons.id as id,
-                    episode_id,
-                    job,
-                    name,
-                    profile_path
-                FROM
-                    EpisodeCrews
-                LEFT OUTER JOIN Persons ON EpisodeCrews.person_id = Persons.id
-
-                ",
-                []
-        )?;
-
-
-        //Person Part
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS Persons (
-                id INTEGER PRIMARY KEY NOT NULL,
-                birthday TEXT,
-                known_for_department TEXT,
-                deathday TEXT,
-                name TEXT,
-                gender INTEGER,
-                biography TEXT,
-                popularity FLOAT,
-                place_of_birth TEXT,
-                profile_path TEXT)",
-            []
-        )?;
-
-        //keywords
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS Keywords (
-                id INTEGER PRIMARY KEY NOT NULL,
-                name TEXT)",
-            [],
-        )?;
-
-        //user
-
-        //Person Part
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS Collections (
-                id INTEGER PRIMARY KEY NOT NULL,
-                name TEXT,
-                description TEXT,
-                creator TEXT,
-                creation_date TEXT,
-                poster_path TEXT,
-                unique(name, creator))",
-            []
-        )?;
-        
-
-        Ok(())
+/// `path` names a temporary or in memory database instead of a file
+fn in_memory(path: &str) -> bool{
+    if path.is_empty() || path == ":memory:"{
+        return true
+    }
+    match path.strip_prefix("file:"){
+        Some(uri) => {
+            let (name, query) = uri.split_once('?').unwrap_or((uri, ""));
+            name.is_empty() || name == ":memory:" || query.split('&').any(|p| p == "mode=memory")
+        },
+        None => false,
     }
 }
 
@@ -634,32 +96,16 @@ pub fn parse_watched(row: Option<u64>) -> u64{
     return 0
 }
 
-pub fn generate_sql<'a>(head: &str, parameters: &'a HashMap<String, Option<(String, String)>>, user: Option<&'a String>,
-                group_by: Option<&'a str>, order_by: &'a Option<String>, limit: Option<u64>, offset: Option<u64>) -> (String, Vec<&'a dyn ToSql>){
+pub fn generate_sql<'a, F: Field>(head: &str, parameters: &'a [Expr<F>], user: Option<&'a String>,
+                group_by: Option<&'a str>, order_by: &[Sort<F>], limit: Option<u64>, offset: Option<u64>) -> (String, Vec<&'a dyn ToSql>){
     let mut param :Vec<&dyn ToSql> = Vec::new();
     let mut sql = head.to_string();
     if let Some(user) = user{
         param.push(user);
     }
-    if parameters.len() > 0{
+    if !parameters.is_empty(){
         sql += " WHERE ";
-        let mut counter = 1;
-        for (name, value) in parameters{
-            if counter > 1{
-                sql += "AND "
-            }
-            
-            if let Some((operator, value)) = value{
-                param.push(value);
-                sql += &format!("{} {} ?{} ", name, operator, &param.len());
-                
-            }
-            else{
-                sql += &format!("{} IS NULL ", name);
-            }
-            
-            counter += 1;
-        }
+        push_group(&mut sql, &mut param, parameters, "AND", "1");
         sql += "\n"
     }
 
@@ -667,8 +113,11 @@ pub fn generate_sql<'a>(head: &str, parameters: &'a HashMap<String, Option<(Stri
         sql += &format!("GROUP BY {} \n", group_by);
     }
 
-    if let Some(order_by) = order_by{
-        sql += &format!("ORDER BY {} \n", order_by);
+    if !order_by.is_empty(){
+        let columns: Vec<String> = order_by.iter()
+            .map(|sort| format!("{} {}", sort.field.sort_sql(), if sort.descending {"DESC"} else {"ASC"}))
+            .collect();
+        sql += &format!("ORDER BY {} \n", columns.join(", "));
     }
 
     if let Some(limit) = limit{
@@ -688,9 +137,69 @@ pub fn generate_sql<'a>(head: &str, parameters: &'a HashMap<String, Option<(Stri
     (sql, param)
 }
 
+/// write the expression with its values bound as numbered parameters
+fn push_expr<'a, F: Field>(sql: &mut String, param: &mut Vec<&'a dyn ToSql>, expr: &'a Expr<F>){
+    match expr{
+        Expr::Condition(condition) => push_condition(sql, param, condition),
+        Expr::And(exprs) => push_group(sql, param, exprs, "AND", "1"),
+        Expr::Or(exprs) => push_group(sql, param, exprs, "OR", "0"),
+        Expr::Not(expr) => {
+            *sql += "NOT ";
+            push_expr(sql, param, expr);
+        }
+        Expr::Sql(text, values) => {
+            let mut parts = text.split('?');
+            *sql += "(";
+            *sql += parts.next().unwrap_or_default();
+            for (part, value) in parts.zip(values){
+                param.push(value);
+                *sql += &format!("?{}{}", param.len(), part);
+            }
+            *sql += ")";
+        }
+    }
+}
+
+/// an empty group is `empty`, the neutral value of its operator
+fn push_group<'a, F: Field>(sql: &mut String, param: &mut Vec<&'a dyn ToSql>, exprs: &'a [Expr<F>], operator: &str, empty: &str){
+    if exprs.is_empty(){
+        *sql += empty;
+        return
+    }
+    *sql += "(";
+    for (index, expr) in exprs.iter().enumerate(){
+        if index > 0{
+            *sql += &format!(" {} ", operator);
+        }
+        push_expr(sql, param, expr);
+    }
+    *sql += ")";
+}
+
+fn push_condition<'a, F: Field>(sql: &mut String, param: &mut Vec<&'a dyn ToSql>, condition: &'a Condition<F>){
+    let mut placeholders = Vec::new();
+    for value in &condition.values{
+        param.push(value);
+        placeholders.push(format!("?{}", param.len()));
+    }
+    let field = condition.field.sql();
+    let operator = condition.operator.sql();
+    *sql += &match condition.operator{
+        Operator::IsNull | Operator::IsNotNull => format!("{} {}", field, operator),
+        Operator::In | Operator::NotIn => format!("{} {} ({})", field, operator, placeholders.join(", ")),
+        Operator::Between => format!("{} {} {} AND {}", field, operator, placeholders[0], placeholders[1]),
+        Operator::Prefix => format!("{} {} {} ESCAPE '\\'", field, operator, placeholders[0]),
+        _ => format!("{} {} {}", field, operator, placeholders[0]),
+    };
+}
+
 #[derive(Debug)]
 pub enum ErrorKind{
     Unknwon,
+    Version,
+    Migration,
+    Backup,
+    Path,
 }
 
 #[derive(Debug)]
@@ -725,4 +234,108 @@ impl std::convert::From<rusqlite::Error> for Error {
     fn from(err: rusqlite::Error) -> Error {
         Error::from_reqwest(err, "Undefined")
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests{
+    use rusqlite::types::{ToSqlOutput, ValueRef};
+
+    use super::*;
+    use crate::library::movie::MovieField;
+
+    fn condition(field: MovieField, operator: Operator, values: &[&str]) -> Expr<MovieField>{
+        Expr::Condition(Condition::new(field, operator, values.iter().map(|v| v.to_string()).collect()).unwrap())
+    }
+
+    /// the sql after `WHERE` and the bound values, in placeholder order
+    fn render(parameters: &[Expr<MovieField>], user: Option<&String>) -> (String, Vec<String>){
+        let (sql, param) = generate_sql("", parameters, user, None, &[], None, None);
+        let values = param.iter().map(|value| match value.to_sql().unwrap(){
+            ToSqlOutput::Borrowed(ValueRef::Text(text)) => String::from_utf8(text.to_vec()).unwrap(),
+            other => panic!("unexpected value {:?}", other),
+        }).collect();
+        (sql.trim_start_matches(" WHERE ").trim_end().to_string(), values)
+    }
+
+    #[test]
+    fn placeholders_follow_nested_groups(){
+        let parameters = vec![
+            condition(MovieField::Title, Operator::Eq, &["matrix"]),
+            Expr::Or(vec![
+                condition(MovieField::Id, Operator::In, &["1", "2"]),
+                Expr::Not(Box::new(Expr::And(vec![
+                    condition(MovieField::Popularity, Operator::Between, &["3", "4"]),
+                    Expr::Sql("Movies.id IN (SELECT ? UNION SELECT ?)", vec!["5".to_string(), "6".to_string()]),
+                ]))),
+            ]),
+            condition(MovieField::Status, Operator::Ne, &["7"]),
+        ];
+        let user = "user".to_string();
+        let (sql, values) = render(&parameters, Some(&user));
+        assert_eq!(sql, "(Movies.title = ?2 AND (Movies.id IN (?3, ?4) OR NOT (Movies.popularity BETWEEN ?5 AND ?6 \
+                         AND (Movies.id IN (SELECT ?7 UNION SELECT ?8)))) AND Movies.status != ?9)");
+        assert_eq!(values, ["user", "matrix", "1", "2", "3", "4", "5", "6", "7"]);
+    }
+
+    #[test]
+    fn in_and_not_in_bind_every_value(){
+        let (sql, values) = render(&[
+            condition(MovieField::Genre, Operator::In, &["1", "2", "3"]),
+            condition(MovieField::Country, Operator::NotIn, &["FR", "US"]),
+        ], None);
+        assert_eq!(sql, "(MovieGenreLinks.genre_id IN (?1, ?2, ?3) AND MovieCountryLinks.country_code NOT IN (?4, ?5))");
+        assert_eq!(values, ["1", "2", "3", "FR", "US"]);
+    }
+
+    #[test]
+    fn equality_without_value_is_a_null_check(){
+        let (sql, values) = render(&[
+            condition(MovieField::Watched, Operator::Eq, &[]),
+            condition(MovieField::Collection, Operator::Ne, &[]),
+        ], None);
+        assert_eq!(sql, "(MovieUserWatched.watched IS NULL AND MovieCollectionLinks.collection_id IS NOT NULL)");
+        assert!(values.is_empty());
+    }
+
+    #[test]
+    fn prefix_escapes_like_wildcards(){
+        let (sql, values) = render(&[condition(MovieField::Title, Operator::Prefix, &["50%_off\\"])], None);
+        assert_eq!(sql, "(Movies.title LIKE ?1 ESCAPE '\\')");
+        assert_eq!(values, ["50\\%\\_off\\\\%"]);
+    }
+
+    #[test]
+    fn empty_groups_are_neutral(){
+        let (sql, _) = render(&[Expr::Or(vec![]), Expr::Not(Box::new(Expr::And(vec![])))], None);
+        assert_eq!(sql, "(0 AND NOT 1)");
+    }
+
+    #[test]
+    fn sorts_group_and_pages(){
+        let sorts = [Sort{ field: MovieField::Adding, descending: true }, Sort{ field: MovieField::Title, descending: false }];
+        let (sql, _) = generate_sql("SELECT id FROM Movies\n", &[], None, Some("Movies.id"), &sorts, Some(10), Some(20));
+        assert_eq!(sql, "SELECT id FROM Movies\nGROUP BY Movies.id \nORDER BY MAX(Videos.adding) DESC, Movies.title ASC \nLIMIT 10 OFFSET 20 \n");
+    }
+
+    #[test]
+    fn open_rejects_in_memory_databases(){
+        for path in ["", ":memory:", "file::memory:", "file:", "file:library?mode=memory&cache=shared"]{
+            let err = SqlLibrary::open(path).unwrap_err();
+            assert!(matches!(err.kind, ErrorKind::Path), "{:?} opened", path);
+        }
+    }
+
+    #[test]
+    fn readers_see_the_writer_commits(){
+        let path = std::env::temp_dir().join(format!("medialibrary-pool-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        let db = SqlLibrary::open(path).unwrap();
+        db.writer().unwrap().execute_batch("CREATE TABLE Pool (id INTEGER); INSERT INTO Pool VALUES (1);").unwrap();
+        let count: u64 = db.reader().unwrap().query_row("SELECT COUNT(*) FROM Pool", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 1);
+        drop(db);
+        for suffix in ["", "-wal", "-shm"]{
+            let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+        }
+    }
+}
@@ -1,5 +1,4 @@
-use std::collections::HashMap;
-
+use rusqlite::{params, ToSql};
 use crate::library::cast::Cast;
 use crate::library::cast::Crew;
 use crate::library::genre::Genre;
@@ -7,24 +6,52 @@ use crate::library::keyword::Keyword;
 use crate::library::trailer::Trailer;
 use crate::rustmdb;
 use super::Error;
+use crate::library::Context;
+use crate::library::filter::{Expr, Sort};
+use crate::library::movie::MovieField;
 use super::SqlLibrary;
+use super::search::{self, Document};
+use super::person::insert_person;
+use super::production::{insert_company, insert_code, link};
+use super::collection;
+use super::overrides::apply_overrides;
+use crate::library::local::LOCAL_ID_BASE;
+use crate::library::overrides::Editable;
 use super::generate_sql;
 use super::parse_concat;
 use super::parse_watched;
-use crate::library::movie::{MovieResult, Movie};
+use crate::library::movie::{BelongsToCollection, MovieResult, Movie};
 
 
 impl SqlLibrary{
-    pub fn create_movie(&self, movie: &rustmdb::model::Movie) -> Result<(Vec<u64>, Vec<String>), Error>{
-        let mut m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_mut().unwrap();
+    pub fn create_movie(&self, movie: &rustmdb::model::Movie, persons: &[rustmdb::model::Person]) -> Result<Vec<String>, Error>{
+        let mut conn = self.writer()?;
         let tx = conn.transaction()?;
 
-        let mut person_ids = Vec::new();
         let mut rsc_path = Vec::new();
 
+        if let Some(collection) = &movie.belongs_to_collection{
+            tx.execute(
+                "INSERT INTO TmdbCollections (
+                    id,
+                    name,
+                    poster_path,
+                    backdrop_path) values (?1, ?2, ?3, ?4)
+                ON CONFLICT(id) DO UPDATE SET
+                    name = excluded.name,
+                    poster_path = excluded.poster_path,
+                    backdrop_path = excluded.backdrop_path",
+                params![
+                    collection.id,
+                    collection.name,
+                    collection.poster_path.as_deref().unwrap_or_default(),
+                    collection.backdrop_path.as_deref().unwrap_or_default()],
+            )?;
+            rsc_path.extend(collection.poster_path.clone());
+        }
+
         tx.execute(
-            "INSERT OR REPLACE INTO Movies (
+            "INSERT INTO Movies (
                 id,
                 original_title,
                 original_language,
@@ -39,25 +66,62 @@ impl SqlLibrary{
                 tagline,
                 status,
                 adult,
-                updated) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, datetime('now'))",
-
-            &[
-                &movie.id.to_string(),
-                &movie.original_title,
-                &movie.original_language,
-                &movie.title,
-                &movie.release_date,
-                &movie.overview.as_ref().unwrap_or(&"".to_string()),
-                &movie.popularity.to_string(),
-                &movie.poster_path.as_ref().unwrap_or(&"".to_string()),
-                &movie.backdrop_path.as_ref().unwrap_or(&"".to_string()),
-                &movie.vote_average.to_string(),
-                &movie.vote_count.to_string(),
-                &movie.tagline.as_ref().unwrap_or(&"".to_string()),
-                &movie.status,
-                &movie.adult.to_string()],
+                runtime,
+                budget,
+                revenue,
+                homepage,
+                belongs_to_collection,
+                updated) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, datetime('now'))
+            ON CONFLICT(id) DO UPDATE SET
+                original_title = excluded.original_title,
+                original_language = excluded.original_language,
+                title = excluded.title,
+                release_date = excluded.release_date,
+                overview = excluded.overview,
+                popularity = excluded.popularity,
+                poster_path = excluded.poster_path,
+                backdrop_path = excluded.backdrop_path,
+                vote_average = excluded.vote_average,
+                vote_count = excluded.vote_count,
+                tagline = excluded.tagline,
+                status = excluded.status,
+                adult = excluded.adult,
+                runtime = excluded.runtime,
+                budget = excluded.budget,
+                revenue = excluded.revenue,
+                homepage = excluded.homepage,
+                belongs_to_collection = excluded.belongs_to_collection,
+                updated = excluded.updated",
+
+            params![
+                movie.id,
+                movie.original_title,
+                movie.original_language,
+                movie.title,
+                movie.release_date,
+                movie.overview.as_deref().unwrap_or_default(),
+                movie.popularity,
+                movie.poster_path.as_deref().unwrap_or_default(),
+                movie.backdrop_path.as_deref().unwrap_or_default(),
+                movie.vote_average,
+                movie.vote_count,
+                movie.tagline.as_deref().unwrap_or_default(),
+                movie.status,
+                movie.adult.to_string(),
+                // TMDB gives 0 for unknown values
+                movie.runtime.filter(|runtime| *runtime > 0),
+                Some(movie.budget).filter(|budget| *budget > 0),
+                Some(movie.revenue).filter(|revenue| *revenue > 0),
+                movie.homepage.as_deref().unwrap_or_default(),
+                movie.belongs_to_collection.as_ref().map(|collection| collection.id)],
         )?;
 
+        // rewritten below, so a refresh drops what TMDB no longer lists
+        for table in ["MovieGenreLinks", "MovieCasts", "MovieCrews", "MovieTrailers", "MovieKeywordLinks",
+                        "MovieCompanyLinks", "MovieCountryLinks", "MovieLanguageLinks"]{
+            tx.execute(&format!("DELETE FROM {} WHERE movie_id = ?1", table), [movie.id])?;
+        }
+
         if let Some(backdrop_path) = &movie.backdrop_path{
             rsc_path.push(backdrop_path.clone())
         }
@@ -68,9 +132,11 @@ impl SqlLibrary{
 
         for genre in &movie.genres{
             tx.execute(
-                "INSERT OR REPLACE INTO MovieGenres (
+                "INSERT INTO MovieGenres (
                     id,
-                    name) values (?1, ?2)",
+                    name) values (?1, ?2)
+                ON CONFLICT(id) DO UPDATE SET
+                    name = excluded.name",
     
                 &[
                 &genre.id.to_string(),
@@ -102,12 +168,10 @@ impl SqlLibrary{
                 &cast.character.as_ref().unwrap_or(&"".to_string()), 
                 &cast.order.to_string()],
             )?;
-
-            person_ids.push(cast.id)
         }
 
         for crew in &movie.credits.crew{
-            if !(crew.job == "Screenplay" ||  crew.job == "Director" || crew.job == "Producer"){
+            if !crew.is_kept(){
                 continue
             }
     
@@ -122,8 +186,6 @@ impl SqlLibrary{
                 &movie.id.to_string(), 
                 &crew.job.to_string()],
             )?;
-
-            person_ids.push(crew.id)
         }
 
         for video in &movie.videos.results{
@@ -146,32 +208,64 @@ impl SqlLibrary{
         for keyword in &movie.keywords.keywords{
 
             tx.execute(
-                "INSERT OR REPLACE INTO movieKeywordLinks (
-                    keyword_id,
-                    movie_id) values (?1, ?2)",
+                "INSERT OR IGNORE INTO Keywords (
+                    id,
+                    name) values (?1, ?2)",
     
                 &[
                 &keyword.id.to_string(),
-                &movie.id.to_string()],
+                &keyword.name],
             )?;
     
             tx.execute(
-                "INSERT OR IGNORE INTO Keywords (
-                    id,
-                    name) values (?1, ?2)",
+                "INSERT OR REPLACE INTO movieKeywordLinks (
+                    keyword_id,
+                    movie_id) values (?1, ?2)",
     
                 &[
                 &keyword.id.to_string(),
-                &keyword.name],
+                &movie.id.to_string()],
             )?;
         }
 
+        for company in &movie.production_companies{
+            insert_company(&tx, "Companies", company.id, &company.name, &company.logo_path, &company.origin_country)?;
+            link(&tx, "MovieCompanyLinks", "movie_id", movie.id, "company_id", &company.id)?;
+            rsc_path.extend(company.logo_path.clone());
+        }
+
+        for country in &movie.production_countries{
+            insert_code(&tx, "Countries", &country.iso_3166_1, Some(&country.name))?;
+            link(&tx, "MovieCountryLinks", "movie_id", movie.id, "country_code", &country.iso_3166_1)?;
+        }
+
+        for language in &movie.spoken_languages{
+            insert_code(&tx, "Languages", &language.iso_639_1, Some(&language.name))?;
+            link(&tx, "MovieLanguageLinks", "movie_id", movie.id, "language_code", &language.iso_639_1)?;
+        }
+
+        for person in persons{
+            rsc_path.extend(insert_person(&tx, person)?);
+        }
+
+        apply_overrides(&tx, Editable::Movie, movie.id)?;
+
+        search::index(&tx, Document::Movie, movie.id)?;
+
+        // the movie may have moved to another franchise, the new one is linked if it is already known
+        tx.execute("DELETE FROM MovieCollectionLinks WHERE movie_id = ?1
+                        AND collection_id IN (SELECT id FROM Collections WHERE tmdb_id IS NOT NULL)", [movie.id])?;
+        if let Some(collection) = &movie.belongs_to_collection{
+            collection::link_franchise(&tx, collection.id)?;
+        }
+        collection::delete_empty_franchises(&tx)?;
+
         tx.commit()?;
 
-        Ok((person_ids, rsc_path))
+        Ok(rsc_path)
     }
 
-    pub fn get_movie(&self, user: &String, movie_id: u64) -> Result<Option<Movie>, Error>{
+    pub fn get_movie(&self, ctx: &Context, user: &String, movie_id: u64) -> Result<Option<Movie>, Error>{
         let sql = "SELECT
                         id,
                         original_title,
@@ -189,19 +283,25 @@ impl SqlLibrary{
                         genres,
                         adding,
                         MovieUserWatched.watched,
-                        updated
+                        updated,
+                        sort_title,
+                        runtime,
+                        budget,
+                        revenue,
+                        homepage,
+                        belongs_to_collection
                         FROM MoviesView
                         LEFT OUTER JOIN MovieUserWatched ON MoviesView.id = MovieUserWatched.movie_id AND MovieUserWatched.user_name = ?1
                         WHERE id = ?2
                         GROUP BY MoviesView.id";
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
         
-        let rows = stmt.query_map(&[user, &movie_id.to_string()], |row| {
+        let mut rows = stmt.query_map([user, &movie_id.to_string()], |row| {
 
-            Ok(Movie{ 
+            Ok((Movie{ 
                 user: user.clone(),
+                ctx: ctx.clone(),
                 id: row.get(0)?, 
                 original_title: row.get(1)?, 
                 original_language: row.get(2)?, 
@@ -219,26 +319,42 @@ impl SqlLibrary{
                 adding: row.get(14)?,
                 watched: parse_watched(row.get(15)?),  
                 updated: row.get(16)?,
+                sort_title: row.get(17)?,
+                runtime: row.get(18)?,
+                budget: row.get::<_, Option<u64>>(19)?.unwrap_or_default(),
+                revenue: row.get::<_, Option<u64>>(20)?.unwrap_or_default(),
+                homepage: row.get::<_, Option<String>>(21)?.unwrap_or_default(),
+                belongs_to_collection: None,
                 video: Vec::new(),
                 cast: Vec::new(),
                 crew: Vec::new(),
                 trailer: Vec::new(),
                 keyword: Vec::new(),
                 collection: Vec::new(),
+                companies: Vec::new(),
+                countries: Vec::new(),
+                languages: Vec::new(),
 
-            })
+            }, row.get::<_, Option<u64>>(22)?))
         })?;
 
-        for row in rows{
-            return Ok(Some(row?));
-        }
+        let (mut movie, collection_id) = match rows.next(){
+            Some(row) => row?,
+            None => return Ok(None),
+        };
+        drop(rows);
+        drop(stmt);
+        drop(conn);
 
-        Ok(None)
+        if let Some(collection_id) = collection_id{
+            movie.belongs_to_collection = self.get_tmdb_collection(collection_id)?;
+        }
+        Ok(Some(movie))
     }
 
-    pub fn get_movies(&self, user: &String, parameters: &HashMap<String, Option<(String, String)>>, 
-                order_by: &Option<String>, limit: Option<u64>, offset: Option<u64>) -> Result<Vec<MovieResult>, Error>{
-        let (sql, param) = generate_sql("SELECT 
+    fn movies_query<'a>(user: &'a String, parameters: &'a [Expr<MovieField>], order_by: &[Sort<MovieField>],
+                    limit: Option<u64>, offset: Option<u64>) -> (String, Vec<&'a dyn ToSql>){
+        generate_sql("SELECT 
                                                     Movies.id, 
                                                     Movies.title, 
                                                     Movies.release_date, 
@@ -247,7 +363,8 @@ impl SqlLibrary{
                                                     GROUP_CONCAT(DISTINCT MovieGenres.name),
                                                     MAX(Videos.adding),
                                                     MovieUserWatched.watched,
-                                                    Movies.backdrop_path
+                                                    Movies.backdrop_path,
+                                                    Movies.runtime
                                                 FROM Movies
                                                 INNER JOIN Videos ON Movies.id = Videos.media_id AND Videos.media_type = 0
                                                 LEFT OUTER JOIN MovieGenreLinks ON Movies.id = MovieGenreLinks.movie_id
@@ -255,16 +372,23 @@ impl SqlLibrary{
                                                 LEFT OUTER JOIN MovieCasts ON Movies.id = MovieCasts.movie_id
                                                 LEFT OUTER JOIN MovieCrews ON Movies.id = MovieCrews.movie_id
                                                 LEFT OUTER JOIN MovieCollectionLinks ON Movies.id = MovieCollectionLinks.movie_id
-                                                LEFT OUTER JOIN MovieUserWatched ON Movies.id = MovieUserWatched.movie_id AND MovieUserWatched.user_name = ?1", 
-                                                &parameters, Some(user), Some("Movies.id"), order_by, limit, offset);
+                                                LEFT OUTER JOIN MovieCompanyLinks ON Movies.id = MovieCompanyLinks.movie_id
+                                                LEFT OUTER JOIN MovieCountryLinks ON Movies.id = MovieCountryLinks.movie_id
+                                                LEFT OUTER JOIN MovieLanguageLinks ON Movies.id = MovieLanguageLinks.movie_id
+                                                LEFT OUTER JOIN MovieUserWatched ON Movies.id = MovieUserWatched.movie_id AND MovieUserWatched.user_name = ?1", parameters, Some(user), Some("Movies.id"), order_by, limit, offset)
+    }
+
+    pub fn get_movies(&self, ctx: &Context, user: &String, parameters: &[Expr<MovieField>], 
+                order_by: &[Sort<MovieField>], limit: Option<u64>, offset: Option<u64>) -> Result<Vec<MovieResult>, Error>{
+        let (sql, param) = SqlLibrary::movies_query(user, parameters, order_by, limit, offset);
         // println!("sql: {}", &sql);
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
     
         let rows = stmt.query_map(param.as_slice(), |row| {
             Ok(MovieResult{ 
                 user: user.clone(),
+                ctx: ctx.clone(),
                 id: row.get(0)?, 
                 title: row.get(1)?, 
                 release_date: row.get(2)?, 
@@ -274,6 +398,7 @@ impl SqlLibrary{
                 adding: row.get(6)?,
                 watched: parse_watched(row.get(7)?),
                 backdrop_path: row.get(8)?,
+                runtime: row.get(9)?,
             })
             
         })?;
@@ -285,7 +410,12 @@ impl SqlLibrary{
         Ok(result)
     }
 
-    pub fn get_movie_cast(&self, user: &String, movie_id: u64) -> Result<Vec<Cast>, Error>{
+    pub fn count_movies(&self, user: &String, parameters: &[Expr<MovieField>]) -> Result<u64, Error>{
+        let (sql, param) = SqlLibrary::movies_query(user, parameters, &[], None, None);
+        self.count(&sql, &param)
+    }
+
+    pub fn get_movie_cast(&self, ctx: &Context, user: &String, movie_id: u64) -> Result<Vec<Cast>, Error>{
         let sql = "SELECT
                             id,
                             character,
@@ -295,13 +425,13 @@ impl SqlLibrary{
                         FROM MovieCastsView
                         WHERE movie_id = ?";
         //println!("sql: {}", &sql);
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
     
         let rows = stmt.query_map(&[&movie_id.to_string()], |row| {
             Ok(Cast{
                 user: user.clone(),
+                ctx: ctx.clone(),
                 id: row.get(0)?,
                 character: row.get(1)?,
                 ord: row.get(2)?,
@@ -318,7 +448,7 @@ impl SqlLibrary{
         Ok(result)
     }
 
-    pub fn get_movie_crew(&self, user: &String, movie_id: u64) -> Result<Vec<Crew>, Error>{
+    pub fn get_movie_crew(&self, ctx: &Context, user: &String, movie_id: u64) -> Result<Vec<Crew>, Error>{
         let sql = "SELECT
                             id,
                             job,
@@ -327,13 +457,13 @@ impl SqlLibrary{
                         FROM MovieCrewsView
                         WHERE movie_id = ?";
         //println!("sql: {}", &sql);
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
     
         let rows = stmt.query_map(&[&movie_id.to_string()], |row| {
             Ok(Crew{
                 user: user.clone(),
+                ctx: ctx.clone(),
                 id: row.get(0)?,
                 job: row.get(1)?,
                 name: row.get(2)?,
@@ -349,6 +479,24 @@ impl SqlLibrary{
         Ok(result)
     }
 
+    /// the franchise of a movie, as TMDB names it
+    pub fn get_tmdb_collection(&self, collection_id: u64) -> Result<Option<BelongsToCollection>, Error>{
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare("SELECT id, name, poster_path, backdrop_path FROM TmdbCollections WHERE id = ?1")?;
+        let mut rows = stmt.query_map([collection_id], |row| {
+            Ok(BelongsToCollection{
+                id: row.get(0)?,
+                name: row.get(1)?,
+                poster_path: row.get(2)?,
+                backdrop_path: row.get(3)?,
+            })
+        })?;
+        match rows.next(){
+            Some(row) => Ok(Some(row?)),
+            None => Ok(None),
+        }
+    }
+
     pub fn get_movie_trailer(&self, movie_id: u64) -> Result<Vec<Trailer>, Error>{
         let sql = "SELECT
                             name,
@@ -357,8 +505,7 @@ impl SqlLibrary{
                         WHERE movie_id = ?";
         // println!("sql: {}", &sql);
 
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
     
         let rows = stmt.query_map(&[&movie_id.to_string()], |row| {
@@ -384,8 +531,7 @@ impl SqlLibrary{
                         INNER JOIN Keywords ON MovieKeywordLinks.keyword_id = Keywords.id
                         WHERE movie_id = ?";
         //println!("sql: {}", &sql);
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
     
         let rows = stmt.query_map(&[&movie_id.to_string()], |row| {
@@ -409,8 +555,7 @@ impl SqlLibrary{
                             id
                         FROM MovieGenres";
         //println!("sql: {}", &sql);
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
     
         let rows = stmt.query_map([], |row| {
@@ -429,8 +574,7 @@ impl SqlLibrary{
     }
 
     pub fn set_movie_watched(&self, user: String, movie_id: u64, watched: u64) -> Result< (), Error>{
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.writer()?;
         conn.execute(
             "INSERT OR REPLACE INTO MovieUserWatched (
                 watched,
@@ -444,34 +588,31 @@ impl SqlLibrary{
         Ok(())
     }
 
+    /// movies whose metadata was written more than `days` days ago
+    pub fn stale_movies(&self, days: u64) -> Result<Vec<u64>, Error>{
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare("SELECT id FROM Movies
+                                        WHERE (updated IS NULL OR updated < datetime('now', '-' || ?1 || ' days')) AND id < ?2
+                                        ORDER BY updated")?;
+        let rows = stmt.query_map([days, LOCAL_ID_BASE], |row| row.get(0))?;
+
+        let mut result = Vec::new();
+        for row in rows{
+            result.push(row?);
+        }
+        Ok(result)
+    }
+
     pub fn delete_movie(&self, movie_id: u64) -> Result<(), Error>{
-        let mut m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_mut().unwrap();
+        let mut conn = self.writer()?;
         let tx = conn.transaction()?;
 
+        tx.execute("DELETE FROM Overrides WHERE kind = 'movie' AND media_id = ?1", [movie_id])?;
         tx.execute("DELETE FROM Movies
                         WHERE id=?1", &[&movie_id.to_string()])?;
-        
-        tx.execute("DELETE FROM MovieGenreLinks
-                        WHERE movie_id=?1", &[&movie_id.to_string()])?;
-        
-        tx.execute("DELETE FROM MovieCollectionLinks
-                        WHERE movie_id=?1", &[&movie_id.to_string()])?;
 
-        tx.execute("DELETE FROM MovieKeywordLinks
-                        WHERE movie_id=?1", &[&movie_id.to_string()])?;
-
-        tx.execute("DELETE FROM MovieTrailers
-                        WHERE movie_id=?1", &[&movie_id.to_string()])?;
-
-        tx.execute("DELETE FROM MovieCasts
-                        WHERE movie_id=?1", &[&movie_id.to_string()])?;
-
-        tx.execute("DELETE FROM MovieCrews
-                        WHERE movie_id=?1", &[&movie_id.to_string()])?;
-        
-        tx.execute("DELETE FROM MovieUserWatched
-                        WHERE movie_id=?1", &[&movie_id.to_string()])?;
+        search::unindex(&tx, Document::Movie, movie_id)?;
+        collection::delete_empty_franchises(&tx)?;
 
         tx.commit()?;
         
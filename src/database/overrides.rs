@@ -0,0 +1,130 @@
+use rusqlite::{Connection, OptionalExtension, params};
+
+use crate::library::overrides::Editable;
+use super::search::{self, Document};
+use super::{SqlLibrary, Error};
+
+fn table(kind: Editable) -> &'static str{
+    match kind{
+        Editable::Movie => "Movies",
+        Editable::Tv => "Tvs",
+        Editable::Season => "Seasons",
+        Editable::Episode => "Episodes",
+    }
+}
+
+/// genre table, link table and its media column
+fn genre_tables(kind: Editable) -> (&'static str, &'static str, &'static str){
+    match kind{
+        Editable::Movie => ("MovieGenres", "MovieGenreLinks", "movie_id"),
+        _ => ("TvGenres", "TvGenreLinks", "tv_id"),
+    }
+}
+
+fn reindex(conn: &Connection, kind: Editable, id: u64) -> Result<(), rusqlite::Error>{
+    match kind{
+        Editable::Movie => search::index(conn, Document::Movie, id),
+        Editable::Tv => search::index(conn, Document::Tv, id),
+        Editable::Episode => search::index(conn, Document::Episode, id),
+        Editable::Season => Ok(()),
+    }
+}
+
+/// current value of `field`, genres as a comma separated list of names
+fn read(conn: &Connection, kind: Editable, id: u64, field: &str) -> Result<Option<String>, rusqlite::Error>{
+    if field == "genres"{
+        let (genres, links, column) = genre_tables(kind);
+        return conn.query_row(
+            &format!("SELECT GROUP_CONCAT(name) FROM (SELECT {genres}.name AS name FROM {links}
+                        INNER JOIN {genres} ON {links}.genre_id = {genres}.id
+                        WHERE {links}.{column} = ?1 ORDER BY {genres}.name)", genres = genres, links = links, column = column),
+            [id], |row| row.get(0))
+    }
+    conn.query_row(&format!("SELECT {} FROM {} WHERE id = ?1", field, table(kind)), [id], |row| row.get(0)).optional().map(Option::flatten)
+}
+
+fn write(conn: &Connection, kind: Editable, id: u64, field: &str, value: Option<&str>) -> Result<(), rusqlite::Error>{
+    if field == "genres"{
+        let (genres, links, column) = genre_tables(kind);
+        conn.execute(&format!("DELETE FROM {} WHERE {} = ?1", links, column), [id])?;
+        for name in value.unwrap_or_default().split(',').filter(|name| !name.is_empty()){
+            conn.execute(
+                &format!("INSERT OR IGNORE INTO {links} (genre_id, {column}) SELECT id, ?1 FROM {genres} WHERE name = ?2",
+                    genres = genres, links = links, column = column),
+                params![id, name],
+            )?;
+        }
+        return Ok(())
+    }
+    conn.execute(&format!("UPDATE {} SET {} = ?1 WHERE id = ?2", table(kind), field), params![value, id])?;
+    Ok(())
+}
+
+/// put the edits back over values just written from TMDB, keeping those as the provider values to revert to
+pub fn apply_overrides(conn: &Connection, kind: Editable, id: u64) -> Result<(), rusqlite::Error>{
+    let overrides: Vec<(String, Option<String>)> = conn
+        .prepare("SELECT field, value FROM Overrides WHERE kind = ?1 AND media_id = ?2")?
+        .query_map(params![kind.name(), id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    for (field, value) in overrides{
+        let provider_value = read(conn, kind, id, &field)?;
+        conn.execute(
+            "UPDATE Overrides SET provider_value = ?4 WHERE kind = ?1 AND media_id = ?2 AND field = ?3",
+            params![kind.name(), id, field, provider_value],
+        )?;
+        write(conn, kind, id, &field, value.as_deref())?;
+    }
+    Ok(())
+}
+
+impl SqlLibrary{
+    /// write `value` and lock `field`, the fields are checked by `Editable::check`
+    pub fn set_override(&self, kind: Editable, id: u64, field: &str, value: &str) -> Result<(), Error>{
+        let mut conn = self.writer()?;
+        let tx = conn.transaction()?;
+
+        // the first edit keeps what the provider gave
+        let provider_value = read(&tx, kind, id, field)?;
+        tx.execute(
+            "INSERT INTO Overrides (kind, media_id, field, value, provider_value) values (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT(kind, media_id, field) DO UPDATE SET value = excluded.value",
+            params![kind.name(), id, field, value, provider_value],
+        )?;
+        write(&tx, kind, id, field, Some(value))?;
+        reindex(&tx, kind, id)?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// unlock `field` and put back the provider value, nothing happens if it was not edited
+    pub fn revert_override(&self, kind: Editable, id: u64, field: &str) -> Result<(), Error>{
+        let mut conn = self.writer()?;
+        let tx = conn.transaction()?;
+
+        let provider_value: Option<Option<String>> = tx.query_row(
+            "SELECT provider_value FROM Overrides WHERE kind = ?1 AND media_id = ?2 AND field = ?3",
+            params![kind.name(), id, field], |row| row.get(0)).optional()?;
+        if let Some(provider_value) = provider_value{
+            write(&tx, kind, id, field, provider_value.as_deref())?;
+            tx.execute("DELETE FROM Overrides WHERE kind = ?1 AND media_id = ?2 AND field = ?3", params![kind.name(), id, field])?;
+            reindex(&tx, kind, id)?;
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn locked_fields(&self, kind: Editable, id: u64) -> Result<Vec<String>, Error>{
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare("SELECT field FROM Overrides WHERE kind = ?1 AND media_id = ?2 ORDER BY field")?;
+        let rows = stmt.query_map(params![kind.name(), id], |row| row.get(0))?;
+
+        let mut result = Vec::new();
+        for row in rows{
+            result.push(row?);
+        }
+        Ok(result)
+    }
+}
@@ -1,59 +1,111 @@
-use std::collections::HashMap;
-
+use rusqlite::{params, Connection, ToSql};
+use strsim::jaro_winkler;
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
 use crate::library::cast::Person;
 use crate::library::cast::PersonResult;
 use crate::rustmdb;
 use super::Error;
+use crate::library::Context;
+use crate::library::filter::{Condition, Expr, Operator, Sort};
+use crate::library::cast::PersonField;
 use super::SqlLibrary;
+use super::search::{self, Document};
 use super::generate_sql;
 
+/// persons matching a query of `person_match_query`
+const PERSON_MATCH: &str = "Persons.id IN (SELECT media_id FROM SearchIndex WHERE SearchIndex MATCH ? AND kind = 'person')";
 
-impl SqlLibrary{
-
-    pub fn create_person(&self, person: &rustmdb::model::Person) -> Result<(Vec<u64>, Vec<String>), Error>{
-        let mut m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_mut().unwrap();
-        let tx = conn.transaction()?;
+const TYPO_CANDIDATES: u64 = 10000;
+const TYPO_MATCHES: usize = 100;
 
-        let person_ids = Vec::new();
-        let mut rsc_path = Vec::new();
-
-        tx.execute(
-            "INSERT OR REPLACE INTO Persons (
-                id,
-                birthday,
-                known_for_department,
-                deathday,
-                name,
-                gender,
-                biography,
-                popularity,
-                place_of_birth,
-                profile_path) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
-
-            &[
-            &person.id.to_string(),
-            &person.birthday.as_ref().unwrap_or(&"".to_string()),
-            &person.known_for_department.as_ref().unwrap_or(&"".to_string()),
-            &person.deathday.as_ref().unwrap_or(&"".to_string()),
-            &person.name,
-            &person.gender.to_string(),
-            &person.biography,
-            &person.popularity.to_string(),
-            &person.place_of_birth.as_ref().unwrap_or(&"".to_string()),
-            &person.profile_path.as_ref().unwrap_or(&"".to_string())],
-        )?;
-
-        if let Some(profile_path) = &person.profile_path{
-            rsc_path.push(profile_path.clone())
+/// `text` lowercased without its diacritics, decomposed letters lose their marks
+/// and the few that don't decompose are spelled out, so "Łódź" compares with "lodz"
+fn fold(text: &str) -> String{
+    let mut folded = String::with_capacity(text.len());
+    for c in text.nfkd().filter(|c| !is_combining_mark(*c)).flat_map(char::to_lowercase){
+        match c{
+            'ß' => folded += "ss",
+            'æ' => folded += "ae",
+            'œ' => folded += "oe",
+            'þ' => folded += "th",
+            'ø' => folded.push('o'),
+            'ð' | 'đ' => folded.push('d'),
+            'ł' => folded.push('l'),
+            'ħ' => folded.push('h'),
+            'ı' => folded.push('i'),
+            c => folded.push(c),
         }
+    }
+    folded
+}
 
-        tx.commit()?;
+/// write a person fetched with the media crediting it, returns its images to download
+pub fn insert_person(conn: &Connection, person: &rustmdb::model::Person) -> Result<Vec<String>, rusqlite::Error>{
+    let mut rsc_path = Vec::new();
 
-        Ok((person_ids, rsc_path))
+    conn.execute(
+        "INSERT INTO Persons (
+            id,
+            birthday,
+            known_for_department,
+            deathday,
+            name,
+            gender,
+            biography,
+            popularity,
+            place_of_birth,
+            profile_path,
+            imdb_id,
+            homepage,
+            adult) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+        ON CONFLICT(id) DO UPDATE SET
+            birthday = excluded.birthday,
+            known_for_department = excluded.known_for_department,
+            deathday = excluded.deathday,
+            name = excluded.name,
+            gender = excluded.gender,
+            biography = excluded.biography,
+            popularity = excluded.popularity,
+            place_of_birth = excluded.place_of_birth,
+            profile_path = excluded.profile_path,
+            imdb_id = excluded.imdb_id,
+            homepage = excluded.homepage,
+            adult = excluded.adult",
+
+        params![
+        person.id,
+        person.birthday.as_deref().unwrap_or_default(),
+        person.known_for_department.as_deref().unwrap_or_default(),
+        person.deathday.as_deref().unwrap_or_default(),
+        person.name,
+        person.gender,
+        person.biography,
+        person.popularity,
+        person.place_of_birth.as_deref().unwrap_or_default(),
+        person.profile_path.as_deref().unwrap_or_default(),
+        person.imdb_id.as_deref().unwrap_or_default(),
+        person.homepage.as_deref().unwrap_or_default(),
+        person.adult],
+    )?;
+
+    conn.execute("DELETE FROM PersonAliases WHERE person_id = ?1", [person.id])?;
+    for alias in &person.also_known_as{
+        conn.execute("INSERT OR IGNORE INTO PersonAliases (person_id, name) values (?1, ?2)", params![person.id, alias])?;
+    }
+
+    if let Some(profile_path) = &person.profile_path{
+        rsc_path.push(profile_path.clone())
     }
 
-    pub fn get_person(&self, user: &String, person_id: u64) -> Result<Option<Person>, Error>{
+    search::index(conn, Document::Person, person.id)?;
+
+    Ok(rsc_path)
+}
+
+impl SqlLibrary{
+
+    pub fn get_person(&self, ctx: &Context, user: &String, person_id: u64) -> Result<Option<Person>, Error>{
         let sql = "SELECT 
                             id,
                             birthday,
@@ -64,19 +116,22 @@ impl SqlLibrary{
                             biography,
                             popularity,
                             place_of_birth,
-                            profile_path 
+                            profile_path,
+                            imdb_id,
+                            homepage,
+                            adult
                         FROM Persons
                         WHERE Persons.id = ?1
                         GROUP BY Persons.id ";
 
         //println!("sql: {}", &sql);
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
     
         let rows = stmt.query_map([&person_id.to_string()], |row| {
             Ok(Person{
                 user: user.clone(),
+                ctx: ctx.clone(),
                 id: row.get(0)?,
                 birthday: row.get(1)?,
                 known_for_department: row.get(2)?,
@@ -87,39 +142,118 @@ impl SqlLibrary{
                 popularity:row.get(7)?,
                 place_of_birth: row.get(8)?,
                 profile_path: row.get(9)?,
+                imdb_id: row.get::<_, Option<String>>(10)?.unwrap_or_default(),
+                homepage: row.get::<_, Option<String>>(11)?.unwrap_or_default(),
+                adult: row.get(12)?,
+                also_known_as: Vec::new(),
                 cast_movie: Vec::new(),
                 crew_movie: Vec::new(),
                 cast_tv:  Vec::new(),
                 crew_tv:  Vec::new(),
+                creator_tv: Vec::new(),
             })
             
         })?;
 
         for person in rows{
-            return Ok(Some(person?))
+            let mut person = person?;
+            person.also_known_as = self.get_person_aliases(person_id)?;
+            return Ok(Some(person))
         }
 
         Ok(None)
     }
 
-    pub fn get_persons(&self, user: &String, parameters: &HashMap<String, Option<(String, String)>>,
-                    order_by: &Option<String>, limit: Option<u64>, offset: Option<u64>) -> Result<Vec<PersonResult>, Error>{
-        let (sql, param) = generate_sql("SELECT 
+    fn get_person_aliases(&self, person_id: u64) -> Result<Vec<String>, Error>{
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare("SELECT name FROM PersonAliases WHERE person_id = ?1 ORDER BY rowid")?;
+        let rows = stmt.query_map([person_id], |row| row.get(0))?;
+        let mut result = Vec::new();
+        for row in rows{
+            result.push(row?);
+        }
+        Ok(result)
+    }
+
+    /// persons whose name or an alias matches `query`: every word prefixes a word of the name, accents ignored.
+    /// Without any, the persons with close names, see `close_persons`.
+    pub fn person_name_condition(&self, query: &str) -> Result<Expr<PersonField>, Error>{
+        if let Some(query) = self.person_match_query(query)?{
+            return Ok(Expr::Sql(PERSON_MATCH, vec![query]))
+        }
+        let ids: Vec<String> = self.close_persons(query)?.iter().map(u64::to_string).collect();
+        if ids.is_empty(){
+            // an empty OR never holds
+            return Ok(Expr::Or(Vec::new()))
+        }
+        Ok(Expr::Condition(Condition{ field: PersonField::Id, operator: Operator::In, values: ids }))
+    }
+
+    /// full text query of `PERSON_MATCH` for `query`, `None` when no person matches
+    fn person_match_query(&self, query: &str) -> Result<Option<String>, Error>{
+        let query = match search::match_query(query){
+            Some(query) => format!("{{title original_title}} : ({})", query),
+            None => return Ok(None),
+        };
+        let conn = self.reader()?;
+        let found: bool = conn.query_row("SELECT EXISTS(SELECT 1 FROM SearchIndex WHERE SearchIndex MATCH ?1 AND kind = 'person')",
+            [&query], |row| row.get(0))?;
+        Ok(if found { Some(query) } else { None })
+    }
+
+    /// the persons with a word of their name or an alias close to every word of `query`, to forgive a typo.
+    /// Only the names of the `TYPO_CANDIDATES` most popular persons are compared, at most `TYPO_MATCHES` are returned.
+    fn close_persons(&self, query: &str) -> Result<Vec<u64>, Error>{
+        let terms: Vec<String> = fold(query).split_whitespace().map(str::to_string).collect();
+        if terms.is_empty(){
+            return Ok(Vec::new())
+        }
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare("SELECT id, name FROM (
+                                            SELECT id, name, popularity FROM Persons
+                                            UNION ALL SELECT Persons.id, PersonAliases.name, Persons.popularity FROM PersonAliases
+                                            INNER JOIN Persons ON Persons.id = PersonAliases.person_id)
+                                        ORDER BY popularity DESC
+                                        LIMIT ?1")?;
+        let rows = stmt.query_map([TYPO_CANDIDATES], |row| Ok((row.get::<_, u64>(0)?, row.get::<_, Option<String>>(1)?.unwrap_or_default())))?;
+        let mut result = Vec::new();
+        for row in rows{
+            let (id, name) = row?;
+            let name = fold(&name);
+            let close = terms.iter().all(|term| name.split(|c: char| !c.is_alphanumeric()).any(|word| jaro_winkler(term, word) >= 0.85));
+            if close && !result.contains(&id){
+                result.push(id);
+                if result.len() == TYPO_MATCHES{
+                    break
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    fn persons_query<'a>(parameters: &'a [Expr<PersonField>], order_by: &[Sort<PersonField>],
+                    limit: Option<u64>, offset: Option<u64>) -> (String, Vec<&'a dyn ToSql>){
+        generate_sql("SELECT 
                                                     id,
                                                     name,
                                                     birthday,
                                                     profile_path 
                                                 FROM Persons
-                                                ", parameters, None, Some("Persons.id"), order_by, limit, offset);
+                                                ", parameters, None, Some("Persons.id"), order_by, limit, offset)
+    }
+
+    pub fn get_persons(&self, ctx: &Context, user: &String, parameters: &[Expr<PersonField>],
+                    order_by: &[Sort<PersonField>], limit: Option<u64>, offset: Option<u64>) -> Result<Vec<PersonResult>, Error>{
+        let (sql, param) = SqlLibrary::persons_query(parameters, order_by, limit, offset);
 
         //println!("sql: {}", &sql);
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
     
         let rows = stmt.query_map(param.as_slice(), |row| {
             Ok(PersonResult{
                 user: user.clone(),
+                ctx: ctx.clone(),
                 id: row.get(0)?,
                 name: row.get(1)?,
                 birthday: row.get(2)?,
@@ -135,16 +269,43 @@ impl SqlLibrary{
         Ok(result)
     }
 
+    pub fn count_persons(&self, parameters: &[Expr<PersonField>]) -> Result<u64, Error>{
+        let (sql, param) = SqlLibrary::persons_query(parameters, &[], None, None);
+        self.count(&sql, &param)
+    }
+
+    /// rewrite a person fetched again, returns its images to download
+    pub fn update_person(&self, person: &rustmdb::model::Person) -> Result<Vec<String>, Error>{
+        let mut conn = self.writer()?;
+        let tx = conn.transaction()?;
+        let rsc_path = insert_person(&tx, person)?;
+        tx.commit()?;
+        Ok(rsc_path)
+    }
+
     pub fn delete_person(&self, person_id: u64) -> Result<(), Error>{
-        let mut m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_mut().unwrap();
+        let mut conn = self.writer()?;
         let tx = conn.transaction()?;
 
         tx.execute("DELETE FROM Persons
                         WHERE id=?1", &[&person_id.to_string()])?;
         
+        search::unindex(&tx, Document::Person, person_id)?;
+
         tx.commit()?;
         
         Ok(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests{
+    use super::fold;
+
+    #[test]
+    fn fold_drops_diacritics(){
+        assert_eq!(fold("Zoë Saldaña"), "zoe saldana");
+        assert_eq!(fold("Łódź Ørsted"), "lodz orsted");
+        assert_eq!(fold("Straße Æsir Œuvre Þór Đoković"), "strasse aesir oeuvre thor dokovic");
+        assert_eq!(fold("ﬁnn"), "finn");
+    }
+}
@@ -0,0 +1,74 @@
+use std::ops::Deref;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rusqlite::{Connection, OpenFlags};
+
+use super::Error;
+
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_IDLE_READERS: usize = 8;
+
+pub fn open_writer(path: &str) -> Result<Connection, Error>{
+    let conn = Connection::open(path)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    conn.execute_batch("PRAGMA journal_mode = WAL;
+                        PRAGMA synchronous = NORMAL;")?;
+    Ok(conn)
+}
+
+fn open_reader(path: &str) -> Result<Connection, Error>{
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY
+                                                | OpenFlags::SQLITE_OPEN_NO_MUTEX
+                                                | OpenFlags::SQLITE_OPEN_URI)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    Ok(conn)
+}
+
+/// Read only connections, opened on demand and kept around once released.
+/// In WAL mode they never wait on the writer and always see its last commit.
+#[derive(Debug)]
+pub struct ReaderPool{
+    path: String,
+    idle: Mutex<Vec<Connection>>,
+}
+
+impl ReaderPool{
+    pub fn new(path: &str) -> ReaderPool{
+        ReaderPool{
+            path: path.to_string(),
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn get(&self) -> Result<PooledConnection<'_>, Error>{
+        if let Some(conn) = self.idle.lock().unwrap().pop(){
+            return Ok(PooledConnection{ pool: self, conn: Some(conn) })
+        }
+        Ok(PooledConnection{ pool: self, conn: Some(open_reader(&self.path)?) })
+    }
+}
+
+pub struct PooledConnection<'a>{
+    pool: &'a ReaderPool,
+    conn: Option<Connection>,
+}
+
+impl Deref for PooledConnection<'_>{
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection{
+        self.conn.as_ref().unwrap()
+    }
+}
+
+impl Drop for PooledConnection<'_>{
+    fn drop(&mut self){
+        if let Some(conn) = self.conn.take(){
+            let mut idle = self.pool.idle.lock().unwrap();
+            if idle.len() < MAX_IDLE_READERS{
+                idle.push(conn);
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use rusqlite::{params, Transaction};
+
+use crate::library::production::{Company, Country, Language};
+use super::{SqlLibrary, Error};
+
+/// `table` is Companies or Networks, they share their columns
+pub fn insert_company(tx: &Transaction, table: &str, id: u64, name: &str, logo_path: &Option<String>, origin_country: &str) -> Result<(), rusqlite::Error>{
+    tx.execute(
+        &format!("INSERT INTO {} (
+            id,
+            name,
+            logo_path,
+            origin_country) values (?1, ?2, ?3, ?4)
+        ON CONFLICT(id) DO UPDATE SET
+            name = excluded.name,
+            logo_path = excluded.logo_path,
+            origin_country = excluded.origin_country", table),
+        params![id, name, logo_path.as_deref().unwrap_or_default(), origin_country],
+    )?;
+    Ok(())
+}
+
+/// `table` is Countries or Languages, without a name the known one is kept, the code stands for it otherwise
+pub fn insert_code(tx: &Transaction, table: &str, code: &str, name: Option<&str>) -> Result<(), rusqlite::Error>{
+    tx.execute(
+        &format!("INSERT INTO {} (
+            code,
+            name) values (?1, COALESCE(?2, ?1))
+        ON CONFLICT(code) DO UPDATE SET
+            name = COALESCE(?2, name)", table),
+        params![code, name],
+    )?;
+    Ok(())
+}
+
+/// link a movie or tv (`column` = `media_id`) to a company, network, country or language (`other` = `value`)
+pub fn link(tx: &Transaction, table: &str, column: &str, media_id: u64, other: &str, value: &dyn rusqlite::ToSql) -> Result<(), rusqlite::Error>{
+    tx.execute(
+        &format!("INSERT OR IGNORE INTO {} ({}, {}) values (?1, ?2)", table, column, other),
+        params![media_id, value],
+    )?;
+    Ok(())
+}
+
+impl SqlLibrary{
+    /// companies or networks (`table`) linked to a movie or tv through `link`
+    pub fn get_companies(&self, table: &str, link: &str, column: &str, other: &str, media_id: u64) -> Result<Vec<Company>, Error>{
+        let sql = format!("SELECT
+                            id,
+                            name,
+                            logo_path,
+                            origin_country
+                        FROM {1}
+                        INNER JOIN {0} ON {1}.{3} = {0}.id
+                        WHERE {1}.{2} = ?1
+                        ORDER BY name", table, link, column, other);
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(&sql)?;
+
+        let rows = stmt.query_map([media_id], |row| {
+            Ok(Company{
+                id: row.get(0)?,
+                name: row.get(1)?,
+                logo_path: row.get(2)?,
+                origin_country: row.get(3)?,
+            })
+        })?;
+
+        let mut result = Vec::new();
+        for row in rows{
+            result.push(row?);
+        }
+        Ok(result)
+    }
+
+    fn get_codes(&self, table: &str, link: &str, column: &str, other: &str, media_id: u64) -> Result<Vec<(String, String)>, Error>{
+        let sql = format!("SELECT
+                            code,
+                            name
+                        FROM {1}
+                        INNER JOIN {0} ON {1}.{3} = {0}.code
+                        WHERE {1}.{2} = ?1
+                        ORDER BY code", table, link, column, other);
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(&sql)?;
+
+        let rows = stmt.query_map([media_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        let mut result = Vec::new();
+        for row in rows{
+            result.push(row?);
+        }
+        Ok(result)
+    }
+
+    /// `link` is MovieCountryLinks or TvCountryLinks, `column` movie_id or tv_id
+    pub fn get_countries(&self, link: &str, column: &str, media_id: u64) -> Result<Vec<Country>, Error>{
+        Ok(self.get_codes("Countries", link, column, "country_code", media_id)?.into_iter()
+            .map(|(code, name)| Country{ code, name })
+            .collect())
+    }
+
+    pub fn get_languages(&self, link: &str, column: &str, media_id: u64) -> Result<Vec<Language>, Error>{
+        Ok(self.get_codes("Languages", link, column, "language_code", media_id)?.into_iter()
+            .map(|(code, name)| Language{ code, name })
+            .collect())
+    }
+}
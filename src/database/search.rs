@@ -0,0 +1,159 @@
+use rusqlite::Connection;
+
+use crate::library::Context;
+use crate::library::search::SearchResult;
+use super::{SqlLibrary, Error};
+
+/// Kind of row held by the full text index, stored in its `kind` column
+#[derive(Debug, Clone, Copy)]
+pub enum Document{
+    Movie,
+    Tv,
+    Episode,
+    Person,
+    Collection,
+}
+
+impl Document{
+    pub fn name(&self) -> &'static str{
+        match self{
+            Document::Movie => "movie",
+            Document::Tv => "tv",
+            Document::Episode => "episode",
+            Document::Person => "person",
+            Document::Collection => "collection",
+        }
+    }
+
+    /// rows of the index for this kind, `index` restricts them to one id of `table`
+    fn select(&self) -> &'static str{
+        match self{
+            Document::Movie => "SELECT 'movie', Movies.id, Movies.title, Movies.original_title,
+                                    COALESCE(Movies.tagline, '') || ' ' || COALESCE(Movies.overview, ''),
+                                    COALESCE((SELECT GROUP_CONCAT(character, ' ') FROM MovieCasts WHERE movie_id = Movies.id), '') || ' ' ||
+                                    COALESCE((SELECT GROUP_CONCAT(Keywords.name, ' ') FROM MovieKeywordLinks
+                                                INNER JOIN Keywords ON MovieKeywordLinks.keyword_id = Keywords.id
+                                                WHERE movie_id = Movies.id), '')
+                                FROM Movies",
+            Document::Tv => "SELECT 'tv', Tvs.id, Tvs.title, Tvs.original_title, COALESCE(Tvs.overview, ''),
+                                    COALESCE((SELECT GROUP_CONCAT(character, ' ') FROM TvCasts WHERE tv_id = Tvs.id), '') || ' ' ||
+                                    COALESCE((SELECT GROUP_CONCAT(Keywords.name, ' ') FROM TvKeywordLinks
+                                                INNER JOIN Keywords ON TvKeywordLinks.keyword_id = Keywords.id
+                                                WHERE tv_id = Tvs.id), '')
+                                FROM Tvs",
+            Document::Episode => "SELECT 'episode', Episodes.id, Episodes.title, '', COALESCE(Episodes.overview, ''),
+                                    COALESCE((SELECT GROUP_CONCAT(character, ' ') FROM EpisodeCasts WHERE episode_id = Episodes.id), '')
+                                FROM Episodes",
+            Document::Person => "SELECT 'person', Persons.id, Persons.name,
+                                    COALESCE((SELECT GROUP_CONCAT(PersonAliases.name, ' ') FROM PersonAliases WHERE person_id = Persons.id), ''), '', ''
+                                FROM Persons",
+            Document::Collection => "SELECT 'collection', Collections.id, Collections.name, '', COALESCE(Collections.description, ''), ''
+                                FROM Collections",
+        }
+    }
+
+    fn table(&self) -> &'static str{
+        match self{
+            Document::Movie => "Movies",
+            Document::Tv => "Tvs",
+            Document::Episode => "Episodes",
+            Document::Person => "Persons",
+            Document::Collection => "Collections",
+        }
+    }
+}
+
+const INDEX_COLUMNS: &str = "INSERT INTO SearchIndex (kind, media_id, title, original_title, overview, names)";
+
+pub fn create_index(conn: &Connection) -> Result<(), rusqlite::Error>{
+    conn.execute(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS SearchIndex USING fts5(
+            kind UNINDEXED,
+            media_id UNINDEXED,
+            title,
+            original_title,
+            overview,
+            names,
+            tokenize = 'unicode61 remove_diacritics 2')",
+        [],
+    )?;
+    Ok(())
+}
+
+/// index again what the database holds, the documents follow the latest schema like the views
+pub fn fill_index(conn: &Connection) -> Result<(), rusqlite::Error>{
+    conn.execute("DELETE FROM SearchIndex", [])?;
+    for document in [Document::Movie, Document::Tv, Document::Episode, Document::Person, Document::Collection]{
+        conn.execute(&format!("{} {}", INDEX_COLUMNS, document.select()), [])?;
+    }
+    Ok(())
+}
+
+/// (re)index one row, to be called in the transaction that wrote it
+pub fn index(conn: &Connection, document: Document, id: u64) -> Result<(), rusqlite::Error>{
+    unindex(conn, document, id)?;
+    conn.execute(&format!("{} {} WHERE {}.id = ?1", INDEX_COLUMNS, document.select(), document.table()), [id])?;
+    Ok(())
+}
+
+pub fn unindex(conn: &Connection, document: Document, id: u64) -> Result<(), rusqlite::Error>{
+    conn.execute("DELETE FROM SearchIndex WHERE kind = ?1 AND media_id = ?2", rusqlite::params![document.name(), id])?;
+    Ok(())
+}
+
+/// episodes leave with their tv through the cascade, their rows in the index have to go first
+pub fn unindex_episodes(conn: &Connection, tv_id: u64) -> Result<(), rusqlite::Error>{
+    conn.execute("DELETE FROM SearchIndex WHERE kind = 'episode'
+                    AND media_id IN (SELECT id FROM Episodes WHERE tv_id = ?1)", [tv_id])?;
+    Ok(())
+}
+
+/// every word of the query has to prefix a word of the document, in any column
+pub fn match_query(query: &str) -> Option<String>{
+    let terms: Vec<String> = query.split_whitespace()
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect();
+    if terms.is_empty(){
+        return None
+    }
+    Some(terms.join(" "))
+}
+
+impl SqlLibrary{
+    pub fn search(&self, ctx: &Context, user: &String, query: &str, limit: u64) -> Result<Vec<SearchResult>, Error>{
+        let query = match match_query(query){
+            Some(query) => query,
+            None => return Ok(Vec::new()),
+        };
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(
+            "SELECT
+                kind,
+                media_id,
+                title,
+                snippet(SearchIndex, -1, '<b>', '</b>', '…', 16),
+                -bm25(SearchIndex, 0.0, 0.0, 10.0, 5.0, 1.0, 2.0) AS score
+            FROM SearchIndex
+            WHERE SearchIndex MATCH ?1
+            ORDER BY score DESC
+            LIMIT ?2")?;
+
+        let rows = stmt.query_map(rusqlite::params![query, limit], |row| {
+            Ok(SearchResult{
+                user: user.clone(),
+                ctx: ctx.clone(),
+                kind: row.get(0)?,
+                id: row.get(1)?,
+                title: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                snippet: row.get(3)?,
+                score: row.get(4)?,
+            })
+        })?;
+
+        let mut result = Vec::new();
+        for row in rows{
+            result.push(row?);
+        }
+        Ok(result)
+    }
+}
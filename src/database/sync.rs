@@ -0,0 +1,60 @@
+use rusqlite::OptionalExtension;
+
+use super::{SqlLibrary, Error};
+
+/// TMDB changes feeds cover at most 14 days per request
+const WINDOW_DAYS: u64 = 14;
+
+impl SqlLibrary{
+    /// day of the last `Library.sync_changes`
+    pub fn last_sync(&self) -> Result<Option<String>, Error>{
+        let conn = self.reader()?;
+        Ok(conn.query_row("SELECT value FROM SyncState WHERE name = 'last_sync'", [], |row| row.get(0)).optional()?)
+    }
+
+    pub fn set_last_sync(&self, date: &str) -> Result<(), Error>{
+        let conn = self.writer()?;
+        conn.execute(
+            "INSERT INTO SyncState (name, value) values ('last_sync', ?1)
+            ON CONFLICT(name) DO UPDATE SET value = excluded.value",
+            [date],
+        )?;
+        Ok(())
+    }
+
+    /// `date` as YYYY-MM-DD, None when sqlite can not read it. Modifiers such as '-7 days' are applied to now.
+    pub fn normalize_date(&self, date: &str) -> Result<Option<String>, Error>{
+        let conn = self.reader()?;
+        let sql = if date.starts_with('-') || date.starts_with('+'){
+            "SELECT date('now', ?1)"
+        }
+        else{
+            "SELECT date(?1)"
+        };
+        Ok(conn.query_row(sql, [date], |row| row.get(0))?)
+    }
+
+    pub fn today(&self) -> Result<String, Error>{
+        let conn = self.reader()?;
+        Ok(conn.query_row("SELECT date('now')", [], |row| row.get(0))?)
+    }
+
+    /// (start_date, end_date) windows covering `since` up to today
+    pub fn sync_windows(&self, since: &str) -> Result<Vec<(String, String)>, Error>{
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(
+            "WITH RECURSIVE Windows(start) AS (
+                SELECT date(?1)
+                UNION ALL
+                SELECT date(start, '+' || ?2 || ' days') FROM Windows
+                    WHERE date(start, '+' || ?2 || ' days') <= date('now'))
+            SELECT start, MIN(date(start, '+' || ?2 || ' days'), date('now')) FROM Windows")?;
+        let rows = stmt.query_map(rusqlite::params![since, WINDOW_DAYS], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        let mut result = Vec::new();
+        for row in rows{
+            result.push(row?);
+        }
+        Ok(result)
+    }
+}
@@ -1,6 +1,7 @@
-use std::collections::HashMap;
+use rusqlite::{params, ToSql};
 
 use crate::database::parse_watched;
+use crate::library::chapter::Markers;
 use crate::library::cast::Cast;
 use crate::library::cast::Crew;
 use crate::library::genre::Genre;
@@ -12,23 +13,30 @@ use crate::library::tv::TvResult;
 use crate::library::tv::Season;
 use crate::rustmdb;
 use super::Error;
+use crate::library::Context;
+use crate::library::filter::{Expr, Sort};
+use crate::library::tv::{EpisodeField, TvField};
 use super::SqlLibrary;
+use super::search::{self, Document};
+use super::person::insert_person;
+use super::production::{insert_company, insert_code, link};
+use super::overrides::apply_overrides;
+use crate::library::local::LOCAL_ID_BASE;
+use crate::library::overrides::Editable;
 use super::generate_sql;
 use super::parse_concat;
 
 
 impl SqlLibrary{
-    pub fn create_tv(&self ,tv: &rustmdb::model::Tv) -> Result<(Vec<u64>, Vec<String>), Error>{
+    pub fn create_tv(&self ,tv: &rustmdb::model::Tv, persons: &[rustmdb::model::Person]) -> Result<Vec<String>, Error>{
 
-        let mut m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_mut().unwrap();
+        let mut conn = self.writer()?;
         let tx = conn.transaction()?;
 
-        let mut person_ids = Vec::new();
         let mut rsc_path = Vec::new();
         // println!("adding tv {:?}", tv);
         tx.execute(
-            "INSERT OR REPLACE INTO Tvs (
+            "INSERT INTO Tvs (
                 id,
                 original_title,
                 original_language,
@@ -45,7 +53,24 @@ impl SqlLibrary{
                 number_of_episodes,
                 number_of_seasons,
                 episode_run_time,
-                updated) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, datetime('now'))",
+                updated) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, datetime('now'))
+            ON CONFLICT(id) DO UPDATE SET
+                original_title = excluded.original_title,
+                original_language = excluded.original_language,
+                title = excluded.title,
+                release_date = excluded.release_date,
+                overview = excluded.overview,
+                popularity = excluded.popularity,
+                poster_path = excluded.poster_path,
+                backdrop_path = excluded.backdrop_path,
+                status = excluded.status,
+                vote_average = excluded.vote_average,
+                vote_count = excluded.vote_count,
+                in_production = excluded.in_production,
+                number_of_episodes = excluded.number_of_episodes,
+                number_of_seasons = excluded.number_of_seasons,
+                episode_run_time = excluded.episode_run_time,
+                updated = excluded.updated",
 
             &[
             &tv.id.to_string(),
@@ -66,10 +91,16 @@ impl SqlLibrary{
             &tv.episode_run_time.get(0).unwrap_or(&0).to_string()],
         )?;
 
+        // rewritten below, so a refresh drops what TMDB no longer lists
+        for table in ["TvGenreLinks", "TvCasts", "TvCrews", "TvCreators", "TvTrailers", "TvKeywordLinks",
+                        "TvCompanyLinks", "TvNetworkLinks", "TvCountryLinks", "TvLanguageLinks"]{
+            tx.execute(&format!("DELETE FROM {} WHERE tv_id = ?1", table), [tv.id])?;
+        }
+
         for season in &tv.seasons{
             // println!("season {}", season.season_number);
             tx.execute(
-                "INSERT OR REPLACE INTO Seasons (
+                "INSERT INTO Seasons (
                     id,
                     tv_id,
                     season_number,
@@ -78,7 +109,16 @@ impl SqlLibrary{
                     overview,
                     poster_path,
                     release_date,
-                    updated) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, datetime('now'))",
+                    updated) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, datetime('now'))
+                ON CONFLICT(id) DO UPDATE SET
+                    tv_id = excluded.tv_id,
+                    season_number = excluded.season_number,
+                    episode_count = excluded.episode_count,
+                    title = excluded.title,
+                    overview = excluded.overview,
+                    poster_path = excluded.poster_path,
+                    release_date = excluded.release_date,
+                    updated = excluded.updated",
     
                 &[
                 &season.id.to_string(),
@@ -94,6 +134,8 @@ impl SqlLibrary{
             if let Some(poster_path) = &season.poster_path{
                 rsc_path.push(poster_path.clone())
             }
+
+            apply_overrides(&tx, Editable::Season, season.id)?;
         }
 
         if let Some(backdrop_path) = &tv.backdrop_path{
@@ -106,9 +148,11 @@ impl SqlLibrary{
 
         for genre in &tv.genres{
             tx.execute(
-                "INSERT OR REPLACE INTO TvGenres (
+                "INSERT INTO TvGenres (
                     id,
-                    name) values (?1, ?2)",
+                    name) values (?1, ?2)
+                ON CONFLICT(id) DO UPDATE SET
+                    name = excluded.name",
     
                 &[
                 &genre.id.to_string(),
@@ -140,12 +184,10 @@ impl SqlLibrary{
                 &cast.character.as_ref().unwrap_or(&"".to_string()), 
                 &cast.order.to_string()],
             )?;
-
-            person_ids.push(cast.id)
         }
 
         for crew in &tv.credits.crew{
-            if !(crew.job == "Screenplay" ||  crew.job == "Director" || crew.job == "Producer"){
+            if !crew.is_kept(){
                 continue
             }
     
@@ -160,25 +202,15 @@ impl SqlLibrary{
                 &tv.id.to_string(), 
                 &crew.job.to_string()],
             )?;
-
-            person_ids.push(crew.id)
         }
 
-        for crew in &tv.created_by{
-    
+        for creator in &tv.created_by{
             tx.execute(
-                "INSERT OR REPLACE INTO TvCrews (
+                "INSERT OR IGNORE INTO TvCreators (
                     person_id,
-                    tv_id,
-                    job) values (?1, ?2, ?3)",
-    
-                &[
-                &crew.id.to_string(),
-                &tv.id.to_string(), 
-                "Creator"],
+                    tv_id) values (?1, ?2)",
+                [creator.id, tv.id],
             )?;
-
-            person_ids.push(crew.id)
         }
 
         for video in &tv.videos.results{
@@ -201,43 +233,80 @@ impl SqlLibrary{
         for keyword in &tv.keywords.results{
 
             tx.execute(
-                "INSERT OR REPLACE INTO TvKeywordLinks (
-                    keyword_id,
-                    tv_id) values (?1, ?2)",
+                "INSERT OR IGNORE INTO Keywords (
+                    id,
+                    name) values (?1, ?2)",
     
                 &[
                 &keyword.id.to_string(),
-                &tv.id.to_string()],
+                &keyword.name],
             )?;
     
             tx.execute(
-                "INSERT OR IGNORE INTO Keywords (
-                    id,
-                    name) values (?1, ?2)",
+                "INSERT OR REPLACE INTO TvKeywordLinks (
+                    keyword_id,
+                    tv_id) values (?1, ?2)",
     
                 &[
                 &keyword.id.to_string(),
-                &keyword.name],
+                &tv.id.to_string()],
             )?;
         }
 
+        for company in &tv.production_companies{
+            insert_company(&tx, "Companies", company.id, &company.name, &company.logo_path, &company.origin_country)?;
+            link(&tx, "TvCompanyLinks", "tv_id", tv.id, "company_id", &company.id)?;
+            rsc_path.extend(company.logo_path.clone());
+        }
+
+        for network in &tv.networks{
+            insert_company(&tx, "Networks", network.id, &network.name, &network.logo_path, &network.origin_country)?;
+            link(&tx, "TvNetworkLinks", "tv_id", tv.id, "network_id", &network.id)?;
+            rsc_path.extend(network.logo_path.clone());
+        }
+
+        // origin countries and languages only come as codes
+        for country in &tv.production_countries{
+            insert_code(&tx, "Countries", &country.iso_3166_1, Some(&country.name))?;
+            link(&tx, "TvCountryLinks", "tv_id", tv.id, "country_code", &country.iso_3166_1)?;
+        }
+        for code in &tv.origin_country{
+            insert_code(&tx, "Countries", code, None)?;
+            link(&tx, "TvCountryLinks", "tv_id", tv.id, "country_code", code)?;
+        }
+
+        for language in &tv.spoken_languages{
+            insert_code(&tx, "Languages", &language.iso_639_1, Some(&language.name))?;
+            link(&tx, "TvLanguageLinks", "tv_id", tv.id, "language_code", &language.iso_639_1)?;
+        }
+        for code in &tv.languages{
+            insert_code(&tx, "Languages", code, None)?;
+            link(&tx, "TvLanguageLinks", "tv_id", tv.id, "language_code", code)?;
+        }
+
+        for person in persons{
+            rsc_path.extend(insert_person(&tx, person)?);
+        }
+
+        apply_overrides(&tx, Editable::Tv, tv.id)?;
+
+        search::index(&tx, Document::Tv, tv.id)?;
+
         tx.commit()?;
 
-        Ok((person_ids, rsc_path))
+        Ok(rsc_path)
     }
 
-    pub fn create_episode(&self, tv_id: u64, episode: &rustmdb::model::TvEpisode) -> Result<(Vec<u64>, Vec<String>), Error>{
+    pub fn create_episode(&self, tv_id: u64, episode: &rustmdb::model::TvEpisode, persons: &[rustmdb::model::Person]) -> Result<Vec<String>, Error>{
         let season_id = self.get_season_id(tv_id, episode.season_number).unwrap().unwrap();
         
-        let mut m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_mut().unwrap();
+        let mut conn = self.writer()?;
         let tx = conn.transaction()?;
 
-        let mut person_ids = Vec::new();
-        let rsc_path = Vec::new();
+        let mut rsc_path = Vec::new();
         
         tx.execute(
-            "INSERT OR REPLACE INTO Episodes (
+            "INSERT INTO Episodes (
                 id,
                 season_id,
                 tv_id,
@@ -248,20 +317,39 @@ impl SqlLibrary{
                 overview,
                 vote_average,
                 vote_count,
-                updated) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, datetime('now'))",
-
-            &[
-            &episode.id.to_string(),
-            &season_id.to_string(),
-            &tv_id.to_string(),
-            &episode.season_number.to_string(),
-            &episode.episode_number.to_string(),
-            &episode.air_date,
-            &episode.name,
-            &episode.overview.as_ref().unwrap_or(&"".to_string()),
-            &episode.vote_average.to_string(),
-            &episode.vote_count.to_string()],
+                still_path,
+                updated) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, datetime('now'))
+            ON CONFLICT(id) DO UPDATE SET
+                season_id = excluded.season_id,
+                tv_id = excluded.tv_id,
+                season_number = excluded.season_number,
+                episode_number = excluded.episode_number,
+                release_date = excluded.release_date,
+                title = excluded.title,
+                overview = excluded.overview,
+                vote_average = excluded.vote_average,
+                vote_count = excluded.vote_count,
+                still_path = excluded.still_path,
+                updated = excluded.updated",
+
+            params![
+            episode.id,
+            season_id,
+            tv_id,
+            episode.season_number,
+            episode.episode_number,
+            episode.air_date,
+            episode.name,
+            episode.overview.as_deref().unwrap_or_default(),
+            episode.vote_average,
+            episode.vote_count,
+            episode.still_path.as_deref().unwrap_or_default()],
         )?;
+        rsc_path.extend(episode.still_path.clone());
+
+        for table in ["EpisodeCasts", "EpisodeCrews"]{
+            tx.execute(&format!("DELETE FROM {} WHERE episode_id = ?1", table), [episode.id])?;
+        }
 
         for cast in &episode.credits.cast{
             tx.execute(
@@ -277,12 +365,22 @@ impl SqlLibrary{
                 &cast.character.as_ref().unwrap_or(&"".to_string()), 
                 &cast.order.to_string()],
             )?;
+        }
 
-            person_ids.push(cast.id)
+        for guest in &episode.guest_stars{
+            tx.execute(
+                "INSERT OR REPLACE INTO EpisodeCasts (
+                    person_id,
+                    episode_id,
+                    character,
+                    ord,
+                    guest) values (?1, ?2, ?3, ?4, 1)",
+                params![guest.id, episode.id, guest.character, guest.order],
+            )?;
         }
 
         for crew in &episode.credits.crew{
-            if !(crew.job == "Screenplay" ||  crew.job == "Director" || crew.job == "Producer"){
+            if !crew.is_kept(){
                 continue
             }
     
@@ -297,19 +395,24 @@ impl SqlLibrary{
                 &episode.id.to_string(), 
                 &crew.job.to_string()],
             )?;
+        }
 
-            person_ids.push(crew.id)
+        for person in persons{
+            rsc_path.extend(insert_person(&tx, person)?);
         }
 
+        apply_overrides(&tx, Editable::Episode, episode.id)?;
+
+        search::index(&tx, Document::Episode, episode.id)?;
+
         tx.commit()?;
 
-        Ok((person_ids, rsc_path))
+        Ok(rsc_path)
     }
 
     pub fn get_season_id(&self, tv_id: u64, season_number: u64) -> Result<Option<u64>, Error> {
         // println!("get season id {} {}", &tv_id, &season_number);
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(
             "SELECT id from Seasons
              WHERE tv_id = ?1 and season_number = ?2",
@@ -322,7 +425,7 @@ impl SqlLibrary{
         Ok(None)
     }
 
-    pub fn get_tv(&self, user: &String, tv_id: u64) -> Result<Option<Tv>, Error>{
+    pub fn get_tv(&self, ctx: &Context, user: &String, tv_id: u64) -> Result<Option<Tv>, Error>{
         //println!("get_tv {:?}", &tv_id);
         let sql = "SELECT
                             TvsView.id, 
@@ -343,18 +446,20 @@ impl SqlLibrary{
                             TvsView.episode_run_time, 
                             TvsView.adding,
                             MIN(COALESCE(EpisodesUserWatched.watched, 0)),
-                            TvsView.updated
+                            TvsView.updated,
+                            TvsView.sort_title
                         FROM TvsView
                         LEFT OUTER JOIN Episodes ON TvsView.id = Episodes.tv_id
                         LEFT OUTER JOIN EpisodesUserWatched ON Episodes.id = EpisodesUserWatched.episode_id AND EpisodesUserWatched.user_name = ?1
-                        WHERE TvsView.id = ?2";
+                        WHERE TvsView.id = ?2
+                        GROUP BY TvsView.id";
 
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
         let rows = stmt.query_map(&[user, &tv_id.to_string()], |row| {
             Ok(Tv{ 
                 user: user.clone(),
+                ctx: ctx.clone(),
                 id: row.get(0)?, 
                 original_title: row.get(1)?, 
                 original_language: row.get(2)?, 
@@ -374,12 +479,18 @@ impl SqlLibrary{
                 adding: row.get(16)?,
                 watched: parse_watched(row.get(17)?),
                 updated: row.get(18)?,
+                sort_title: row.get(19)?,
                 seasons: Vec::new(),
                 cast: Vec::new(),
                 crew: Vec::new(),
+                creators: Vec::new(),
                 trailer: Vec::new(),
                 keyword: Vec::new(),
                 collection: Vec::new(),
+                companies: Vec::new(),
+                networks: Vec::new(),
+                countries: Vec::new(),
+                languages: Vec::new(),
             })
         })?;
 
@@ -390,9 +501,9 @@ impl SqlLibrary{
         Ok(None)
     }
 
-    pub fn get_tvs(&self,user: &String, parameters: &HashMap<String, Option<(String, String)>>, 
-                    order_by: &Option<String>, limit: Option<u64>, offset: Option<u64>) -> Result<Vec<TvResult>, Error>{
-        let (sql, param) = generate_sql("SELECT 
+    fn tvs_query<'a>(user: &'a String, parameters: &'a [Expr<TvField>], order_by: &[Sort<TvField>],
+                    limit: Option<u64>, offset: Option<u64>) -> (String, Vec<&'a dyn ToSql>){
+        generate_sql("SELECT 
                                                     Tvs.id,
                                                     Tvs.title,
                                                     Tvs.release_date,
@@ -409,18 +520,28 @@ impl SqlLibrary{
                                                 LEFT OUTER JOIN TvGenres ON TvGenreLinks.genre_id = TvGenres.id
                                                 LEFT OUTER JOIN TvCasts ON Tvs.id = TvCasts.tv_id
                                                 LEFT OUTER JOIN TvCrews ON Tvs.id = TvCrews.tv_id
+                                                LEFT OUTER JOIN TvCreators ON Tvs.id = TvCreators.tv_id
                                                 LEFT OUTER JOIN TvCollectionLinks ON Tvs.id = TvCollectionLinks.tv_id
+                                                LEFT OUTER JOIN TvCompanyLinks ON Tvs.id = TvCompanyLinks.tv_id
+                                                LEFT OUTER JOIN TvNetworkLinks ON Tvs.id = TvNetworkLinks.tv_id
+                                                LEFT OUTER JOIN TvCountryLinks ON Tvs.id = TvCountryLinks.tv_id
+                                                LEFT OUTER JOIN TvLanguageLinks ON Tvs.id = TvLanguageLinks.tv_id
                                                 LEFT OUTER JOIN EpisodesUserWatched ON Episodes.id = EpisodesUserWatched.episode_id AND EpisodesUserWatched.user_name = ?1
-                                                ", parameters, Some(user), Some("Tvs.id"), order_by, limit, offset);
+                                                ", parameters, Some(user), Some("Tvs.id"), order_by, limit, offset)
+    }
+
+    pub fn get_tvs(&self, ctx: &Context, user: &String, parameters: &[Expr<TvField>], 
+                    order_by: &[Sort<TvField>], limit: Option<u64>, offset: Option<u64>) -> Result<Vec<TvResult>, Error>{
+        let (sql, param) = SqlLibrary::tvs_query(user, parameters, order_by, limit, offset);
 
         //println!("sql: {}", &sql);
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
     
         let rows = stmt.query_map(param.as_slice(), |row| {
             Ok(TvResult{
                 user: user.clone(),
+                ctx: ctx.clone(),
                 id: row.get(0)?,
                 title: row.get(1)?,
                 release_date: row.get(2)?,
@@ -441,9 +562,14 @@ impl SqlLibrary{
         Ok(result)
     }
 
+    pub fn count_tvs(&self, user: &String, parameters: &[Expr<TvField>]) -> Result<u64, Error>{
+        let (sql, param) = SqlLibrary::tvs_query(user, parameters, &[], None, None);
+        self.count(&sql, &param)
+    }
+
     
 
-    pub fn get_seasons(&self, user: &String, tv_id: u64) -> Result<Vec<Season>, Error>{
+    pub fn get_seasons(&self, ctx: &Context, user: &String, tv_id: u64) -> Result<Vec<Season>, Error>{
         let sql = "SELECT
                             SeasonsView.season_number,
                             SeasonsView.episode_count,
@@ -453,8 +579,12 @@ impl SqlLibrary{
                             SeasonsView.release_date,
                             SeasonsView.tv_id,
                             MIN(COALESCE(EpisodesUserWatched.watched, 0)),
-                            SeasonsView.updated
+                            SeasonsView.updated,
+                            SeasonMarkers.intro_start,
+                            SeasonMarkers.intro_end,
+                            SeasonMarkers.credits_start
                         FROM SeasonsView
+                        LEFT OUTER JOIN SeasonMarkers ON SeasonsView.tv_id = SeasonMarkers.tv_id AND SeasonsView.season_number = SeasonMarkers.season_number
                         LEFT OUTER JOIN Episodes ON SeasonsView.tv_id = Episodes.tv_id AND SeasonsView.season_number = Episodes.season_number
                         LEFT OUTER JOIN EpisodeCasts ON Episodes.id = EpisodeCasts.episode_id
                         LEFT OUTER JOIN EpisodeCrews ON Episodes.id = EpisodeCrews.episode_id
@@ -462,13 +592,13 @@ impl SqlLibrary{
                         WHERE SeasonsView.tv_id = ?2
                         GROUP BY SeasonsView.id";
         // println!("sql: {}", &sql);
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
     
         let rows = stmt.query_map(&[user, &tv_id.to_string()], |row| {
             Ok(Season{
                 user: user.clone(),
+                ctx: ctx.clone(),
                 season_number: row.get(0)?,
                 episode_count: row.get(1)?,
                 title: row.get(2)?,
@@ -478,6 +608,11 @@ impl SqlLibrary{
                 tv_id: row.get(6)?,
                 watched: parse_watched(row.get(7)?),
                 updated: row.get(8)?,
+                markers: Markers{
+                    intro_start: row.get(9)?,
+                    intro_end: row.get(10)?,
+                    credits_start: row.get(11)?,
+                },
                 tv: None,
                 episodes: Vec::new(),
             })
@@ -491,9 +626,9 @@ impl SqlLibrary{
         Ok(result)
     }
 
-    pub fn get_episodes(&self, user: &String, parameters: &HashMap<String, Option<(String, String)>>, 
-                order_by: &Option<String>, limit: Option<u64>, offset: Option<u64>) -> Result<Vec<Episode>, Error>{
-        let (sql, param) = generate_sql("SELECT 
+    fn episodes_query<'a>(user: &'a String, parameters: &'a [Expr<EpisodeField>], order_by: &[Sort<EpisodeField>],
+                    limit: Option<u64>, offset: Option<u64>) -> (String, Vec<&'a dyn ToSql>){
+        generate_sql("SELECT 
                                                     Episodes.season_number,
                                                     Episodes.episode_number,
                                                     Episodes.release_date,
@@ -506,22 +641,28 @@ impl SqlLibrary{
                                                     EpisodesUserWatched.watched,
                                                     Episodes.updated,
                                                     Tvs.title,
-                                                    Tvs.poster_path
+                                                    Tvs.poster_path,
+                                                    Episodes.still_path
                                                 FROM Episodes
                                                 INNER JOIN Videos ON Videos.media_id = Episodes.id AND Videos.media_type = 1
                                                 LEFT OUTER JOIN Tvs ON Episodes.tv_id = Tvs.id
                                                 LEFT OUTER JOIN EpisodeCasts ON Episodes.id = EpisodeCasts.episode_id
                                                 LEFT OUTER JOIN EpisodeCrews ON Episodes.id = EpisodeCrews.episode_id
                                                 LEFT OUTER JOIN EpisodesUserWatched ON Episodes.id = EpisodesUserWatched.episode_id AND EpisodesUserWatched.user_name = ?1
-                                                ", parameters, Some(user), Some("Episodes.id"), order_by, limit, offset);
+                                                ", parameters, Some(user), Some("Episodes.id"), order_by, limit, offset)
+    }
+
+    pub fn get_episodes(&self, ctx: &Context, user: &String, parameters: &[Expr<EpisodeField>], 
+                order_by: &[Sort<EpisodeField>], limit: Option<u64>, offset: Option<u64>) -> Result<Vec<Episode>, Error>{
+        let (sql, param) = SqlLibrary::episodes_query(user, parameters, order_by, limit, offset);
         //println!("sql: {}", &sql);
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
     
         let rows = stmt.query_map(param.as_slice(), |row| {
             Ok(Episode{
                 user: user.clone(),
+                ctx: ctx.clone(),
                 season_number: row.get(0)?,
                 episode_number: row.get(1)?,
                 release_date: row.get(2)?,
@@ -535,10 +676,12 @@ impl SqlLibrary{
                 updated: row.get(10)?,
                 tv_title: row.get(11)?,
                 poster_path: row.get(12)?,
+                still_path: row.get::<_, Option<String>>(13)?.unwrap_or_default(),
                 tv: None,
                 season: None,
                 video: Vec::new(),
                 cast: Vec::new(),
+                guest_stars: Vec::new(),
                 crew: Vec::new(),
             })
             
@@ -551,8 +694,13 @@ impl SqlLibrary{
         Ok(result)
     }
 
-    pub fn get_season(&self, user: &String, tv_id: u64, season_number: u64) -> Result<Option<Season>, Error>{
-        for season in self.get_seasons(user, tv_id)? {
+    pub fn count_episodes(&self, user: &String, parameters: &[Expr<EpisodeField>]) -> Result<u64, Error>{
+        let (sql, param) = SqlLibrary::episodes_query(user, parameters, &[], None, None);
+        self.count(&sql, &param)
+    }
+
+    pub fn get_season(&self, ctx: &Context, user: &String, tv_id: u64, season_number: u64) -> Result<Option<Season>, Error>{
+        for season in self.get_seasons(ctx, user, tv_id)? {
             if season.season_number == season_number{
                 return Ok(Some(season))
             }
@@ -561,7 +709,7 @@ impl SqlLibrary{
     }
     
 
-    pub fn get_tv_cast(&self, user: &String, tv_id: u64) -> Result<Vec<Cast>, Error>{
+    pub fn get_tv_cast(&self, ctx: &Context, user: &String, tv_id: u64) -> Result<Vec<Cast>, Error>{
         let sql = "SELECT
                             id,
                             character,
@@ -571,13 +719,13 @@ impl SqlLibrary{
                         FROM TvCastsView
                         WHERE tv_id = ?1";
         //println!("sql: {}", &sql);
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
     
         let rows = stmt.query_map(&[&tv_id.to_string()], |row| {
             Ok(Cast{
                 user: user.clone(),
+                ctx: ctx.clone(),
                 id: row.get(0)?,
                 character: row.get(1)?,
                 ord: row.get(2)?,
@@ -594,7 +742,7 @@ impl SqlLibrary{
         Ok(result)
     }
 
-    pub fn get_tv_crew(&self, user: &String, tv_id: u64) -> Result<Vec<Crew>, Error>{
+    pub fn get_tv_crew(&self, ctx: &Context, user: &String, tv_id: u64) -> Result<Vec<Crew>, Error>{
         let sql = "SELECT
                             id,
                             job,
@@ -603,13 +751,13 @@ impl SqlLibrary{
                         FROM TvCrewsView
                         WHERE tv_id = ?1";
         //println!("sql: {}", &sql);
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
     
         let rows = stmt.query_map(&[&tv_id.to_string()], |row| {
             Ok(Crew{
                 user: user.clone(),
+                ctx: ctx.clone(),
                 id: row.get(0)?,
                 job: row.get(1)?,
                 name: row.get(2)?,
@@ -625,7 +773,37 @@ impl SqlLibrary{
         Ok(result)
     }
 
-    pub fn get_episode_cast(&self, user: &String, episode_id: u64) -> Result<Vec<Cast>, Error>{
+    /// persons credited as creators of the show, as crew with the job "Creator"
+    pub fn get_tv_creators(&self, ctx: &Context, user: &str, tv_id: u64) -> Result<Vec<Crew>, Error>{
+        let sql = "SELECT
+                            id,
+                            name,
+                            profile_path
+                        FROM TvCreatorsView
+                        WHERE tv_id = ?1";
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(sql)?;
+    
+        let rows = stmt.query_map([tv_id], |row| {
+            Ok(Crew{
+                user: user.to_string(),
+                ctx: ctx.clone(),
+                id: row.get(0)?,
+                job: "Creator".to_string(),
+                name: row.get(1)?,
+                profile_path: row.get(2)?,
+            })
+        })?;
+
+        let mut result = Vec::new();
+        for row in rows{
+            result.push(row?);
+        }
+        Ok(result)
+    }
+
+    /// the regular cast, or the guest stars with `guest`
+    pub fn get_episode_cast(&self, ctx: &Context, user: &str, episode_id: u64, guest: bool) -> Result<Vec<Cast>, Error>{
         let sql = "SELECT
                             id,
                             character,
@@ -633,15 +811,16 @@ impl SqlLibrary{
                             name,
                             profile_path
                         FROM EpisodeCastsView
-                        WHERE episode_id = ?1";
+                        WHERE episode_id = ?1 AND guest = ?2
+                        ORDER BY ord";
         //println!("sql: {}", &sql);
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
-        let mut stmt = conn.prepare(&sql)?;
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(sql)?;
     
-        let rows = stmt.query_map(&[&episode_id.to_string()], |row| {
+        let rows = stmt.query_map(params![episode_id, guest], |row| {
             Ok(Cast{
-                user: user.clone(),
+                user: user.to_string(),
+                ctx: ctx.clone(),
                 id: row.get(0)?,
                 character: row.get(1)?,
                 ord: row.get(2)?,
@@ -658,7 +837,7 @@ impl SqlLibrary{
         Ok(result)
     }
 
-    pub fn get_episode_crew(&self, user: &String, episode_id: u64) -> Result<Vec<Crew>, Error>{
+    pub fn get_episode_crew(&self, ctx: &Context, user: &String, episode_id: u64) -> Result<Vec<Crew>, Error>{
         let sql = "SELECT
                             id,
                             job,
@@ -667,13 +846,13 @@ impl SqlLibrary{
                         FROM EpisodeCrewsView
                         WHERE episode_id = ?1";
         //println!("sql: {}", &sql);
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
     
         let rows = stmt.query_map(&[&episode_id.to_string()], |row| {
             Ok(Crew{
                 user: user.clone(),
+                ctx: ctx.clone(),
                 id: row.get(0)?,
                 job: row.get(1)?,
                 name: row.get(2)?,
@@ -696,8 +875,7 @@ impl SqlLibrary{
                         FROM TvTrailers
                         WHERE tv_id = ?";
         // println!("sql: {}", &sql);
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
     
         let rows = stmt.query_map(&[&tv_id.to_string()], |row| {
@@ -723,8 +901,7 @@ impl SqlLibrary{
                         INNER JOIN Keywords ON TvKeywordLinks.keyword_id = Keywords.id
                         WHERE tv_id = ?";
         //println!("sql: {}", &sql);
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
     
         let rows = stmt.query_map(&[&tv_id.to_string()], |row| {
@@ -748,8 +925,7 @@ impl SqlLibrary{
                             id
                         FROM TvGenres";
         //println!("sql: {}", &sql);
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
     
         let rows = stmt.query_map([], |row| {
@@ -768,8 +944,7 @@ impl SqlLibrary{
     }
 
     pub fn set_episode_watched(&self, user: String, movie_id: u64, watched: u64) -> Result< (), Error>{
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.writer()?;
         conn.execute(
             "INSERT OR REPLACE INTO EpisodesUserWatched (
                 watched,
@@ -783,34 +958,69 @@ impl SqlLibrary{
         Ok(())
     }
 
+    pub fn set_season_markers(&self, tv_id: u64, season_number: u64, markers: &Markers) -> Result<(), Error>{
+        let conn = self.writer()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO SeasonMarkers (
+                tv_id,
+                season_number,
+                intro_start,
+                intro_end,
+                credits_start) values (?1, ?2, ?3, ?4, ?5)",
+            params![
+                tv_id,
+                season_number,
+                markers.intro_start,
+                markers.intro_end,
+                markers.credits_start],
+        )?;
+        Ok(())
+    }
+
+    /// tvs whose metadata was written more than `days` days ago
+    pub fn stale_tvs(&self, days: u64) -> Result<Vec<u64>, Error>{
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare("SELECT id FROM Tvs
+                                        WHERE (updated IS NULL OR updated < datetime('now', '-' || ?1 || ' days')) AND id < ?2
+                                        ORDER BY updated")?;
+        let rows = stmt.query_map([days, LOCAL_ID_BASE], |row| row.get(0))?;
+
+        let mut result = Vec::new();
+        for row in rows{
+            result.push(row?);
+        }
+        Ok(result)
+    }
+
+    /// (tv_id, season_number, episode_number) of episodes written more than `days` days ago
+    pub fn stale_episodes(&self, days: u64) -> Result<Vec<(u64, u64, u64)>, Error>{
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare("SELECT tv_id, season_number, episode_number FROM Episodes
+                                        WHERE (updated IS NULL OR updated < datetime('now', '-' || ?1 || ' days')) AND tv_id < ?2
+                                        ORDER BY updated")?;
+        let rows = stmt.query_map([days, LOCAL_ID_BASE], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+
+        let mut result = Vec::new();
+        for row in rows{
+            result.push(row?);
+        }
+        Ok(result)
+    }
+
     pub fn delete_tv(&self, tv_id: u64) -> Result<(), Error>{
-        let mut m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_mut().unwrap();
+        let mut conn = self.writer()?;
         let tx = conn.transaction()?;
 
-        tx.execute("DELETE FROM Tvs
-                        WHERE id=?1", &[&tv_id.to_string()])?;
-        
-        tx.execute("DELETE FROM TvGenreLinks
-                        WHERE tv_id=?1", &[&tv_id.to_string()])?;
-        
-        tx.execute("DELETE FROM TvCollectionLinks
-                        WHERE tv_id=?1", &[&tv_id.to_string()])?;
+        search::unindex_episodes(&tx, tv_id)?;
 
-        tx.execute("DELETE FROM TvKeywordLinks
-                        WHERE tv_id=?1", &[&tv_id.to_string()])?;
+        tx.execute("DELETE FROM Overrides WHERE (kind = 'tv' AND media_id = ?1)
+                        OR (kind = 'season' AND media_id IN (SELECT id FROM Seasons WHERE tv_id = ?1))
+                        OR (kind = 'episode' AND media_id IN (SELECT id FROM Episodes WHERE tv_id = ?1))", [tv_id])?;
 
-        tx.execute("DELETE FROM TvTrailers
-                        WHERE tv_id=?1", &[&tv_id.to_string()])?;
+        tx.execute("DELETE FROM Tvs
+                        WHERE id=?1", &[&tv_id.to_string()])?;
 
-        tx.execute("DELETE FROM TvCasts
-                        WHERE tv_id=?1", &[&tv_id.to_string()])?;
-
-        tx.execute("DELETE FROM TvCrews
-                        WHERE tv_id=?1", &[&tv_id.to_string()])?;
-        
-        tx.execute("DELETE FROM Seasons
-                        WHERE tv_id=?1", &[&tv_id.to_string()])?;
+        search::unindex(&tx, Document::Tv, tv_id)?;
 
         tx.commit()?;
         
@@ -818,21 +1028,14 @@ impl SqlLibrary{
     }
 
     pub fn delete_episode(&self, episode_id: u64) -> Result<(), Error>{
-        let mut m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_mut().unwrap();
+        let mut conn = self.writer()?;
         let tx = conn.transaction()?;
 
+        tx.execute("DELETE FROM Overrides WHERE kind = 'episode' AND media_id = ?1", [episode_id])?;
         tx.execute("DELETE FROM Episodes
                         WHERE id=?1", &[&episode_id.to_string()])?;
-        
-        tx.execute("DELETE FROM EpisodeCasts
-                        WHERE episode_id=?1", &[&episode_id.to_string()])?;
-        
-        tx.execute("DELETE FROM EpisodeCrews
-                        WHERE episode_id=?1", &[&episode_id.to_string()])?;
 
-        tx.execute("DELETE FROM EpisodesUserWatched
-                        WHERE episode_id=?1", &[&episode_id.to_string()])?;
+        search::unindex(&tx, Document::Episode, episode_id)?;
 
         tx.commit()?;
         
@@ -1,15 +1,18 @@
-use std::collections::HashMap;
+use rusqlite::{params, ToSql};
 use super::{Error, generate_sql};
 
+use crate::library::Context;
+use crate::library::filter::{Expr, Sort};
+use crate::library::video::VideoField;
 use super::{SqlLibrary, parse_concat};
 
+use crate::library::chapter::{Chapter, Markers};
 use crate::library::video::{MediaInfo, Video, VideoResult, EpisodeMinimal, MovieMinimal};
 
 impl SqlLibrary{
 
     pub fn create_video(&self, video: Video) -> Result<u64, Error>{
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.writer()?;
         conn.execute(
             "INSERT INTO Videos (
                 path,
@@ -50,10 +53,21 @@ impl SqlLibrary{
             )?;
         }
 
+        for (ord, chapter) in video.chapters.iter().enumerate(){
+            conn.execute(
+                "INSERT OR REPLACE INTO Chapters (
+                    video_id,
+                    ord,
+                    start,
+                    title) values (?1, ?2, ?3, ?4)",
+                &[&video_id.to_string(), &ord.to_string(), &chapter.start.to_string(), &chapter.title],
+            )?;
+        }
+
         Ok(video_id)
     }
 
-    pub fn get_video(&self,user: &String, video_id: u64) -> Result<Option<Video>, Error>{
+    pub fn get_video(&self, ctx: &Context, user: &String, video_id: u64) -> Result<Option<Video>, Error>{
         let sql = "SELECT
                             id,
                             path,
@@ -73,12 +87,12 @@ impl SqlLibrary{
                         FROM VideosView
                         LEFT OUTER JOIN WatchTimes ON VideosView.id = WatchTimes.video_id AND WatchTimes.user_name = ?1
                         WHERE id = ?2";
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
-        let rows = stmt.query_map(&[user, &video_id.to_string()], |row| {
+        let mut rows = stmt.query_map(&[user, &video_id.to_string()], |row| {
             Ok(Video{
                 user: user.clone(),
+                ctx: ctx.clone(),
                 id: row.get(0)?,
                 path:row.get(1)?,
                 media_type: row.get(2)?,
@@ -94,19 +108,97 @@ impl SqlLibrary{
                 audios: parse_concat(row.get(12)?).unwrap_or_default(),
                 watch_time: row.get(13)?,
                 last_watch: row.get(14)?,
+                chapters: Vec::new(),
+                markers: Markers::default(),
             })
         })?;
 
+        let mut video = match rows.next(){
+            Some(row) => row?,
+            None => return Ok(None),
+        };
+        drop(rows);
+        drop(stmt);
+        drop(conn);
+
+        video.chapters = self.get_video_chapters(video.id)?;
+        video.markers = self.get_video_markers(video.id)?;
+
+        Ok(Some(video))
+    }
+
+    pub fn get_video_chapters(&self, video_id: u64) -> Result<Vec<Chapter>, Error>{
+        let sql = "SELECT
+                            start,
+                            title
+                        FROM Chapters
+                        WHERE video_id = ?1
+                        ORDER BY ord";
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(&sql)?;
+
+        let rows = stmt.query_map(&[&video_id.to_string()], |row| {
+            Ok(Chapter{
+                start: row.get(0)?,
+                title: row.get(1)?,
+            })
+        })?;
+
+        let mut result = Vec::new();
         for row in rows{
-            return Ok(Some(row?));
+            result.push(row?);
+        }
+        Ok(result)
+    }
+
+    /// markers of the video itself, falling back on the season markers for episodes
+    pub fn get_video_markers(&self, video_id: u64) -> Result<Markers, Error>{
+        let sql = "SELECT
+                            COALESCE(VideoMarkers.intro_start, SeasonMarkers.intro_start),
+                            COALESCE(VideoMarkers.intro_end, SeasonMarkers.intro_end),
+                            COALESCE(VideoMarkers.credits_start, SeasonMarkers.credits_start)
+                        FROM Videos
+                        LEFT OUTER JOIN VideoMarkers ON Videos.id = VideoMarkers.video_id
+                        LEFT OUTER JOIN Episodes ON Videos.media_type = 1 AND Videos.media_id = Episodes.id
+                        LEFT OUTER JOIN SeasonMarkers ON Episodes.tv_id = SeasonMarkers.tv_id AND Episodes.season_number = SeasonMarkers.season_number
+                        WHERE Videos.id = ?1";
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(&sql)?;
+
+        let mut rows = stmt.query_map(&[&video_id.to_string()], |row| {
+            Ok(Markers{
+                intro_start: row.get(0)?,
+                intro_end: row.get(1)?,
+                credits_start: row.get(2)?,
+            })
+        })?;
+
+        match rows.next(){
+            Some(row) => Ok(row?),
+            None => Ok(Markers::default()),
         }
+    }
 
-        Ok(None)
+    pub fn set_video_markers(&self, video_id: u64, markers: &Markers) -> Result<(), Error>{
+        let conn = self.writer()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO VideoMarkers (
+                video_id,
+                intro_start,
+                intro_end,
+                credits_start) values (?1, ?2, ?3, ?4)",
+            params![
+                video_id,
+                markers.intro_start,
+                markers.intro_end,
+                markers.credits_start],
+        )?;
+        Ok(())
     }
 
-    pub fn get_videos(&self, user: &String, parameters: &HashMap<String, Option<(String, String)>>,
-            order_by: &Option<String>, limit: Option<u64>, offset: Option<u64>) -> Result<Vec<VideoResult>, Error>{
-        let (sql, param) = generate_sql("SELECT 
+    fn videos_query<'a>(user: &'a String, parameters: &'a [Expr<VideoField>], order_by: &[Sort<VideoField>],
+                    limit: Option<u64>, offset: Option<u64>) -> (String, Vec<&'a dyn ToSql>){
+        generate_sql("SELECT 
                                 id, 
                                 path, 
                                 media_type, 
@@ -126,11 +218,14 @@ impl SqlLibrary{
                                 t_id,
                                 WatchTimes.last_watch as last_watch
                             FROM VideosView
-                            LEFT OUTER JOIN WatchTimes ON VideosView.id = WatchTimes.video_id AND WatchTimes.user_name = ?1", 
-                            &parameters, Some(user), Some("VideosView.id"), order_by, limit, offset);
+                            LEFT OUTER JOIN WatchTimes ON VideosView.id = WatchTimes.video_id AND WatchTimes.user_name = ?1", parameters, Some(user), Some("VideosView.id"), order_by, limit, offset)
+    }
+
+    pub fn get_videos(&self, ctx: &Context, user: &String, parameters: &[Expr<VideoField>],
+            order_by: &[Sort<VideoField>], limit: Option<u64>, offset: Option<u64>) -> Result<Vec<VideoResult>, Error>{
+        let (sql, param) = SqlLibrary::videos_query(user, parameters, order_by, limit, offset);
         //println!("sql: {}", &sql);
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare(&sql)?;
     
         let rows = stmt.query_map(param.as_slice(), |row| {
@@ -154,6 +249,7 @@ impl SqlLibrary{
             };
             Ok(VideoResult{
                 user: user.clone(),
+                ctx: ctx.clone(),
                 id: row.get(0)?,
                 path: row.get(1)?,
                 media_type: row.get(2)?,
@@ -174,9 +270,13 @@ impl SqlLibrary{
         Ok(result)
     }
 
+    pub fn count_videos(&self, user: &String, parameters: &[Expr<VideoField>]) -> Result<u64, Error>{
+        let (sql, param) = SqlLibrary::videos_query(user, parameters, &[], None, None);
+        self.count(&sql, &param)
+    }
+
     pub fn edit_video_media_id(&self, video_id: u64, media_id: u64) -> Result<(), Error>{
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.writer()?;
         conn.execute(
             "UPDATE Videos SET media_id = ?1 WHERE id = ?2",
             &[
@@ -187,8 +287,7 @@ impl SqlLibrary{
     }
 
     pub fn edit_video_path(&self, video_id: u64, path: &str) -> Result<(), Error>{
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.writer()?;
         conn.execute(
             "UPDATE Videos SET path = ?1 WHERE id = ?2",
             &[
@@ -199,8 +298,7 @@ impl SqlLibrary{
     }
 
     pub fn set_watch_time(&self, user: String, video_id: u64, time: u64) -> Result< (), Error>{
-        let m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_ref().unwrap();
+        let conn = self.writer()?;
         conn.execute(
             "INSERT OR REPLACE INTO WatchTimes (
                 user_name,
@@ -217,24 +315,9 @@ impl SqlLibrary{
     }
 
     pub fn delete_video(&self, video_id: u64) -> Result<(), Error>{
-        let mut m_conn = self.conn.lock().unwrap();
-        let conn = m_conn.as_mut().unwrap();
-        let tx = conn.transaction()?;
-
-        tx.execute("DELETE FROM Videos
+        let conn = self.writer()?;
+        conn.execute("DELETE FROM Videos
                         WHERE id=?1", &[&video_id.to_string()])?;
-        
-        tx.execute("DELETE FROM WatchTimes
-                        WHERE video_id=?1", &[&video_id.to_string()])?;
-        
-        tx.execute("DELETE FROM Audios
-                        WHERE video_id=?1", &[&video_id.to_string()])?;
-
-        tx.execute("DELETE FROM Subtitles
-                        WHERE video_id=?1", &[&video_id.to_string()])?;
-
-        tx.commit()?;
-        
         Ok(())
     }
 }
\ No newline at end of file
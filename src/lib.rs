@@ -1,7 +1,5 @@
 #[macro_use]
 extern crate serde_derive;
-#[macro_use]
-extern crate lazy_static;
 
 mod database;
 mod rustmdb;
@@ -14,7 +12,14 @@ use pyo3::PyErr;
 
 
 use library::Library;
-use library::video::Video;
+use library::video::{Video, VideoField};
+use library::movie::MovieField;
+use library::tv::{TvField, EpisodeField};
+use library::cast::PersonField;
+use library::collection::CollectionField;
+use library::album::AlbumField;
+use library::filter::{Filter, Operator};
+use library::page::Page;
 
 create_exception!(medialibrary, DatabaseError, PyException);
 
@@ -24,7 +29,7 @@ impl std::convert::From<database::Error> for PyErr {
     }
 }
 
-use rustmdb::{set_api_key, set_language, Tmdb};
+use rustmdb::Tmdb;
 
 create_exception!(medialibrary, TmdbError, PyException);
 
@@ -42,20 +47,23 @@ impl std::convert::From<library::Error> for PyErr {
     }
 }
 
-#[pyfunction]
-fn tmdb_init(key: &str, lang: &str)  -> PyResult<()> {
-    set_api_key(key);
-    set_language(lang);
-    Ok(())
-}
-
 #[pymodule]
 fn medialibrary(py: Python, module: &PyModule) -> PyResult<()> {
+    module.add("DatabaseError", py.get_type::<DatabaseError>())?;
     module.add("TmdbError", py.get_type::<TmdbError>())?;
     module.add("LibraryError", py.get_type::<LibraryError>())?;
-    module.add_function(wrap_pyfunction!(tmdb_init, module)?)?;
     module.add_class::<Tmdb>()?;
     module.add_class::<Library>()?;
     module.add_class::<Video>()?;
+    module.add_class::<Operator>()?;
+    module.add_class::<Filter>()?;
+    module.add_class::<Page>()?;
+    module.add_class::<VideoField>()?;
+    module.add_class::<MovieField>()?;
+    module.add_class::<TvField>()?;
+    module.add_class::<EpisodeField>()?;
+    module.add_class::<PersonField>()?;
+    module.add_class::<CollectionField>()?;
+    module.add_class::<AlbumField>()?;
     Ok(())
 }
@@ -0,0 +1,233 @@
+use std::path::Path;
+
+use pyo3::prelude::*;
+
+use super::{Context, Error, ErrorKind};
+use super::page::{self, search_iterator, Batched, Page};
+use super::filter::{search_fields, parse_field, parse_order_by, py_values, Condition, Expr, Filter, Operator, Sort};
+
+use super::local;
+use super::video::VideoResult;
+use super::video::VideoSearch;
+
+/// Media type of videos that are neither movies nor episodes, grouped by folder into albums
+pub const OTHER_MEDIA_TYPE: u8 = 2;
+
+/// folder grouping the video at `path`
+pub fn folder(path: &str) -> String{
+    Path::new(path).parent().map(|parent| parent.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+#[pyclass]
+#[derive(Debug, Serialize, Clone)]
+pub struct Album{
+    pub user: String,
+    #[serde(skip)]
+    pub ctx: Context,
+    #[pyo3(get)]
+    pub id: u64,
+    /// folder of its videos
+    #[pyo3(get)]
+    pub path: String,
+    #[pyo3(get)]
+    pub title: String,
+    #[pyo3(get)]
+    pub date: String,
+    #[pyo3(get)]
+    pub thumbnail_path: String,
+    #[pyo3(get)]
+    pub video_count: u64,
+    #[pyo3(get)]
+    pub adding: String,
+    #[pyo3(get)]
+    pub video: Vec<VideoResult>,
+}
+
+#[pymethods]
+impl Album{
+    pub fn set_videos(&mut self) -> PyResult<()>{
+        self.video = VideoSearch::new(&self.ctx, &self.user).other()?.media_id(self.id)?.results(None, None)?;
+        Ok(())
+    }
+
+    pub fn set_title(&mut self, title: String) -> PyResult<()>{
+        self.title = title;
+        self.save()
+    }
+
+    pub fn set_date(&mut self, date: String) -> PyResult<()>{
+        self.date = date;
+        self.save()
+    }
+
+    /// copy the image file into the resource folder and use it as thumbnail
+    pub fn set_thumbnail(&mut self, thumbnail: String) -> PyResult<()>{
+        self.thumbnail_path = local::import_image(&self.ctx, &thumbnail, &format!("album-{}-thumbnail", self.id))?;
+        self.save()
+    }
+
+    /// remove the album, its videos go back to unknown
+    pub fn delete(&self) -> PyResult<()>{
+        Ok(self.ctx.db.delete_album(self.id)?)
+    }
+
+    pub fn json(&self) -> PyResult<String>{
+        return Ok(serde_json::to_string(self).unwrap())
+    }
+
+    fn __str__(&self) -> PyResult<String>{
+        Ok(format!("{:?}", self))
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("{:?}", self))
+    }
+}
+
+impl Album{
+    fn save(&self) -> PyResult<()>{
+        Ok(self.ctx.db.update_album(self)?)
+    }
+}
+
+search_fields!{ AlbumField {
+    Id => "id" : "Albums.id",
+    Path => "path" : "Albums.path",
+    Title => "title" : "Albums.title",
+    Date => "date" : "Albums.date",
+    Adding => "adding" : "MAX(Videos.adding)",
+}}
+
+#[pyclass]
+#[derive(Debug, Clone)]
+pub struct AlbumSearch{
+   parameters: Vec<Expr<AlbumField>>,
+   user: String,
+   ctx: Context,
+   order_by: Vec<Sort<AlbumField>>,
+}
+
+impl AlbumSearch{
+    pub fn new(ctx: &Context, user: &String) -> AlbumSearch{
+        AlbumSearch{
+            parameters: Vec::new(),
+            user: user.clone(),
+            ctx: ctx.clone(),
+            order_by: Vec::new(),
+        }
+    }
+
+    fn condition(&mut self, field: AlbumField, operator: Operator, values: Vec<String>) -> PyResult<AlbumSearch>{
+        self.push(Expr::Condition(Condition::new(field, operator, values)?))
+    }
+
+    /// adding is the latest video of the album, an aggregate only sorts can use
+    fn push(&mut self, expr: Expr<AlbumField>) -> PyResult<AlbumSearch>{
+        if expr.uses(&AlbumField::Adding){
+            return Err(Error::new(ErrorKind::InvalidFilter, "adding can only sort albums".to_string(), "AlbumSearch.filter").into())
+        }
+        self.parameters.push(expr);
+        Ok(self.clone())
+    }
+}
+
+#[pymethods]
+impl AlbumSearch{
+    pub fn id(&mut self, id: u64) -> PyResult<AlbumSearch>{
+        self.condition(AlbumField::Id, Operator::Eq, vec![id.to_string()])
+    }
+
+    pub fn path(&mut self, path: String) -> PyResult<AlbumSearch>{
+        self.condition(AlbumField::Path, Operator::Eq, vec![path])
+    }
+
+    /// albums in `path` or one of its sub folders
+    pub fn under(&mut self, path: String) -> PyResult<AlbumSearch>{
+        self.condition(AlbumField::Path, Operator::Prefix, vec![path])
+    }
+
+    pub fn filter(&mut self, field: AlbumField, operator: Operator, value: Option<&PyAny>) -> PyResult<AlbumSearch>{
+        self.condition(field, operator, py_values(value)?)
+    }
+
+    pub fn filter_by(&mut self, filter: Filter) -> PyResult<AlbumSearch>{
+        self.push(filter.expr.resolve()?)
+    }
+
+    pub fn find(&mut self, column: &str, operator: &str, value: Option<&PyAny>) -> PyResult<AlbumSearch>{
+        self.filter(parse_field(column)?, Operator::parse(operator)?, value)
+    }
+
+    pub fn sort(&mut self, field: AlbumField, descending: bool) -> PyResult<AlbumSearch>{
+        self.order_by.push(Sort{ field, descending });
+        Ok(self.clone())
+    }
+
+    pub fn order_by(&mut self, order_by: String) -> PyResult<AlbumSearch>{
+        self.order_by = parse_order_by(&order_by)?;
+        Ok(self.clone())
+    }
+
+    pub fn exist(&self) -> PyResult<bool>{
+        Ok(self.results(Some(1), None)?.len() > 0)
+    }
+
+    pub fn count(&self) -> PyResult<u64>{
+        Ok(Python::with_gil(|py| py.allow_threads(|| self.ctx.db.count_albums(&self.parameters)))?)
+    }
+
+    pub fn page(&self, size: u64, number: u64) -> PyResult<Page>{
+        let (limit, offset) = page::bounds(size, number)?;
+        Ok(Page::new(self.results(Some(limit), Some(offset))?, self.count()?, size, number))
+    }
+
+    pub fn json_page(&self, size: u64, number: u64) -> PyResult<String>{
+        let (limit, offset) = page::bounds(size, number)?;
+        let list = self.results(Some(limit), Some(offset))?;
+        Ok(page::json(&list, self.count()?, size, number))
+    }
+
+    pub fn results(&self, limit: Option<u64>, offset: Option<u64>) -> PyResult<Vec<Album>>{
+        Ok(Python::with_gil(|py| py.allow_threads(|| self.ctx.db.get_albums(&self.ctx, &self.user, &self.parameters, &self.order_by, limit, offset)))?)
+    }
+
+    pub fn json_results(&self, limit: Option<u64>, offset: Option<u64>) -> PyResult<String>{
+        let list = self.results(limit, offset)?;
+        Ok(serde_json::to_string(&list).unwrap())
+    }
+
+    pub fn last(&self) -> PyResult<Option<Album>>{
+        Ok(self.results(None, None)?.pop())
+    }
+
+    fn __str__(&self) -> PyResult<String>{
+        Ok(format!("{:?}", self))
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("{:?}", self))
+    }
+}
+
+impl Batched for AlbumSearch{
+    type Item = Album;
+
+    fn sorted(&self) -> bool{
+        !self.order_by.is_empty()
+    }
+
+    fn batch(&self, after: Option<u64>, offset: Option<u64>, size: u64) -> PyResult<Vec<Album>>{
+        let mut search = self.clone();
+        if let Some(id) = after{
+            search.condition(AlbumField::Id, Operator::Gt, vec![id.to_string()])?;
+        }
+        search.order_by.push(Sort{ field: AlbumField::Id, descending: false });
+        search.results(Some(size), offset)
+    }
+
+    fn item_id(item: &Album) -> u64{
+        item.id
+    }
+}
+
+search_iterator!(AlbumIterator, AlbumSearch);
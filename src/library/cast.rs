@@ -1,19 +1,22 @@
-use std::collections::HashMap;
-
 use pyo3::prelude::*;
 
-use crate::database::DATABASE;
+use super::Context;
+use super::page::{self, search_iterator, Batched, Page};
+use super::filter::{search_fields, parse_field, parse_order_by, py_values, Condition, Expr, Filter, Operator, Sort};
 
 use super::movie::MovieResult;
 use super::movie::MovieSearch;
 use super::tv::TvResult;
 use super::tv::TvSearch;
+use super::tv::EpisodeSearch;
 
 #[pyclass]
 #[derive(Debug, Serialize, Clone)]
 pub struct Crew{
     #[pyo3(get)]
     pub user: String,
+    #[serde(skip)]
+    pub ctx: Context,
     #[pyo3(get)]
     pub id: u64,
     #[pyo3(get)]
@@ -27,7 +30,7 @@ pub struct Crew{
 #[pymethods]
 impl Crew{
     pub fn full(&self) -> PyResult<Person>{
-        Ok(DATABASE.get_person(&self.user, self.id)?.unwrap())
+        Ok(self.ctx.db.get_person(&self.ctx, &self.user, self.id)?.unwrap())
     }
 
     fn __str__(&self) -> PyResult<String>{
@@ -44,6 +47,8 @@ impl Crew{
 pub struct Cast{
     #[pyo3(get)]
     pub user: String,
+    #[serde(skip)]
+    pub ctx: Context,
     #[pyo3(get)]
     pub id: u64,
     #[pyo3(get)]
@@ -59,7 +64,7 @@ pub struct Cast{
 #[pymethods]
 impl Cast{
     pub fn full(&self) -> PyResult<Person>{
-        Ok(DATABASE.get_person(&self.user, self.id)?.unwrap())
+        Ok(self.ctx.db.get_person(&self.ctx, &self.user, self.id)?.unwrap())
     }
 
     fn __str__(&self) -> PyResult<String>{
@@ -75,6 +80,8 @@ impl Cast{
 #[derive(Debug, Serialize, Clone)]
 pub struct Person{
     pub user: String,
+    #[serde(skip)]
+    pub ctx: Context,
     #[pyo3(get)]
     pub id: u64,
     #[pyo3(get)]
@@ -96,6 +103,14 @@ pub struct Person{
     #[pyo3(get)]
     pub profile_path: String,
     #[pyo3(get)]
+    pub also_known_as: Vec<String>,
+    #[pyo3(get)]
+    pub imdb_id: String,
+    #[pyo3(get)]
+    pub homepage: String,
+    #[pyo3(get)]
+    pub adult: bool,
+    #[pyo3(get)]
     pub cast_movie: Vec<MovieResult>,
     #[pyo3(get)]
     pub crew_movie: Vec<MovieResult>,
@@ -103,37 +118,49 @@ pub struct Person{
     pub cast_tv: Vec<TvResult>,
     #[pyo3(get)]
     pub crew_tv: Vec<TvResult>,
+    #[pyo3(get)]
+    pub creator_tv: Vec<TvResult>,
 }
 
 #[pymethods]
 impl Person{
     pub fn set_movie(&mut self) -> PyResult<()>{
-        self.cast_movie = MovieSearch::new(&self.user).cast(self.id)?.results(None, None)?;
-        self.crew_movie = MovieSearch::new(&self.user).crew(self.id)?.results(None, None)?;
+        self.cast_movie = MovieSearch::new(&self.ctx, &self.user).cast(self.id)?.results(None, None)?;
+        self.crew_movie = MovieSearch::new(&self.ctx, &self.user).crew(self.id)?.results(None, None)?;
         Ok(())
     }
 
     pub fn set_tv(&mut self) -> PyResult<()>{
-        self.cast_tv = TvSearch::new(&self.user).cast(self.id)?.results(None, None)?;
-        self.crew_tv = TvSearch::new(&self.user).crew(self.id)?.results(None, None)?;
+        self.cast_tv = TvSearch::new(&self.ctx, &self.user).cast(self.id)?.results(None, None)?;
+        self.crew_tv = TvSearch::new(&self.ctx, &self.user).crew(self.id)?.results(None, None)?;
+        self.creator_tv = TvSearch::new(&self.ctx, &self.user).creator(self.id)?.results(None, None)?;
         Ok(())
     }
 
     pub fn delete(&mut self)  -> PyResult<()>{
-        if MovieSearch::new(&self.user).cast(self.id)?.exist()?{
+        if MovieSearch::new(&self.ctx, &self.user).cast(self.id)?.exist()?{
+            return Ok(())
+        }
+        if MovieSearch::new(&self.ctx, &self.user).crew(self.id)?.exist()?{
             return Ok(())
         }
-        if MovieSearch::new(&self.user).crew(self.id)?.exist()?{
+        if TvSearch::new(&self.ctx, &self.user).cast(self.id)?.exist()?{
             return Ok(())
         }
-        if TvSearch::new(&self.user).cast(self.id)?.exist()?{
+        if TvSearch::new(&self.ctx, &self.user).crew(self.id)?.exist()?{
             return Ok(())
         }
-        if TvSearch::new(&self.user).crew(self.id)?.exist()?{
+        if TvSearch::new(&self.ctx, &self.user).creator(self.id)?.exist()?{
+            return Ok(())
+        }
+        if EpisodeSearch::new(&self.ctx, &self.user).cast(self.id)?.exist()?{
+            return Ok(())
+        }
+        if EpisodeSearch::new(&self.ctx, &self.user).crew(self.id)?.exist()?{
             return Ok(())
         }
         
-        DATABASE.delete_person(self.id)?;
+        self.ctx.db.delete_person(self.id)?;
 
         return Ok(())
         
@@ -156,6 +183,8 @@ impl Person{
 #[derive(Debug, Serialize, Clone)]
 pub struct PersonResult{
     pub user: String,
+    #[serde(skip)]
+    pub ctx: Context,
     #[pyo3(get)]
     pub id: u64,
     #[pyo3(get)]
@@ -169,7 +198,7 @@ pub struct PersonResult{
 #[pymethods]
 impl PersonResult{
     pub fn full(&self) -> PyResult<Person>{
-        Ok(DATABASE.get_person(&self.user, self.id)?.unwrap())
+        Ok(self.ctx.db.get_person(&self.ctx, &self.user, self.id)?.unwrap())
     }
 
     fn __str__(&self) -> PyResult<String>{
@@ -181,52 +210,135 @@ impl PersonResult{
     }
 }
 
+search_fields!{ PersonField {
+    Id => "id" : "Persons.id",
+    Name => "name" : "Persons.name",
+    Birthday => "birthday" : "Persons.birthday",
+    Deathday => "deathday" : "Persons.deathday",
+    Popularity => "popularity" : "Persons.popularity",
+    Department => "department" : "Persons.known_for_department",
+    ImdbId => "imdb_id" : "Persons.imdb_id",
+    Adult => "adult" : "Persons.adult",
+    Titles => "titles" : "CAST((SELECT COUNT(*) FROM Movies WHERE Movies.id IN (
+                                SELECT movie_id FROM MovieCasts WHERE person_id = Persons.id
+                                UNION SELECT movie_id FROM MovieCrews WHERE person_id = Persons.id))
+                            + (SELECT COUNT(*) FROM Tvs WHERE Tvs.id IN (
+                                SELECT tv_id FROM TvCasts WHERE person_id = Persons.id
+                                UNION SELECT tv_id FROM TvCrews WHERE person_id = Persons.id
+                                UNION SELECT tv_id FROM TvCreators WHERE person_id = Persons.id
+                                UNION SELECT Episodes.tv_id FROM EpisodeCasts INNER JOIN Episodes ON EpisodeCasts.episode_id = Episodes.id
+                                    WHERE EpisodeCasts.person_id = Persons.id
+                                UNION SELECT Episodes.tv_id FROM EpisodeCrews INNER JOIN Episodes ON EpisodeCrews.episode_id = Episodes.id
+                                    WHERE EpisodeCrews.person_id = Persons.id)) AS INTEGER)",
+}}
+
 #[pyclass]
 #[derive(Debug, Clone)]
 pub struct PersonSearch{
-   parameters: HashMap<String, Option<(String, String)>>,
+   parameters: Vec<Expr<PersonField>>,
    user: String,
-   order_by: Option<String>,
+   ctx: Context,
+   order_by: Vec<Sort<PersonField>>,
 }
 
 impl PersonSearch{
-    pub fn new(user: &String) -> PersonSearch{
+    pub fn new(ctx: &Context, user: &String) -> PersonSearch{
         PersonSearch{
-            parameters: HashMap::new(),
+            parameters: Vec::new(),
             user: user.clone(),
-            order_by: None,
+            ctx: ctx.clone(),
+            order_by: Vec::new(),
         }
     }
+
+    fn condition(&mut self, field: PersonField, operator: Operator, values: Vec<String>) -> PyResult<PersonSearch>{
+        self.parameters.push(Expr::Condition(Condition::new(field, operator, values)?));
+        Ok(self.clone())
+    }
 }
 
 #[pymethods]
 impl PersonSearch{
     pub fn id(&mut self, id: u64) -> PyResult<PersonSearch>{
-        self.find("Persons.id", "=", Some(id.to_string()))
+        self.condition(PersonField::Id, Operator::Eq, vec![id.to_string()])
     }
 
-    pub fn find(&mut self, column: &str, operator: &str, value: Option<String>) -> PyResult<PersonSearch>{
-        if let Some(value) = value {
-            self.parameters.insert(column.to_string(), Some((operator.to_string(), value)));
-        }
-        else{
-            self.parameters.insert(column.to_string(), None);
-        }
+    /// name or alias matching `query` whatever the accents and the word order, close names when nothing does
+    pub fn name(&mut self, query: &str) -> PyResult<PersonSearch>{
+        self.parameters.push(self.ctx.db.person_name_condition(query)?);
         Ok(self.clone())
     }
 
-    pub fn order_by(&mut self, order_by: String) -> PyResult<PersonSearch>{
-        self.order_by = Some(order_by);
+    /// known for this department, Acting, Directing, Writing...
+    pub fn department(&mut self, department: &str) -> PyResult<PersonSearch>{
+        self.condition(PersonField::Department, Operator::Eq, vec![department.to_string()])
+    }
+
+    pub fn alive(&mut self) -> PyResult<PersonSearch>{
+        self.parameters.push(Expr::Or(vec![
+            Expr::Condition(Condition::new(PersonField::Deathday, Operator::IsNull, Vec::new())?),
+            Expr::Condition(Condition::new(PersonField::Deathday, Operator::Eq, vec![String::new()])?),
+        ]));
+        Ok(self.clone())
+    }
+
+    pub fn born_in_year(&mut self, year: u64) -> PyResult<PersonSearch>{
+        self.condition(PersonField::Birthday, Operator::Prefix, vec![format!("{:04}-", year)])
+    }
+
+    /// most credited in the library first, `sort(PersonField.Titles, ...)` or `order_by('titles desc')` otherwise
+    pub fn by_titles(&mut self) -> PyResult<PersonSearch>{
+        self.sort(PersonField::Titles, true)
+    }
+
+    pub fn by_popularity(&mut self) -> PyResult<PersonSearch>{
+        self.sort(PersonField::Popularity, true)
+    }
+
+    pub fn filter(&mut self, field: PersonField, operator: Operator, value: Option<&PyAny>) -> PyResult<PersonSearch>{
+        self.condition(field, operator, py_values(value)?)
+    }
+
+    pub fn filter_by(&mut self, filter: Filter) -> PyResult<PersonSearch>{
+        self.parameters.push(filter.expr.resolve()?);
         Ok(self.clone())
+    }
 
+    pub fn find(&mut self, column: &str, operator: &str, value: Option<&PyAny>) -> PyResult<PersonSearch>{
+        self.filter(parse_field(column)?, Operator::parse(operator)?, value)
+    }
+
+    pub fn sort(&mut self, field: PersonField, descending: bool) -> PyResult<PersonSearch>{
+        self.order_by.push(Sort{ field, descending });
+        Ok(self.clone())
+    }
+
+    pub fn order_by(&mut self, order_by: String) -> PyResult<PersonSearch>{
+        self.order_by = parse_order_by(&order_by)?;
+        Ok(self.clone())
     }
 
     pub fn exist(&self) -> PyResult<bool>{
-        Ok(self.results(None, None)?.len() > 0)
-    } 
+        Ok(self.results(Some(1), None)?.len() > 0)
+    }
+
+    pub fn count(&self) -> PyResult<u64>{
+        Ok(Python::with_gil(|py| py.allow_threads(|| self.ctx.db.count_persons(&self.parameters)))?)
+    }
+
+    pub fn page(&self, size: u64, number: u64) -> PyResult<Page>{
+        let (limit, offset) = page::bounds(size, number)?;
+        Ok(Page::new(self.results(Some(limit), Some(offset))?, self.count()?, size, number))
+    }
+
+    pub fn json_page(&self, size: u64, number: u64) -> PyResult<String>{
+        let (limit, offset) = page::bounds(size, number)?;
+        let list = self.results(Some(limit), Some(offset))?;
+        Ok(page::json(&list, self.count()?, size, number))
+    }
 
     pub fn results(&self, limit: Option<u64>, offset: Option<u64>) -> PyResult<Vec<PersonResult>>{
-        Ok(DATABASE.get_persons(&self.user, &self.parameters, &self.order_by, limit, offset)?)
+        Ok(Python::with_gil(|py| py.allow_threads(|| self.ctx.db.get_persons(&self.ctx, &self.user, &self.parameters, &self.order_by, limit, offset)))?)
     }
 
     pub fn json_results(&self, limit: Option<u64>, offset: Option<u64>) -> PyResult<String>{
@@ -245,4 +357,27 @@ impl PersonSearch{
     fn __repr__(&self) -> PyResult<String> {
         Ok(format!("{:?}", self))
     }
-}
\ No newline at end of file
+}
+
+impl Batched for PersonSearch{
+    type Item = PersonResult;
+
+    fn sorted(&self) -> bool{
+        !self.order_by.is_empty()
+    }
+
+    fn batch(&self, after: Option<u64>, offset: Option<u64>, size: u64) -> PyResult<Vec<PersonResult>>{
+        let mut search = self.clone();
+        if let Some(id) = after{
+            search.condition(PersonField::Id, Operator::Gt, vec![id.to_string()])?;
+        }
+        search.order_by.push(Sort{ field: PersonField::Id, descending: false });
+        search.results(Some(size), offset)
+    }
+
+    fn item_id(item: &PersonResult) -> u64{
+        item.id
+    }
+}
+
+search_iterator!(PersonIterator, PersonSearch);
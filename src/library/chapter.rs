@@ -0,0 +1,43 @@
+use pyo3::prelude::*;
+
+#[pyclass]
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub struct Chapter{
+    #[pyo3(get)]
+    pub start: u64,
+    #[pyo3(get)]
+    pub title: String,
+}
+
+#[pymethods]
+impl Chapter {
+    fn __str__(&self) -> PyResult<String>{
+        Ok(format!("{:?}", self))
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("{:?}", self))
+    }
+}
+
+#[pyclass]
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone, Default)]
+pub struct Markers{
+    #[pyo3(get)]
+    pub intro_start: Option<u64>,
+    #[pyo3(get)]
+    pub intro_end: Option<u64>,
+    #[pyo3(get)]
+    pub credits_start: Option<u64>,
+}
+
+#[pymethods]
+impl Markers {
+    fn __str__(&self) -> PyResult<String>{
+        Ok(format!("{:?}", self))
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("{:?}", self))
+    }
+}
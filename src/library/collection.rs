@@ -1,10 +1,10 @@
-use std::collections::HashMap;
-
 use pyo3::prelude::*;
 
-use crate::database::DATABASE;
+use super::{Context, Error, ErrorKind};
+use super::page::{self, search_iterator, Batched, Page};
+use super::filter::{search_fields, parse_field, parse_order_by, py_values, Condition, Expr, Filter, Operator, Sort};
 
-use super::movie::MovieResult;
+use super::movie::{MovieField, MovieResult};
 use super::movie::MovieSearch;
 use super::tv::TvResult;
 use super::tv::TvSearch;
@@ -14,6 +14,8 @@ use super::tv::TvSearch;
 pub struct Collection{
     #[pyo3(get)]
     pub user: String,
+    #[serde(skip)]
+    pub ctx: Context,
     #[pyo3(get)]
     pub id: u64,
     #[pyo3(get)]
@@ -26,41 +28,91 @@ pub struct Collection{
     pub creation_date: String,
     #[pyo3(get)]
     pub poster_path: String,
+    /// TMDB collection of a franchise, franchises are maintained by the library and read only
+    #[pyo3(get)]
+    pub tmdb_id: Option<u64>,
     #[pyo3(get)]
     pub movie: Vec<MovieResult>,
     #[pyo3(get)]
     pub tv: Vec<TvResult>, 
 }
 
+/// A movie of a franchise
+#[pyclass]
+#[derive(Debug, Serialize, Clone)]
+pub struct FranchisePart{
+    #[pyo3(get)]
+    pub id: u64,
+    #[pyo3(get)]
+    pub title: String,
+    #[pyo3(get)]
+    pub release_date: String,
+    #[pyo3(get)]
+    pub poster_path: String,
+}
+
+#[pymethods]
+impl FranchisePart{
+    fn __str__(&self) -> PyResult<String>{
+        Ok(format!("{:?}", self))
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("{:?}", self))
+    }
+}
+
+impl Collection{
+    fn editable(&self, location: &str) -> PyResult<()>{
+        match self.tmdb_id{
+            Some(tmdb_id) => Err(Error::new(ErrorKind::ReadOnly, format!("collection {} is the franchise {}", self.id, tmdb_id), location).into()),
+            None => Ok(()),
+        }
+    }
+}
+
 #[pymethods]
 impl Collection{
 
     pub fn set_movie(&mut self) -> PyResult<()>{
-        println!("self.id {}", self.id);
-        self.movie = MovieSearch::new(&self.user).collection(self.id)?.results(None, None)?;
-        println!("self.id {:?}", self.movie);
+        let mut search = MovieSearch::new(&self.ctx, &self.user);
+        search.collection(self.id)?;
+        if self.tmdb_id.is_some(){
+            search.sort(MovieField::ReleaseDate, false)?;
+        }
+        self.movie = search.results(None, None)?;
         Ok(())
     }
 
+    /// parts of the franchise without a video in the library, by release date
+    pub fn missing(&self) -> PyResult<Vec<FranchisePart>>{
+        match self.tmdb_id{
+            Some(tmdb_id) => Ok(self.ctx.db.missing_franchise_parts(tmdb_id)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
     pub fn set_tv(&mut self) -> PyResult<()>{
-        self.tv = TvSearch::new(&self.user).collection(self.id)?.results(None, None)?;
+        self.tv = TvSearch::new(&self.ctx, &self.user).collection(self.id)?.results(None, None)?;
         Ok(())
     }
 
     pub fn add_movie(&mut self, movie_id: u64) -> PyResult<()>{
-        let movie = MovieSearch::new(&self.user).id(movie_id)?.last()?.unwrap();
+        self.editable("collection.add_movie")?;
+        let movie = MovieSearch::new(&self.ctx, &self.user).id(movie_id)?.last()?.unwrap();
         if self.poster_path.len() == 0{
             self.poster_path = movie.poster_path
         }
-        Ok(DATABASE.add_movie_collection(self.id, movie_id)?)
+        Ok(self.ctx.db.add_movie_collection(self.id, movie_id)?)
     }
 
     pub fn add_tv(&mut self, tv_id: u64) -> PyResult<()>{
-        let movie = TvSearch::new(&self.user).id(tv_id)?.last()?.unwrap();
+        self.editable("collection.add_tv")?;
+        let movie = TvSearch::new(&self.ctx, &self.user).id(tv_id)?.last()?.unwrap();
         if self.poster_path.len() == 0{
             self.poster_path = movie.poster_path
         }
-        Ok(DATABASE.add_tv_collection(self.id, tv_id)?)
+        Ok(self.ctx.db.add_tv_collection(self.id, tv_id)?)
     }
 
     pub fn edit_description(&mut self, description: String){
@@ -72,11 +124,13 @@ impl Collection{
     }
 
     pub fn save(&self)  -> PyResult<Collection>{
-        Ok(DATABASE.update_collection(&self.user, &self)?)
+        self.editable("collection.save")?;
+        Ok(self.ctx.db.update_collection(&self.ctx, &self.user, &self)?)
     }
 
     pub fn delete(&self) -> PyResult<()>{
-        DATABASE.delete_collection(self.id)?;
+        self.editable("collection.delete")?;
+        self.ctx.db.delete_collection(self.id)?;
         Ok(())
     }
 
@@ -99,6 +153,8 @@ impl Collection{
 pub struct CollectionResult{
     #[pyo3(get)]
     pub user: String,
+    #[serde(skip)]
+    pub ctx: Context,
     #[pyo3(get)]
     pub id: u64,
     #[pyo3(get)]
@@ -109,12 +165,14 @@ pub struct CollectionResult{
     pub creation_date: String,
     #[pyo3(get)]
     pub poster_path: String,
+    #[pyo3(get)]
+    pub tmdb_id: Option<u64>,
 }
 
 #[pymethods]
 impl CollectionResult{
     pub fn full(&self) -> PyResult<Collection>{
-        Ok(DATABASE.get_collection(&self.user, self.id)?.unwrap())
+        Ok(self.ctx.db.get_collection(&self.ctx, &self.user, self.id)?.unwrap())
     }
 
     fn __str__(&self) -> PyResult<String>{
@@ -126,60 +184,108 @@ impl CollectionResult{
     }
 }
 
+search_fields!{ CollectionField {
+    Id => "id" : "Collections.id",
+    Name => "name" : "Collections.name",
+    Creator => "creator" : "Collections.creator",
+    CreationDate => "creation_date" : "Collections.creation_date",
+    Movie => "movie" : "MovieCollectionLinks.movie_id",
+    Tv => "tv" : "TvCollectionLinks.tv_id",
+    Franchise => "franchise" : "Collections.tmdb_id",
+}}
+
 #[pyclass]
 #[derive(Debug, Clone)]
 pub struct CollectionSearch{
-   parameters: HashMap<String, Option<(String, String)>>,
+   parameters: Vec<Expr<CollectionField>>,
    user: String,
-   order_by: Option<String>,
+   ctx: Context,
+   order_by: Vec<Sort<CollectionField>>,
 }
 
 impl CollectionSearch{
-    pub fn new(user: &String) -> CollectionSearch{
+    pub fn new(ctx: &Context, user: &String) -> CollectionSearch{
         CollectionSearch{
-            parameters: HashMap::new(),
+            parameters: Vec::new(),
             user: user.clone(),
-            order_by: None,
+            ctx: ctx.clone(),
+            order_by: Vec::new(),
         }
     }
+
+    fn condition(&mut self, field: CollectionField, operator: Operator, values: Vec<String>) -> PyResult<CollectionSearch>{
+        self.parameters.push(Expr::Condition(Condition::new(field, operator, values)?));
+        Ok(self.clone())
+    }
 }
 
 #[pymethods]
 impl CollectionSearch{
     pub fn movie(&mut self, movie_id: u64) -> PyResult<CollectionSearch>{
-        self.find("MovieCollectionLinks.movie_id", "=", Some(movie_id.to_string()))
+        self.condition(CollectionField::Movie, Operator::Eq, vec![movie_id.to_string()])
     }
 
     pub fn tv(&mut self, tv_id: u64) -> PyResult<CollectionSearch>{
-        self.find("TvCollectionLinks.tv_id", "=", Some(tv_id.to_string()))
+        self.condition(CollectionField::Tv, Operator::Eq, vec![tv_id.to_string()])
     }
 
     pub fn restrict(&mut self) -> PyResult<CollectionSearch>{
-        self.find("Collections.creator", "=", Some(self.user.clone()))
+        self.condition(CollectionField::Creator, Operator::Eq, vec![self.user.clone()])
     }
 
-    pub fn find(&mut self, column: &str, operator: &str, value: Option<String>) -> PyResult<CollectionSearch>{
-        if let Some(value) = value {
-            self.parameters.insert(column.to_string(), Some((operator.to_string(), value)));
-        }
-        else{
-            self.parameters.insert(column.to_string(), None);
-        }
+    /// collections maintained from TMDB collections
+    pub fn franchises(&mut self) -> PyResult<CollectionSearch>{
+        self.condition(CollectionField::Franchise, Operator::IsNotNull, Vec::new())
+    }
+
+    pub fn user_created(&mut self) -> PyResult<CollectionSearch>{
+        self.condition(CollectionField::Franchise, Operator::IsNull, Vec::new())
+    }
+
+    pub fn filter(&mut self, field: CollectionField, operator: Operator, value: Option<&PyAny>) -> PyResult<CollectionSearch>{
+        self.condition(field, operator, py_values(value)?)
+    }
+
+    pub fn filter_by(&mut self, filter: Filter) -> PyResult<CollectionSearch>{
+        self.parameters.push(filter.expr.resolve()?);
         Ok(self.clone())
     }
 
-    pub fn order_by(&mut self, order_by: String) -> PyResult<CollectionSearch>{
-        self.order_by = Some(order_by);
+    pub fn find(&mut self, column: &str, operator: &str, value: Option<&PyAny>) -> PyResult<CollectionSearch>{
+        self.filter(parse_field(column)?, Operator::parse(operator)?, value)
+    }
+
+    pub fn sort(&mut self, field: CollectionField, descending: bool) -> PyResult<CollectionSearch>{
+        self.order_by.push(Sort{ field, descending });
         Ok(self.clone())
+    }
 
+    pub fn order_by(&mut self, order_by: String) -> PyResult<CollectionSearch>{
+        self.order_by = parse_order_by(&order_by)?;
+        Ok(self.clone())
     }
 
     pub fn exist(&self) -> PyResult<bool>{
-        Ok(self.results(None, None)?.len() > 0)
-    } 
+        Ok(self.results(Some(1), None)?.len() > 0)
+    }
+
+    pub fn count(&self) -> PyResult<u64>{
+        Ok(Python::with_gil(|py| py.allow_threads(|| self.ctx.db.count_collections(&self.parameters)))?)
+    }
+
+    pub fn page(&self, size: u64, number: u64) -> PyResult<Page>{
+        let (limit, offset) = page::bounds(size, number)?;
+        Ok(Page::new(self.results(Some(limit), Some(offset))?, self.count()?, size, number))
+    }
+
+    pub fn json_page(&self, size: u64, number: u64) -> PyResult<String>{
+        let (limit, offset) = page::bounds(size, number)?;
+        let list = self.results(Some(limit), Some(offset))?;
+        Ok(page::json(&list, self.count()?, size, number))
+    }
 
     pub fn results(&self, limit: Option<u64>, offset: Option<u64>) -> PyResult<Vec<CollectionResult>>{
-        Ok(DATABASE.get_collections(&self.user, &self.parameters, &self.order_by, limit, offset)?)
+        Ok(Python::with_gil(|py| py.allow_threads(|| self.ctx.db.get_collections(&self.ctx, &self.user, &self.parameters, &self.order_by, limit, offset)))?)
     }
 
     pub fn json_results(&self, limit: Option<u64>, offset: Option<u64>) -> PyResult<String>{
@@ -198,4 +304,27 @@ impl CollectionSearch{
     fn __repr__(&self) -> PyResult<String> {
         Ok(format!("{:?}", self))
     }
-}
\ No newline at end of file
+}
+
+impl Batched for CollectionSearch{
+    type Item = CollectionResult;
+
+    fn sorted(&self) -> bool{
+        !self.order_by.is_empty()
+    }
+
+    fn batch(&self, after: Option<u64>, offset: Option<u64>, size: u64) -> PyResult<Vec<CollectionResult>>{
+        let mut search = self.clone();
+        if let Some(id) = after{
+            search.condition(CollectionField::Id, Operator::Gt, vec![id.to_string()])?;
+        }
+        search.order_by.push(Sort{ field: CollectionField::Id, descending: false });
+        search.results(Some(size), offset)
+    }
+
+    fn item_id(item: &CollectionResult) -> u64{
+        item.id
+    }
+}
+
+search_iterator!(CollectionIterator, CollectionSearch);
@@ -0,0 +1,372 @@
+use pyo3::prelude::*;
+use pyo3::types::{PyList, PyTuple};
+
+use super::{Error, ErrorKind};
+
+/// A column a search can filter or sort on.
+/// Only the sql of a known field ever reaches a query, values are always bound.
+pub trait Field: Copy{
+    fn sql(&self) -> &'static str;
+    /// sql an `ORDER BY` sorts on, the filter sql unless the field declares an aggregate
+    fn sort_sql(&self) -> &'static str;
+    fn from_name(name: &str) -> Option<Self>;
+}
+
+/// Declare the whitelist of columns of a search as a python enum.
+/// Each field is reachable by its name or by the sql column it maps to.
+/// A field can follow its sql with `/ "sort sql"` when sorting needs another expression,
+/// such as an aggregate over the grouped rows that a `WHERE` can't use.
+macro_rules! search_fields {
+    (@sort $sql:literal) => { $sql };
+    (@sort $sql:literal $sort:literal) => { $sort };
+    ($name:ident { $($variant:ident => $key:literal : $sql:literal $(/ $sort:literal)?),* $(,)? }) => {
+        #[pyclass]
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        pub enum $name{
+            $($variant),*
+        }
+
+        impl $crate::library::filter::Field for $name{
+            fn sql(&self) -> &'static str{
+                match self{
+                    $($name::$variant => $sql),*
+                }
+            }
+
+            fn sort_sql(&self) -> &'static str{
+                match self{
+                    $($name::$variant => search_fields!(@sort $sql $($sort)?)),*
+                }
+            }
+
+            fn from_name(name: &str) -> Option<$name>{
+                $(
+                    if name.eq_ignore_ascii_case($key) || name.eq_ignore_ascii_case($sql){
+                        return Some($name::$variant)
+                    }
+                )*
+                None
+            }
+        }
+
+        #[pymethods]
+        impl $name{
+            /// name of the field, as accepted by `Filter` and `find`
+            #[getter]
+            pub fn key(&self) -> &'static str{
+                match self{
+                    $($name::$variant => $key),*
+                }
+            }
+        }
+    };
+}
+
+pub(crate) use search_fields;
+
+#[pyclass]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator{
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Like,
+    NotLike,
+    Prefix,
+    In,
+    NotIn,
+    Between,
+    IsNull,
+    IsNotNull,
+}
+
+impl Operator{
+    pub fn sql(&self) -> &'static str{
+        match self{
+            Operator::Eq => "=",
+            Operator::Ne => "!=",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+            Operator::Like => "LIKE",
+            Operator::NotLike => "NOT LIKE",
+            Operator::Prefix => "LIKE",
+            Operator::In => "IN",
+            Operator::NotIn => "NOT IN",
+            Operator::Between => "BETWEEN",
+            Operator::IsNull => "IS NULL",
+            Operator::IsNotNull => "IS NOT NULL",
+        }
+    }
+
+    pub fn parse(operator: &str) -> Result<Operator, Error>{
+        let operator = operator.split_whitespace().collect::<Vec<&str>>().join(" ").to_lowercase();
+        match operator.as_ref(){
+            "=" | "==" | "is" => Ok(Operator::Eq),
+            "!=" | "<>" | "is not" => Ok(Operator::Ne),
+            "<" => Ok(Operator::Lt),
+            "<=" => Ok(Operator::Le),
+            ">" => Ok(Operator::Gt),
+            ">=" => Ok(Operator::Ge),
+            "like" => Ok(Operator::Like),
+            "not like" => Ok(Operator::NotLike),
+            "prefix" | "starts with" => Ok(Operator::Prefix),
+            "in" => Ok(Operator::In),
+            "not in" => Ok(Operator::NotIn),
+            "between" => Ok(Operator::Between),
+            "is null" => Ok(Operator::IsNull),
+            "is not null" => Ok(Operator::IsNotNull),
+            _ => Err(Error::new(ErrorKind::InvalidFilter, format!("unknown operator {}", operator), "Operator.parse")),
+        }
+    }
+}
+
+/// `field operator values`, the number of values depends on the operator
+#[derive(Debug, Clone)]
+pub struct Condition<F>{
+    pub field: F,
+    pub operator: Operator,
+    pub values: Vec<String>,
+}
+
+impl<F> Condition<F>{
+    /// check the values against the operator,
+    /// `=` and `!=` without value keep their legacy meaning of `IS NULL` and `IS NOT NULL`
+    pub fn new(field: F, operator: Operator, values: Vec<String>) -> Result<Condition<F>, Error>{
+        let operator = match (operator, values.len()){
+            (Operator::Eq, 0) => Operator::IsNull,
+            (Operator::Ne, 0) => Operator::IsNotNull,
+            (operator, _) => operator,
+        };
+        let valid = match operator{
+            Operator::IsNull | Operator::IsNotNull => values.is_empty(),
+            Operator::In | Operator::NotIn => !values.is_empty(),
+            Operator::Between => values.len() == 2,
+            _ => values.len() == 1,
+        };
+        if !valid{
+            return Err(Error::new(ErrorKind::InvalidFilter, format!("{} values given to {:?}", values.len(), operator), "Condition.new"))
+        }
+        let values = match operator{
+            Operator::Prefix => vec![format!("{}%", escape_like(&values[0]))],
+            _ => values,
+        };
+        Ok(Condition{ field, operator, values })
+    }
+}
+
+/// escape the LIKE wildcards, the pattern is matched with `ESCAPE '\'`
+fn escape_like(value: &str) -> String{
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// Boolean tree of conditions.
+/// Built from python with field names, it gets the field type of a search once applied to it.
+#[derive(Debug, Clone)]
+pub enum Expr<F>{
+    Condition(Condition<F>),
+    And(Vec<Expr<F>>),
+    Or(Vec<Expr<F>>),
+    Not(Box<Expr<F>>),
+    /// fixed sql with a `?` bound to each value in order, for conditions no field can express
+    Sql(&'static str, Vec<String>),
+}
+
+impl<F: PartialEq> Expr<F>{
+    /// whether one of the conditions is on `field`
+    pub fn uses(&self, field: &F) -> bool{
+        match self{
+            Expr::Condition(condition) => &condition.field == field,
+            Expr::And(exprs) | Expr::Or(exprs) => exprs.iter().any(|expr| expr.uses(field)),
+            Expr::Not(expr) => expr.uses(field),
+            Expr::Sql(_, _) => false,
+        }
+    }
+}
+
+impl Expr<String>{
+    pub fn resolve<F: Field>(&self) -> Result<Expr<F>, Error>{
+        Ok(match self{
+            Expr::Condition(condition) => Expr::Condition(Condition{
+                field: parse_field(&condition.field)?,
+                operator: condition.operator,
+                values: condition.values.clone(),
+            }),
+            Expr::And(exprs) => Expr::And(exprs.iter().map(|expr| expr.resolve()).collect::<Result<_, _>>()?),
+            Expr::Or(exprs) => Expr::Or(exprs.iter().map(|expr| expr.resolve()).collect::<Result<_, _>>()?),
+            Expr::Not(expr) => Expr::Not(Box::new(expr.resolve()?)),
+            Expr::Sql(sql, values) => Expr::Sql(sql, values.clone()),
+        })
+    }
+}
+
+/// Filter expression usable with the `filter_by` method of every search,
+/// combine them with `&`, `|` and `~` or `Filter.all` and `Filter.any`
+#[pyclass]
+#[derive(Debug, Clone)]
+pub struct Filter{
+    pub expr: Expr<String>,
+}
+
+#[pymethods]
+impl Filter{
+    #[new]
+    pub fn new(field: &PyAny, operator: Operator, value: Option<&PyAny>) -> PyResult<Self>{
+        let field = match field.extract::<String>(){
+            Ok(field) => field,
+            Err(_) => field.getattr("key")?.extract()?,
+        };
+        Ok(Filter{ expr: Expr::Condition(Condition::new(field, operator, py_values(value)?)?) })
+    }
+
+    #[staticmethod]
+    pub fn all(filters: Vec<Filter>) -> Filter{
+        Filter{ expr: Expr::And(filters.into_iter().map(|filter| filter.expr).collect()) }
+    }
+
+    #[staticmethod]
+    pub fn any(filters: Vec<Filter>) -> Filter{
+        Filter{ expr: Expr::Or(filters.into_iter().map(|filter| filter.expr).collect()) }
+    }
+
+    fn __and__(&self, other: Filter) -> Filter{
+        Filter::all(vec![self.clone(), other])
+    }
+
+    fn __or__(&self, other: Filter) -> Filter{
+        Filter::any(vec![self.clone(), other])
+    }
+
+    fn __invert__(&self) -> Filter{
+        Filter{ expr: Expr::Not(Box::new(self.expr.clone())) }
+    }
+
+    fn __str__(&self) -> PyResult<String>{
+        Ok(format!("{:?}", self))
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("{:?}", self))
+    }
+}
+
+/// values of a condition from python: None, a single value or a list of them
+pub fn py_values(value: Option<&PyAny>) -> PyResult<Vec<String>>{
+    let value = match value{
+        Some(value) if !value.is_none() => value,
+        _ => return Ok(Vec::new()),
+    };
+    if value.is_instance_of::<PyList>()? || value.is_instance_of::<PyTuple>()?{
+        return value.iter()?.map(|item| py_value(item?)).collect()
+    }
+    Ok(vec![py_value(value)?])
+}
+
+fn py_value(value: &PyAny) -> PyResult<String>{
+    match value.extract::<String>(){
+        Ok(value) => Ok(value),
+        Err(_) => Ok(value.str()?.to_string()),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sort<F>{
+    pub field: F,
+    pub descending: bool,
+}
+
+pub fn parse_field<F: Field>(name: &str) -> Result<F, Error>{
+    match F::from_name(name.trim()){
+        Some(field) => Ok(field),
+        None => Err(Error::new(ErrorKind::InvalidFilter, format!("unknown column {}", name), "parse_field")),
+    }
+}
+
+/// parse the legacy "column [ASC|DESC], ..." form of order_by
+pub fn parse_order_by<F: Field>(order_by: &str) -> Result<Vec<Sort<F>>, Error>{
+    let mut sorts = Vec::new();
+    for part in order_by.split(','){
+        let tokens: Vec<&str> = part.split_whitespace().collect();
+        let descending = match tokens.get(1).map(|t| t.to_lowercase()).as_deref(){
+            None | Some("asc") => false,
+            Some("desc") => true,
+            Some(direction) => return Err(Error::new(ErrorKind::InvalidFilter, format!("unknown direction {}", direction), "parse_order_by")),
+        };
+        if tokens.is_empty() || tokens.len() > 2{
+            return Err(Error::new(ErrorKind::InvalidFilter, format!("invalid order by {}", part), "parse_order_by"))
+        }
+        sorts.push(Sort{ field: parse_field(tokens[0])?, descending });
+    }
+    Ok(sorts)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::library::movie::MovieField;
+
+    fn values(values: &[&str]) -> Vec<String>{
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    fn invalid<T>(result: Result<T, Error>) -> bool{
+        matches!(result, Err(Error{ kind: ErrorKind::InvalidFilter, .. }))
+    }
+
+    #[test]
+    fn arity_depends_on_the_operator(){
+        assert!(Condition::new(MovieField::Title, Operator::Like, values(&["a"])).is_ok());
+        assert!(invalid(Condition::new(MovieField::Title, Operator::Like, values(&["a", "b"]))));
+        assert!(invalid(Condition::new(MovieField::Title, Operator::Lt, values(&[]))));
+        assert!(Condition::new(MovieField::Id, Operator::In, values(&["1", "2", "3"])).is_ok());
+        assert!(invalid(Condition::new(MovieField::Id, Operator::In, values(&[]))));
+        assert!(invalid(Condition::new(MovieField::Id, Operator::NotIn, values(&[]))));
+        assert!(Condition::new(MovieField::Runtime, Operator::Between, values(&["90", "120"])).is_ok());
+        assert!(invalid(Condition::new(MovieField::Runtime, Operator::Between, values(&["90"]))));
+        assert!(invalid(Condition::new(MovieField::Runtime, Operator::Between, values(&["90", "120", "150"]))));
+        assert!(Condition::new(MovieField::Status, Operator::IsNull, values(&[])).is_ok());
+        assert!(invalid(Condition::new(MovieField::Status, Operator::IsNotNull, values(&["x"]))));
+    }
+
+    #[test]
+    fn legacy_equality_without_value(){
+        let condition = Condition::new(MovieField::Status, Operator::Eq, values(&[])).unwrap();
+        assert_eq!(condition.operator, Operator::IsNull);
+        let condition = Condition::new(MovieField::Status, Operator::Ne, values(&[])).unwrap();
+        assert_eq!(condition.operator, Operator::IsNotNull);
+        let condition = Condition::new(MovieField::Status, Operator::Eq, values(&["Released"])).unwrap();
+        assert_eq!(condition.operator, Operator::Eq);
+    }
+
+    #[test]
+    fn prefix_is_an_escaped_pattern(){
+        let condition = Condition::new(MovieField::Title, Operator::Prefix, values(&["100%_a\\b"])).unwrap();
+        assert_eq!(condition.values, ["100\\%\\_a\\\\b%"]);
+        let condition = Condition::new(MovieField::Title, Operator::Like, values(&["100%"])).unwrap();
+        assert_eq!(condition.values, ["100%"]);
+    }
+
+    #[test]
+    fn fields_resolve_by_key_or_sql(){
+        assert_eq!(parse_field::<MovieField>("release_date").unwrap(), MovieField::ReleaseDate);
+        assert_eq!(parse_field::<MovieField>(" Movies.Title ").unwrap(), MovieField::Title);
+        assert!(invalid(parse_field::<MovieField>("title; DROP TABLE Movies")));
+        let expr = Expr::Not(Box::new(Expr::Condition(Condition::new("unknown".to_string(), Operator::Eq, values(&["1"])).unwrap())));
+        assert!(invalid(expr.resolve::<MovieField>()));
+    }
+
+    #[test]
+    fn order_by_columns_and_directions(){
+        let sorts = parse_order_by::<MovieField>("title, release_date DESC,popularity asc").unwrap();
+        let sorts: Vec<(MovieField, bool)> = sorts.into_iter().map(|sort| (sort.field, sort.descending)).collect();
+        assert_eq!(sorts, [(MovieField::Title, false), (MovieField::ReleaseDate, true), (MovieField::Popularity, false)]);
+        assert!(invalid(parse_order_by::<MovieField>("title sideways")));
+        assert!(invalid(parse_order_by::<MovieField>("title DESC NULLS")));
+        assert!(invalid(parse_order_by::<MovieField>("title,")));
+        assert!(invalid(parse_order_by::<MovieField>("unknown DESC")));
+    }
+}
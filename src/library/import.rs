@@ -0,0 +1,69 @@
+use pyo3::prelude::*;
+
+/// Metadata import of one media, recorded while it runs so a failed one is retried
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Import{
+    Movie(u64),
+    Tv(u64),
+    /// tv id, season number, episode number
+    Episode(u64, u64, u64),
+    Person(u64),
+    /// TMDB collection, the franchise of movies
+    Collection(u64),
+}
+
+impl Import{
+    /// kind, media id, season number, episode number as stored in PendingImports
+    pub fn key(&self) -> (&'static str, u64, u64, u64){
+        match *self{
+            Import::Movie(id) => ("movie", id, 0, 0),
+            Import::Tv(id) => ("tv", id, 0, 0),
+            Import::Episode(tv_id, season, episode) => ("episode", tv_id, season, episode),
+            Import::Person(id) => ("person", id, 0, 0),
+            Import::Collection(id) => ("collection", id, 0, 0),
+        }
+    }
+
+    pub fn from_key(kind: &str, media_id: u64, season_number: u64, episode_number: u64) -> Option<Import>{
+        match kind{
+            "movie" => Some(Import::Movie(media_id)),
+            "tv" => Some(Import::Tv(media_id)),
+            "episode" => Some(Import::Episode(media_id, season_number, episode_number)),
+            "person" => Some(Import::Person(media_id)),
+            "collection" => Some(Import::Collection(media_id)),
+            _ => None,
+        }
+    }
+}
+
+#[pyclass]
+#[derive(Debug, Serialize, Clone)]
+pub struct PendingImport{
+    #[pyo3(get)]
+    pub kind: String,
+    /// movie, tv, person or TMDB collection id
+    #[pyo3(get)]
+    pub media_id: u64,
+    #[pyo3(get)]
+    pub season_number: u64,
+    #[pyo3(get)]
+    pub episode_number: u64,
+    /// last error, None while the import runs or if it was interrupted
+    #[pyo3(get)]
+    pub error: Option<String>,
+    #[pyo3(get)]
+    pub attempts: u64,
+    #[pyo3(get)]
+    pub updated: String,
+}
+
+#[pymethods]
+impl PendingImport{
+    fn __str__(&self) -> PyResult<String>{
+        Ok(format!("{:?}", self))
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("{:?}", self))
+    }
+}
@@ -0,0 +1,67 @@
+use pyo3::prelude::*;
+
+/// Problems found by `Library.check_integrity`, `repaired` tells if they were fixed
+#[pyclass]
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct IntegrityReport{
+    #[pyo3(get)]
+    pub repaired: bool,
+    /// persons without any credit
+    #[pyo3(get)]
+    pub orphan_persons: Vec<u64>,
+    /// keywords without any movie or tv
+    #[pyo3(get)]
+    pub orphan_keywords: Vec<u64>,
+    /// production companies without any movie or tv
+    #[pyo3(get)]
+    pub orphan_companies: Vec<u64>,
+    /// networks without any tv
+    #[pyo3(get)]
+    pub orphan_networks: Vec<u64>,
+    /// episodes whose season row is missing, removed on repair
+    #[pyo3(get)]
+    pub episodes_without_season: Vec<u64>,
+    /// videos linked to a missing movie or episode, unlinked on repair
+    #[pyo3(get)]
+    pub videos_without_media: Vec<u64>,
+    /// (collection_id, movie_id) links where one side is missing
+    #[pyo3(get)]
+    pub movie_collection_links: Vec<(u64, u64)>,
+    /// (collection_id, tv_id) links where one side is missing
+    #[pyo3(get)]
+    pub tv_collection_links: Vec<(u64, u64)>,
+    /// images referenced by the database but missing from the resource folder, those downloaded again on repair are left out
+    #[pyo3(get)]
+    pub missing_images: Vec<String>,
+    /// missing images that could not be downloaded again
+    #[pyo3(get)]
+    pub failed_images: Vec<String>,
+}
+
+#[pymethods]
+impl IntegrityReport{
+    /// true when nothing was found
+    pub fn is_clean(&self) -> bool{
+        self.orphan_persons.is_empty()
+            && self.orphan_keywords.is_empty()
+            && self.orphan_companies.is_empty()
+            && self.orphan_networks.is_empty()
+            && self.episodes_without_season.is_empty()
+            && self.videos_without_media.is_empty()
+            && self.movie_collection_links.is_empty()
+            && self.tv_collection_links.is_empty()
+            && self.missing_images.is_empty()
+    }
+
+    pub fn json(&self) -> PyResult<String>{
+        return Ok(serde_json::to_string(self).unwrap())
+    }
+
+    fn __str__(&self) -> PyResult<String>{
+        Ok(format!("{:?}", self))
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("{:?}", self))
+    }
+}
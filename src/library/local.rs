@@ -0,0 +1,79 @@
+use std::fs;
+use std::path::Path;
+
+use pyo3::prelude::*;
+
+use super::{Context, Error, ErrorKind};
+use super::overrides::Editable;
+
+/// Movies, tvs, seasons and episodes entered by hand get ids from here up, far above TMDB ones
+pub const LOCAL_ID_BASE: u64 = 1 << 48;
+
+pub fn is_local(id: u64) -> bool{
+    id >= LOCAL_ID_BASE
+}
+
+/// Metadata of a movie or tv that TMDB does not know
+#[derive(Debug, Clone, Default)]
+pub struct LocalMedia{
+    pub title: String,
+    pub release_date: String,
+    pub overview: String,
+    /// image files to copy into the resource folder
+    pub poster: Option<String>,
+    pub backdrop: Option<String>,
+    pub genres: Vec<String>,
+}
+
+impl LocalMedia{
+    /// poster and backdrop paths of the media `kind` `id` in the resource folder, empty without image
+    pub fn image_paths(&self, kind: Editable, id: u64) -> (String, String){
+        let path = |source: &Option<String>, name: &str| match source{
+            Some(source) => image_path(source, &format!("{}-{}-{}", kind.name(), id, name)),
+            None => String::new(),
+        };
+        (path(&self.poster, "poster"), path(&self.backdrop, "backdrop"))
+    }
+
+    /// copy the poster and backdrop once the media `kind` `id` is in the database
+    pub fn import_images(&self, ctx: &Context, kind: Editable, id: u64) -> PyResult<()>{
+        let (poster_path, backdrop_path) = self.image_paths(kind, id);
+        if let Some(poster) = &self.poster{
+            copy_image(ctx, poster, &poster_path)?;
+        }
+        if let Some(backdrop) = &self.backdrop{
+            copy_image(ctx, backdrop, &backdrop_path)?;
+        }
+        Ok(())
+    }
+}
+
+/// fail before anything is written when `source` is not an image file that can be copied
+pub fn check_image(source: &str) -> PyResult<()>{
+    if !Path::new(source).is_file(){
+        return Err(Error::new(ErrorKind::NotFound, format!("no image at {}", source), "local.check_image").into())
+    }
+    Ok(())
+}
+
+/// path of the copy of `source` named `name`, relative to the `original` folder
+fn image_path(source: &str, name: &str) -> String{
+    match Path::new(source).extension(){
+        Some(extension) => format!("/local/{}.{}", name, extension.to_string_lossy()),
+        None => format!("/local/{}", name),
+    }
+}
+
+fn copy_image(ctx: &Context, source: &str, rsc_path: &str) -> PyResult<()>{
+    fs::create_dir_all(format!("{}/original/local", ctx.rsc_path))?;
+    fs::copy(source, format!("{}/original{}", ctx.rsc_path, rsc_path))?;
+    Ok(())
+}
+
+/// copy a local image into the resource folder, returns its path relative to the `original` folder
+pub fn import_image(ctx: &Context, source: &str, name: &str) -> PyResult<String>{
+    check_image(source)?;
+    let rsc_path = image_path(source, name);
+    copy_image(ctx, source, &rsc_path)?;
+    Ok(rsc_path)
+}
@@ -1,7 +1,11 @@
 use std::fmt;
+use std::ops::Deref;
+use std::path::Path;
+use std::collections::HashSet;
 use std::sync::{Arc, Mutex};
 
-use crate::database::DATABASE;
+use crate::database::SqlLibrary;
+use crate::rustmdb::{best_movie, best_tv, Client, DEFAULT_LANGUAGE};
 
 use pyo3::prelude::*;
 
@@ -15,6 +19,18 @@ pub mod keyword;
 pub mod trailer;
 pub mod collection;
 pub mod genre;
+pub mod chapter;
+pub mod filter;
+pub mod search;
+pub mod page;
+pub mod integrity;
+pub mod import;
+pub mod overrides;
+pub mod local;
+pub mod album;
+pub mod nfo;
+pub mod provider;
+pub mod production;
 
 use video::Video;
 
@@ -23,102 +39,425 @@ use self::collection::{Collection, CollectionSearch};
 use self::movie::{Movie, MovieSearch};
 use self::tv::{Tv, Season, Episode, TvSearch, EpisodeSearch};
 use self::video::VideoSearch;
+use self::search::SearchResult;
+use self::integrity::IntegrityReport;
+use self::import::{Import, PendingImport};
+use self::overrides::Editable;
+use self::local::{is_local, LocalMedia};
+use self::album::{Album, AlbumSearch, OTHER_MEDIA_TYPE};
+use self::provider::Providers;
 
-lazy_static! {
-    pub static ref RSCPATH: Arc<Mutex<String>> = Arc::new(Mutex::new("".to_string()));
+pub struct LibraryState{
+    pub db: SqlLibrary,
+    pub rsc_path: String,
+    tmdb: Arc<Mutex<Option<Client>>>,
+    providers: Mutex<Providers>,
+}
+
+impl LibraryState{
+    /// tmdb settings of this library, an error when it has none
+    pub fn tmdb(&self) -> PyResult<Client>{
+        provider::configured(&self.tmdb)
+    }
+
+    /// metadata providers, in the order they are asked
+    pub fn providers(&self) -> Providers{
+        self.providers.lock().unwrap().clone()
+    }
+}
+
+/// Shared handle on the state of one Library, held by every search builder and result object
+#[derive(Clone)]
+pub struct Context(Arc<LibraryState>);
+
+impl Deref for Context{
+    type Target = LibraryState;
+
+    fn deref(&self) -> &LibraryState{
+        &self.0
+    }
+}
+
+impl PartialEq for Context{
+    fn eq(&self, other: &Context) -> bool{
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for Context{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Context({})", &self.rsc_path)
+    }
 }
 
 #[pyclass]
 pub struct Library{
+    ctx: Context,
 }
 
 #[pymethods]
 impl Library {
+    /// `providers` are "tmdb" or directories of local metadata, asked in order, TMDB alone by default
     #[new]
-    pub fn new(database_path: &str, rsc_path: String) -> Self {
-        *RSCPATH.lock().unwrap() = rsc_path.to_string();
-        DATABASE.connect(database_path);
-        Library{ }
+    pub fn new(database_path: &str, rsc_path: String, tmdb_key: Option<String>, tmdb_language: Option<String>,
+                providers: Option<Vec<String>>) -> PyResult<Self> {
+        let tmdb = Arc::new(Mutex::new(tmdb_key.map(|api_key| Client{
+            api_key,
+            language: tmdb_language.unwrap_or_else(|| DEFAULT_LANGUAGE.to_string()),
+        })));
+        let providers = Providers::parse(&providers.unwrap_or_else(|| vec!["tmdb".to_string()]), &tmdb)?;
+        Ok(Library{
+            ctx: Context(Arc::new(LibraryState{
+                db: SqlLibrary::open(database_path)?,
+                rsc_path,
+                tmdb,
+                providers: Mutex::new(providers),
+            })),
+        })
+    }
+
+    pub fn tmdb_init(&self, key: String, lang: String){
+        *self.ctx.tmdb.lock().unwrap() = Some(Client{ api_key: key, language: lang });
+    }
+
+    pub fn providers(&self) -> Vec<String>{
+        self.ctx.providers().names()
+    }
+
+    /// replace the provider chain, see `new`
+    pub fn set_providers(&self, providers: Vec<String>) -> PyResult<()>{
+        *self.ctx.providers.lock().unwrap() = Providers::parse(&providers, &self.ctx.tmdb)?;
+        Ok(())
+    }
+
+    /// id of the movie closest to `title` among the results of the providers
+    pub fn search_movie_id(&self, title: &str, year: Option<u64>) -> PyResult<Option<u64>>{
+        let results = self.ctx.providers().search_movie(title, year)?;
+        Ok(best_movie(title, year.unwrap_or_default(), &results))
     }
 
+    pub fn search_tv_id(&self, title: &str) -> PyResult<Option<u64>>{
+        Ok(best_tv(title, &self.ctx.providers().search_tv(title)?))
+    }
+
+    /// media_type is 0 for movies, 1 for episodes and 2 for other videos, those go to the album of their folder.
+    /// Movies and episodes described by Kodi NFO files are linked right away, failures leave them unknown.
     pub fn new_video(&self, user: String,  path: String, media_type: u8) -> PyResult<Video> {
-        Ok(self.video(user.clone(), DATABASE.create_video(Video::from_path(user, path, media_type)?)?)?.unwrap())
+        let mut video = self.video(user.clone(), self.ctx.db.create_video(Video::from_path(&self.ctx, user, path, media_type)?)?)?.unwrap();
+        if media_type == OTHER_MEDIA_TYPE{
+            video.set_album(None)?;
+        }
+        else{
+            // failed TMDB imports wait in pending_imports, the video stays unknown
+            video.import_nfo().ok();
+        }
+        Ok(video)
     }
 
     pub fn videos(&self, user: String) -> VideoSearch{
-        VideoSearch::new(&user)
+        VideoSearch::new(&self.ctx, &user)
     }
     
     pub fn video(&self, user: String, video_id: u64) -> PyResult<Option<Video>>{
-        Ok(DATABASE.get_video(&user, video_id)?)
+        Ok(self.ctx.db.get_video(&self.ctx, &user, video_id)?)
     }
 
     pub fn movies(&self, user: String) -> MovieSearch{
-        MovieSearch::new(&user)
+        MovieSearch::new(&self.ctx, &user)
     }
 
     pub fn movie(&self, user:String, movie_id: u64) -> PyResult<Option<Movie>>{
-        Ok(DATABASE.get_movie(&user, movie_id)?)
+        Ok(self.ctx.db.get_movie(&self.ctx, &user, movie_id)?)
     }
 
     pub fn tvs(&self, user: String) -> TvSearch{
-        TvSearch::new(&user)
+        TvSearch::new(&self.ctx, &user)
     }
 
     pub fn tv(&self, user: String, tv_id: u64) -> PyResult<Option<Tv>>{
-        Ok(DATABASE.get_tv(&user, tv_id)?)
+        Ok(self.ctx.db.get_tv(&self.ctx, &user, tv_id)?)
     }
 
     pub fn tv_season(&self, user: String, tv_id: u64, season_number: u64) -> PyResult<Option<Season>>{
-        Ok(DATABASE.get_season(&user, tv_id, season_number)?)
+        Ok(self.ctx.db.get_season(&self.ctx, &user, tv_id, season_number)?)
     }
 
     pub fn tv_episode(&self, user:String, tv_id: u64, season_number: u64, episode_number: u64) -> PyResult<Option<Episode>>{
-        Ok(EpisodeSearch::new(&user).tv(tv_id)?.season(season_number)?.episode(episode_number)?.last()?)
+        Ok(EpisodeSearch::new(&self.ctx, &user).tv(tv_id)?.season(season_number)?.episode(episode_number)?.last()?)
     }
 
     pub fn tv_episodes(&self, user: String) -> EpisodeSearch{
-        EpisodeSearch::new(&user)
+        EpisodeSearch::new(&self.ctx, &user)
     }
 
     pub fn persons(&self, user: String) -> PersonSearch{
-        PersonSearch::new(&user)
+        PersonSearch::new(&self.ctx, &user)
     }
 
     pub fn person(&self, user: String, person_id: u64) -> PyResult<Option<Person>>{
-        Ok(DATABASE.get_person(&user, person_id)?)
+        Ok(self.ctx.db.get_person(&self.ctx, &user, person_id)?)
     }
 
     pub fn new_collection(&self, user: String, collection_name: String) -> PyResult<Collection>{
-        Ok(DATABASE.create_collection(&user, collection_name)?)
+        Ok(self.ctx.db.create_collection(&self.ctx, &user, collection_name)?)
     }
 
     pub fn collection(&self, user: String, collection_id: u64) -> PyResult<Option<Collection>>{
-        Ok(DATABASE.get_collection(&user, collection_id)?)
+        Ok(self.ctx.db.get_collection(&self.ctx, &user, collection_id)?)
+    }
+
+    pub fn albums(&self, user: String) -> AlbumSearch{
+        AlbumSearch::new(&self.ctx, &user)
+    }
+
+    pub fn album(&self, user: String, album_id: u64) -> PyResult<Option<Album>>{
+        Ok(AlbumSearch::new(&self.ctx, &user).id(album_id)?.last()?)
     }
 
     pub fn collections(&self, user: String) -> CollectionSearch{
-        CollectionSearch::new(&user)
+        CollectionSearch::new(&self.ctx, &user)
+    }
+
+    /// full text search over titles, overviews, names, characters and keywords, best match first
+    pub fn search(&self, user: String, query: String, limit: Option<u64>) -> PyResult<Vec<SearchResult>>{
+        Ok(Python::with_gil(|py| py.allow_threads(|| self.ctx.db.search(&self.ctx, &user, &query, limit.unwrap_or(50))))?)
+    }
+
+    pub fn search_json(&self, user: String, query: String, limit: Option<u64>) -> PyResult<String>{
+        let list = self.search(user, query, limit)?;
+        Ok(serde_json::to_string(&list).unwrap())
+    }
+
+    /// find rows and images left behind by failed imports, fix them if `repair`
+    pub fn check_integrity(&self, repair: bool) -> PyResult<IntegrityReport>{
+        let mut report = Python::with_gil(|py| py.allow_threads(|| self.ctx.db.check_integrity(repair)))?;
+        for path in self.ctx.db.image_paths()?{
+            if Path::new(&format!("{}/original{}", self.ctx.rsc_path, path)).exists(){
+                continue
+            }
+            if repair{
+                if update_db::update_rsc(&self.ctx, &path).is_ok(){
+                    continue
+                }
+                report.failed_images.push(path.clone());
+            }
+            report.missing_images.push(path);
+        }
+        Ok(report)
+    }
+
+    /// imports that failed or were interrupted, oldest first
+    pub fn pending_imports(&self) -> PyResult<Vec<PendingImport>>{
+        Ok(self.ctx.db.pending_imports()?)
+    }
+
+    /// run every pending import again, those failing again stay pending and are returned
+    pub fn retry_imports(&self, user: String) -> PyResult<Vec<PendingImport>>{
+        for pending in self.ctx.db.pending_imports()?{
+            if let Some(import) = Import::from_key(&pending.kind, pending.media_id, pending.season_number, pending.episode_number){
+                let _ = update_db::retry(&self.ctx, &user, &import);
+            }
+        }
+        Ok(self.ctx.db.pending_imports()?)
+    }
+
+    /// refresh every movie, tv and episode written more than `older_than` days ago, returns how many were refreshed.
+    /// Failures stay in `pending_imports`.
+    pub fn refresh_stale(&self, user: String, older_than: u64) -> PyResult<u64>{
+        let mut count = 0;
+        for movie_id in self.ctx.db.stale_movies(older_than)?{
+            if let Some(mut movie) = self.ctx.db.get_movie(&self.ctx, &user, movie_id)?{
+                if movie.refresh().is_ok(){
+                    count += 1;
+                }
+            }
+        }
+        for tv_id in self.ctx.db.stale_tvs(older_than)?{
+            if let Some(mut tv) = self.ctx.db.get_tv(&self.ctx, &user, tv_id)?{
+                if tv.refresh().is_ok(){
+                    count += 1;
+                }
+            }
+        }
+        for (tv_id, season_number, episode_number) in self.ctx.db.stale_episodes(older_than)?{
+            if let Some(mut episode) = EpisodeSearch::new(&self.ctx, &user).tv(tv_id)?.season(season_number)?.episode(episode_number)?.last()?{
+                if episode.refresh().is_ok(){
+                    count += 1;
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    /// fetch every franchise again, new parts and releases show in `Collection.missing`. Returns how many were refreshed.
+    pub fn refresh_franchises(&self) -> PyResult<u64>{
+        let mut count = 0;
+        for tmdb_id in self.ctx.db.franchise_ids()?{
+            if update_db::refresh_franchise(&self.ctx, tmdb_id).is_ok(){
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// refresh the movies, tvs, episodes and persons TMDB lists as changed since `since` (YYYY-MM-DD, or a modifier
+    /// like '-7 days'), by default since the last sync or the last 14 days. Returns how many were refreshed.
+    pub fn sync_changes(&self, user: String, since: Option<String>) -> PyResult<u64>{
+        let since = match since.or(self.ctx.db.last_sync()?){
+            Some(since) => since,
+            None => "-14 days".to_string(),
+        };
+        let since = match self.ctx.db.normalize_date(&since)?{
+            Some(date) => date,
+            None => return Err(Error::new(ErrorKind::InvalidDate, since, "library.sync_changes").into()),
+        };
+        let today = self.ctx.db.today()?;
+
+        let tmdb = self.ctx.tmdb()?;
+        let mut movie_ids = HashSet::new();
+        let mut tv_ids = HashSet::new();
+        let mut person_ids = HashSet::new();
+        let windows = self.ctx.db.sync_windows(&since)?;
+        for (start_date, end_date) in &windows{
+            movie_ids.extend(tmdb.get_movie_changes(start_date, end_date)?);
+            tv_ids.extend(tmdb.get_tv_changes(start_date, end_date)?);
+            person_ids.extend(tmdb.get_person_changes(start_date, end_date)?);
+        }
+
+        let mut count = 0;
+        for movie_id in movie_ids{
+            if let Some(mut movie) = self.ctx.db.get_movie(&self.ctx, &user, movie_id)?{
+                if movie.refresh().is_ok(){
+                    count += 1;
+                }
+            }
+        }
+        for tv_id in tv_ids{
+            if let Some(mut tv) = self.ctx.db.get_tv(&self.ctx, &user, tv_id)?{
+                if tv.refresh().is_ok(){
+                    count += 1;
+                }
+                // seasons come with their tv, episodes only when the changes of their season list them
+                let mut episodes = HashSet::new();
+                for (start_date, end_date) in &windows{
+                    for (season_id, season_number) in tmdb.get_tv_season_changes(tv_id, start_date, end_date)?{
+                        for episode_number in tmdb.get_season_episode_changes(season_id, start_date, end_date)?{
+                            episodes.insert((season_number, episode_number));
+                        }
+                    }
+                }
+                for (season_number, episode_number) in episodes{
+                    let search = EpisodeSearch::new(&self.ctx, &user).tv(tv_id)?.season(season_number)?.episode(episode_number)?.last()?;
+                    if let Some(mut episode) = search{
+                        if episode.refresh().is_ok(){
+                            count += 1;
+                        }
+                    }
+                }
+            }
+        }
+        for person_id in person_ids{
+            if PersonSearch::new(&self.ctx, &user).id(person_id)?.exist()? && update_db::refresh_person(&self.ctx, person_id).is_ok(){
+                count += 1;
+            }
+        }
+
+        // failed refreshes wait in pending_imports, they do not hold the watermark back
+        self.ctx.db.set_last_sync(&today)?;
+        Ok(count)
+    }
+
+    /// add a movie TMDB does not know, returns its id to give to `Video.set_movie`.
+    /// `poster` and `backdrop` are image files copied into the resource folder, `genres` names of existing genres.
+    pub fn new_local_movie(&self, title: String, release_date: Option<String>, overview: Option<String>,
+                            poster: Option<String>, backdrop: Option<String>, genres: Option<Vec<String>>) -> PyResult<u64>{
+        let movie = self.local_media(Editable::Movie, title, release_date, overview, poster, backdrop, genres)?;
+        let id = self.ctx.db.create_local_movie(&movie)?;
+        movie.import_images(&self.ctx, Editable::Movie, id)?;
+        Ok(id)
+    }
+
+    /// add a tv TMDB does not know, its episodes come from `new_local_episode`
+    pub fn new_local_tv(&self, title: String, release_date: Option<String>, overview: Option<String>,
+                            poster: Option<String>, backdrop: Option<String>, genres: Option<Vec<String>>) -> PyResult<u64>{
+        let tv = self.local_media(Editable::Tv, title, release_date, overview, poster, backdrop, genres)?;
+        let id = self.ctx.db.create_local_tv(&tv)?;
+        tv.import_images(&self.ctx, Editable::Tv, id)?;
+        Ok(id)
+    }
+
+    /// add an episode to a local tv, returns its id, `Video.set_tv(tv_id, season_number, episode_number)` links it
+    pub fn new_local_episode(&self, tv_id: u64, season_number: u64, episode_number: u64, title: String,
+                            release_date: Option<String>, overview: Option<String>) -> PyResult<u64>{
+        if !is_local(tv_id){
+            return Err(Error::new(ErrorKind::MediaType, format!("tv {} is not local", tv_id), "library.new_local_episode").into())
+        }
+        if self.ctx.db.get_episode_id(tv_id, season_number, episode_number)?.is_some(){
+            return Err(Error::new(ErrorKind::AlreadyExists, format!("s{}e{} of tv {} already exists", season_number, episode_number, tv_id), "library.new_local_episode").into())
+        }
+        Ok(self.ctx.db.create_local_episode(tv_id, season_number, episode_number, &title,
+                                            &release_date.unwrap_or_default(), &overview.unwrap_or_default())?)
+    }
+
+    /// `export_nfo` every movie and tv, watched state is the one of `user`. Returns how many files were written.
+    /// Files already there are kept unless `overwrite`.
+    pub fn export_nfo(&self, user: String, overwrite: Option<bool>) -> PyResult<u64>{
+        let mut count = 0;
+        for movie in MovieSearch::new(&self.ctx, &user).results(None, None)?{
+            count += movie.full()?.export_nfo(overwrite)?.len() as u64;
+        }
+        for tv in TvSearch::new(&self.ctx, &user).results(None, None)?{
+            count += tv.full()?.export_nfo(overwrite)?.len() as u64;
+        }
+        Ok(count)
     }
 
     pub fn genre_movie_json(&self) -> PyResult<String>{
-        let list = DATABASE.genre_movie()?;
+        let list = self.ctx.db.genre_movie()?;
         Ok(serde_json::to_string(&list).unwrap())
     }
 
     pub fn genre_tv_json(&self) -> PyResult<String>{
-        let list = DATABASE.genre_tv()?;
+        let list = self.ctx.db.genre_tv()?;
         Ok(serde_json::to_string(&list).unwrap())
     }
 
 
 }
 
+impl Library{
+    #[allow(clippy::too_many_arguments)]
+    fn local_media(&self, kind: Editable, title: String, release_date: Option<String>, overview: Option<String>,
+                    poster: Option<String>, backdrop: Option<String>, genres: Option<Vec<String>>) -> PyResult<LocalMedia>{
+        let genres = genres.unwrap_or_default();
+        kind.check(&self.ctx, "genres", &genres.join(","))?;
+        for image in poster.iter().chain(backdrop.iter()){
+            local::check_image(image)?;
+        }
+        Ok(LocalMedia{
+            title,
+            release_date: release_date.unwrap_or_default(),
+            overview: overview.unwrap_or_default(),
+            poster,
+            backdrop,
+            genres,
+        })
+    }
+}
+
 #[derive(Debug)]
 pub enum ErrorKind{
     ParseName,
     NotFound,
-    MediaType
+    MediaType,
+    InvalidFilter,
+    InvalidPage,
+    InvalidDate,
+    InvalidOverride,
+    AlreadyExists,
+    InvalidMetadata,
+    ReadOnly,
+    NoTmdbKey,
 }
 
 #[derive(Debug)]
@@ -1,23 +1,29 @@
 
-use std::collections::HashMap;
-
 use pyo3::prelude::*;
 
-use crate::database::DATABASE;
+use super::Context;
+use super::page::{self, search_iterator, Batched, Page};
+use super::filter::{search_fields, parse_field, parse_order_by, py_values, Condition, Expr, Filter, Operator, Sort};
 
 use super::cast::Cast;
 use super::cast::Crew;
 use super::collection::CollectionResult;
 use super::collection::CollectionSearch;
 use super::keyword::Keyword;
+use super::production::{Company, Country, Language};
 use super::trailer::Trailer;
 use super::video::VideoResult;
 use super::video::VideoSearch;
+use super::update_db;
+use super::overrides::Editable;
+use super::nfo;
 
 #[pyclass]
 #[derive(Debug, Serialize)]
 pub struct Movie{
     pub user: String,
+    #[serde(skip)]
+    pub ctx: Context,
     #[pyo3(get)]
     pub id: u64,
     #[pyo3(get)]
@@ -26,6 +32,9 @@ pub struct Movie{
     pub original_language: String,
     #[pyo3(get)]
     pub title: String,
+    /// title used to sort, the title unless edited
+    #[pyo3(get)]
+    pub sort_title: String,
     #[pyo3(get)]
     pub release_date: String,
     #[pyo3(get)]
@@ -62,55 +71,109 @@ pub struct Movie{
     pub keyword: Vec<Keyword>,
     #[pyo3(get)]
     pub collection: Vec<CollectionResult>,
+    /// minutes, None when unknown
+    #[pyo3(get)]
+    pub runtime: Option<u64>,
+    /// dollars, 0 when unknown
+    #[pyo3(get)]
+    pub budget: u64,
+    #[pyo3(get)]
+    pub revenue: u64,
+    #[pyo3(get)]
+    pub homepage: String,
+    /// the franchise, as TMDB names it
+    #[pyo3(get)]
+    pub belongs_to_collection: Option<BelongsToCollection>,
+    #[pyo3(get)]
+    pub companies: Vec<Company>,
+    /// production countries
+    #[pyo3(get)]
+    pub countries: Vec<Country>,
+    /// spoken languages
+    #[pyo3(get)]
+    pub languages: Vec<Language>,
     #[pyo3(get)]
     pub updated: String,
 }
 
+/// A TMDB collection, the franchise a movie belongs to
+#[pyclass]
+#[derive(Debug, Serialize, Clone)]
+pub struct BelongsToCollection{
+    #[pyo3(get)]
+    pub id: u64,
+    #[pyo3(get)]
+    pub name: String,
+    #[pyo3(get)]
+    pub poster_path: String,
+    #[pyo3(get)]
+    pub backdrop_path: String,
+}
+
+#[pymethods]
+impl BelongsToCollection{
+    fn __str__(&self) -> PyResult<String>{
+        Ok(format!("{:?}", self))
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("{:?}", self))
+    }
+}
+
 #[pymethods]
 impl Movie{
 
     pub fn set_videos(&mut self) -> PyResult<()>{
-        self.video = VideoSearch::new(&self.user).movie()?.media_id(self.id)?.results(None, None)?;
+        self.video = VideoSearch::new(&self.ctx, &self.user).movie()?.media_id(self.id)?.results(None, None)?;
         Ok(())
     }
 
     pub fn set_collection(&mut self) -> PyResult<()>{
-        self.collection = CollectionSearch::new(&self.user).movie(self.id)?.results(None, None)?;
+        self.collection = CollectionSearch::new(&self.ctx, &self.user).movie(self.id)?.results(None, None)?;
         Ok(())
     }
 
     pub fn set_persons(&mut self) -> PyResult<()>{
-        self.cast = DATABASE.get_movie_cast(&self.user, self.id)?;
-        self.crew = DATABASE.get_movie_crew(&self.user, self.id)?;
+        self.cast = self.ctx.db.get_movie_cast(&self.ctx, &self.user, self.id)?;
+        self.crew = self.ctx.db.get_movie_crew(&self.ctx, &self.user, self.id)?;
         Ok(())
     }
 
     pub fn set_trailers(&mut self) -> PyResult<()>{
-        self.trailer = DATABASE.get_movie_trailer(self.id)?;
+        self.trailer = self.ctx.db.get_movie_trailer(self.id)?;
         Ok(())
     }
 
     pub fn set_keywords(&mut self) -> PyResult<()>{
-        self.keyword = DATABASE.get_movie_keywords(self.id)?;
+        self.keyword = self.ctx.db.get_movie_keywords(self.id)?;
+        Ok(())
+    }
+
+    /// companies, countries and languages
+    pub fn set_production(&mut self) -> PyResult<()>{
+        self.companies = self.ctx.db.get_companies("Companies", "MovieCompanyLinks", "movie_id", "company_id", self.id)?;
+        self.countries = self.ctx.db.get_countries("MovieCountryLinks", "movie_id", self.id)?;
+        self.languages = self.ctx.db.get_languages("MovieLanguageLinks", "movie_id", self.id)?;
         Ok(())
     }
 
     pub fn set_watched(&self, b: bool) -> PyResult<()>{
         if b{
-            Ok(DATABASE.set_movie_watched(self.user.clone(), self.id, self.watched+1)?)
+            Ok(self.ctx.db.set_movie_watched(self.user.clone(), self.id, self.watched+1)?)
         }
         else{
-            Ok(DATABASE.set_movie_watched(self.user.clone(), self.id, 0)?)
+            Ok(self.ctx.db.set_movie_watched(self.user.clone(), self.id, 0)?)
         }
         
     }
 
     pub fn delete(&mut self) -> PyResult<()>{
-        if VideoSearch::new(&self.user).media_id(self.id)?.exist()?{
+        if VideoSearch::new(&self.ctx, &self.user).movie()?.media_id(self.id)?.exist()?{
             return Ok(())
         }
         self.set_persons()?;
-        DATABASE.delete_movie(self.id)?;
+        self.ctx.db.delete_movie(self.id)?;
         for crew in &self.crew{
             crew.full()?.delete()?;
         }
@@ -120,6 +183,92 @@ impl Movie{
         Ok(())
     }
 
+    /// fetch the movie again from TMDB, persons left without credits are removed
+    pub fn refresh(&mut self) -> PyResult<()>{
+        self.set_persons()?;
+        update_db::refresh_movie(&self.ctx, &self.user, self.id)?;
+        for crew in &self.crew{
+            crew.full()?.delete()?;
+        }
+        for cast in &self.cast{
+            cast.full()?.delete()?;
+        }
+        if let Some(movie) = self.ctx.db.get_movie(&self.ctx, &self.user, self.id)?{
+            *self = movie;
+        }
+        Ok(())
+    }
+
+    pub fn set_title(&mut self, title: String) -> PyResult<()>{
+        self.edit("title", &title)?;
+        self.title = title;
+        Ok(())
+    }
+
+    pub fn set_sort_title(&mut self, sort_title: String) -> PyResult<()>{
+        self.edit("sort_title", &sort_title)?;
+        self.sort_title = sort_title;
+        Ok(())
+    }
+
+    pub fn set_overview(&mut self, overview: String) -> PyResult<()>{
+        self.edit("overview", &overview)?;
+        self.overview = overview;
+        Ok(())
+    }
+
+    /// path of an image in the resource folder, relative to its `original` folder
+    pub fn set_poster_path(&mut self, poster_path: String) -> PyResult<()>{
+        self.edit("poster_path", &poster_path)?;
+        self.poster_path = poster_path;
+        Ok(())
+    }
+
+    pub fn set_backdrop_path(&mut self, backdrop_path: String) -> PyResult<()>{
+        self.edit("backdrop_path", &backdrop_path)?;
+        self.backdrop_path = backdrop_path;
+        Ok(())
+    }
+
+    pub fn set_release_date(&mut self, release_date: String) -> PyResult<()>{
+        self.edit("release_date", &release_date)?;
+        self.release_date = release_date;
+        Ok(())
+    }
+
+    /// names of existing movie genres
+    pub fn set_genres(&mut self, genres: Vec<String>) -> PyResult<()>{
+        self.edit("genres", &genres.join(","))?;
+        self.genres = genres;
+        Ok(())
+    }
+
+    /// fields edited by hand, refreshes leave them alone
+    pub fn locked_fields(&self) -> PyResult<Vec<String>>{
+        Ok(self.ctx.db.locked_fields(Editable::Movie, self.id)?)
+    }
+
+    /// write Kodi NFO files and artwork next to the videos, returns the files written.
+    /// Files already there are kept unless `overwrite`.
+    pub fn export_nfo(&mut self, overwrite: Option<bool>) -> PyResult<Vec<String>>{
+        nfo::export_movie(self, overwrite.unwrap_or(false))
+    }
+
+    /// drop the edit of `field` and go back to the TMDB value
+    pub fn revert(&mut self, field: String) -> PyResult<()>{
+        self.ctx.db.revert_override(Editable::Movie, self.id, &field)?;
+        if let Some(movie) = self.ctx.db.get_movie(&self.ctx, &self.user, self.id)?{
+            self.title = movie.title;
+            self.sort_title = movie.sort_title;
+            self.overview = movie.overview;
+            self.poster_path = movie.poster_path;
+            self.backdrop_path = movie.backdrop_path;
+            self.release_date = movie.release_date;
+            self.genres = movie.genres;
+        }
+        Ok(())
+    }
+
     pub fn json(&self) -> PyResult<String>{
         return Ok(serde_json::to_string(self).unwrap())
     }
@@ -134,10 +283,19 @@ impl Movie{
 
 }
 
+impl Movie{
+    fn edit(&self, field: &str, value: &str) -> PyResult<()>{
+        Editable::Movie.check(&self.ctx, field, value)?;
+        Ok(self.ctx.db.set_override(Editable::Movie, self.id, field, value)?)
+    }
+}
+
 #[pyclass]
 #[derive(Debug, Serialize, Clone)]
 pub struct MovieResult{
     pub user: String,
+    #[serde(skip)]
+    pub ctx: Context,
     #[pyo3(get)]
     pub id: u64,
     #[pyo3(get)]
@@ -156,12 +314,14 @@ pub struct MovieResult{
     pub adding: String,
     #[pyo3(get)]
     pub watched: u64,
+    #[pyo3(get)]
+    pub runtime: Option<u64>,
 }
 
 #[pymethods]
 impl MovieResult{
     pub fn full(&self) -> PyResult<Movie>{
-        Ok(DATABASE.get_movie(&self.user, self.id)?.unwrap())
+        Ok(self.ctx.db.get_movie(&self.ctx, &self.user, self.id)?.unwrap())
     }
 
     fn __str__(&self) -> PyResult<String>{
@@ -173,64 +333,131 @@ impl MovieResult{
     }
 }
 
+search_fields!{ MovieField {
+    Id => "id" : "Movies.id",
+    Title => "title" : "Movies.title",
+    SortTitle => "sort_title" : "COALESCE(Movies.sort_title, Movies.title)",
+    OriginalTitle => "original_title" : "Movies.original_title",
+    ReleaseDate => "release_date" : "Movies.release_date",
+    VoteAverage => "vote_average" : "Movies.vote_average",
+    VoteCount => "vote_count" : "Movies.vote_count",
+    Popularity => "popularity" : "Movies.popularity",
+    Status => "status" : "Movies.status",
+    Adding => "adding" : "Videos.adding" / "MAX(Videos.adding)",
+    Watched => "watched" : "MovieUserWatched.watched",
+    Genre => "genre" : "MovieGenreLinks.genre_id",
+    Cast => "cast" : "MovieCasts.person_id",
+    Crew => "crew" : "MovieCrews.person_id",
+    Collection => "collection" : "MovieCollectionLinks.collection_id",
+    Runtime => "runtime" : "Movies.runtime",
+    Budget => "budget" : "Movies.budget",
+    Revenue => "revenue" : "Movies.revenue",
+    Company => "company" : "MovieCompanyLinks.company_id",
+    Country => "country" : "MovieCountryLinks.country_code",
+    Language => "language" : "MovieLanguageLinks.language_code",
+}}
+
 #[pyclass]
 #[derive(Debug, Clone)]
 pub struct MovieSearch{
-   parameters: HashMap<String, Option<(String, String)>>,
+   parameters: Vec<Expr<MovieField>>,
    user: String,
-   order_by: Option<String>,
+   ctx: Context,
+   order_by: Vec<Sort<MovieField>>,
 }
 
 impl MovieSearch{
-    pub fn new(user: &String) -> MovieSearch{
+    pub fn new(ctx: &Context, user: &String) -> MovieSearch{
         MovieSearch{
-            parameters: HashMap::new(),
+            parameters: Vec::new(),
             user: user.clone(),
-            order_by: None,
+            ctx: ctx.clone(),
+            order_by: Vec::new(),
         }
     }
+
+    fn condition(&mut self, field: MovieField, operator: Operator, values: Vec<String>) -> PyResult<MovieSearch>{
+        self.parameters.push(Expr::Condition(Condition::new(field, operator, values)?));
+        Ok(self.clone())
+    }
 }
 
 #[pymethods]
 impl MovieSearch{
     pub fn id(&mut self, id: u64) -> PyResult<MovieSearch>{
-        self.find("Movies.id", "=", Some(id.to_string()))
+        self.condition(MovieField::Id, Operator::Eq, vec![id.to_string()])
     }
 
     pub fn cast(&mut self, person_id: u64) -> PyResult<MovieSearch>{
-        self.find("MovieCasts.person_id", "=", Some(person_id.to_string()))
+        self.condition(MovieField::Cast, Operator::Eq, vec![person_id.to_string()])
     }
 
     pub fn collection(&mut self, collection_id: u64) -> PyResult<MovieSearch>{
-        self.find("MovieCollectionLinks.collection_id", "=", Some(collection_id.to_string()))
+        self.condition(MovieField::Collection, Operator::Eq, vec![collection_id.to_string()])
     }
 
     pub fn crew(&mut self, person_id: u64) -> PyResult<MovieSearch>{
-        self.find("MovieCrews.person_id", "=", Some(person_id.to_string()))
+        self.condition(MovieField::Crew, Operator::Eq, vec![person_id.to_string()])
     }
 
-    pub fn find(&mut self, column: &str, operator: &str, value: Option<String>) -> PyResult<MovieSearch>{
-        if let Some(value) = value {
-            self.parameters.insert(column.to_string(), Some((operator.to_string(), value)));
-        }
-        else{
-            self.parameters.insert(column.to_string(), None);
-        }
+    pub fn company(&mut self, company_id: u64) -> PyResult<MovieSearch>{
+        self.condition(MovieField::Company, Operator::Eq, vec![company_id.to_string()])
+    }
+
+    /// produced in the ISO 3166-1 `code` country
+    pub fn country(&mut self, code: &str) -> PyResult<MovieSearch>{
+        self.condition(MovieField::Country, Operator::Eq, vec![code.to_uppercase()])
+    }
+
+    /// spoken in the ISO 639-1 `code` language
+    pub fn language(&mut self, code: &str) -> PyResult<MovieSearch>{
+        self.condition(MovieField::Language, Operator::Eq, vec![code.to_lowercase()])
+    }
+
+    pub fn filter(&mut self, field: MovieField, operator: Operator, value: Option<&PyAny>) -> PyResult<MovieSearch>{
+        self.condition(field, operator, py_values(value)?)
+    }
+
+    pub fn filter_by(&mut self, filter: Filter) -> PyResult<MovieSearch>{
+        self.parameters.push(filter.expr.resolve()?);
         Ok(self.clone())
     }
 
-    pub fn order_by(&mut self, order_by: String) -> PyResult<MovieSearch>{
-        self.order_by = Some(order_by);
+    pub fn find(&mut self, column: &str, operator: &str, value: Option<&PyAny>) -> PyResult<MovieSearch>{
+        self.filter(parse_field(column)?, Operator::parse(operator)?, value)
+    }
+
+    pub fn sort(&mut self, field: MovieField, descending: bool) -> PyResult<MovieSearch>{
+        self.order_by.push(Sort{ field, descending });
         Ok(self.clone())
+    }
 
+    pub fn order_by(&mut self, order_by: String) -> PyResult<MovieSearch>{
+        self.order_by = parse_order_by(&order_by)?;
+        Ok(self.clone())
     }
 
     pub fn exist(&self) -> PyResult<bool>{
-        Ok(self.results(None, None)?.len() > 0)
-    } 
+        Ok(self.results(Some(1), None)?.len() > 0)
+    }
+
+    pub fn count(&self) -> PyResult<u64>{
+        Ok(Python::with_gil(|py| py.allow_threads(|| self.ctx.db.count_movies(&self.user, &self.parameters)))?)
+    }
+
+    pub fn page(&self, size: u64, number: u64) -> PyResult<Page>{
+        let (limit, offset) = page::bounds(size, number)?;
+        Ok(Page::new(self.results(Some(limit), Some(offset))?, self.count()?, size, number))
+    }
+
+    pub fn json_page(&self, size: u64, number: u64) -> PyResult<String>{
+        let (limit, offset) = page::bounds(size, number)?;
+        let list = self.results(Some(limit), Some(offset))?;
+        Ok(page::json(&list, self.count()?, size, number))
+    }
 
     pub fn results(&self, limit: Option<u64>, offset: Option<u64>) -> PyResult<Vec<MovieResult>>{
-        Ok(DATABASE.get_movies(&self.user, &self.parameters, &self.order_by, limit, offset)?)
+        Ok(Python::with_gil(|py| py.allow_threads(|| self.ctx.db.get_movies(&self.ctx, &self.user, &self.parameters, &self.order_by, limit, offset)))?)
     }
 
     pub fn json_results(&self, limit: Option<u64>, offset: Option<u64>) -> PyResult<String>{
@@ -249,4 +476,27 @@ impl MovieSearch{
     fn __repr__(&self) -> PyResult<String> {
         Ok(format!("{:?}", self))
     }
-}
\ No newline at end of file
+}
+
+impl Batched for MovieSearch{
+    type Item = MovieResult;
+
+    fn sorted(&self) -> bool{
+        !self.order_by.is_empty()
+    }
+
+    fn batch(&self, after: Option<u64>, offset: Option<u64>, size: u64) -> PyResult<Vec<MovieResult>>{
+        let mut search = self.clone();
+        if let Some(id) = after{
+            search.condition(MovieField::Id, Operator::Gt, vec![id.to_string()])?;
+        }
+        search.order_by.push(Sort{ field: MovieField::Id, descending: false });
+        search.results(Some(size), offset)
+    }
+
+    fn item_id(item: &MovieResult) -> u64{
+        item.id
+    }
+}
+
+search_iterator!(MovieIterator, MovieSearch);
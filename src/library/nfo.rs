@@ -0,0 +1,398 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use pyo3::prelude::*;
+use regex::Regex;
+
+use super::Context;
+use super::cast::{Cast, Crew};
+use super::local::{is_local, LocalMedia};
+use super::movie::Movie;
+use super::overrides::Editable;
+use super::tv::{Episode, EpisodeSearch, Tv};
+use super::video::Video;
+
+/// Metadata of a Kodi NFO sidecar file, see `export_movie` and `export_tv` for the other way, `root` is movie, tvshow or episodedetails, empty for a file holding only a url
+#[derive(Debug, Default, Clone)]
+pub struct Nfo{
+    pub root: String,
+    pub tmdb_id: Option<u64>,
+    pub imdb_id: Option<String>,
+    pub title: String,
+    pub plot: String,
+    /// premiered or aired date, the year alone when nothing else is given
+    pub premiered: String,
+    pub genres: Vec<String>,
+    pub season: Option<u64>,
+    pub episode: Option<u64>,
+}
+
+impl Nfo{
+    pub fn read(path: &Path) -> Option<Nfo>{
+        let text = fs::read_to_string(path).ok()?;
+        // Kodi allows a url after the xml
+        let (xml, tail) = match text.rfind('>'){
+            Some(end) => text.split_at(end + 1),
+            None => ("", text.as_str()),
+        };
+        let mut nfo = Nfo::parse_xml(xml).unwrap_or_default();
+        nfo.parse_urls(tail);
+        if nfo.root.is_empty() && nfo.tmdb_id.is_none() && nfo.imdb_id.is_none(){
+            return None
+        }
+        Some(nfo)
+    }
+
+    fn parse_xml(xml: &str) -> Option<Nfo>{
+        let document = roxmltree::Document::parse(xml).ok()?;
+        let root = document.root_element();
+        let mut nfo = Nfo{ root: root.tag_name().name().to_string(), ..Default::default() };
+        let mut year = String::new();
+
+        for node in root.children().filter(|node| node.is_element()){
+            let text = node.text().unwrap_or_default().trim();
+            match node.tag_name().name(){
+                "title" => nfo.title = text.to_string(),
+                "plot" => nfo.plot = text.to_string(),
+                "premiered" | "aired" => nfo.premiered = text.to_string(),
+                "year" => year = text.to_string(),
+                "genre" if !text.is_empty() => nfo.genres.push(text.to_string()),
+                "season" => nfo.season = text.parse().ok(),
+                "episode" => nfo.episode = text.parse().ok(),
+                "tmdbid" => nfo.tmdb_id = text.parse().ok(),
+                "imdbid" | "imdb_id" if text.starts_with("tt") => nfo.imdb_id = Some(text.to_string()),
+                "uniqueid" => match node.attribute("type"){
+                    Some("tmdb") => nfo.tmdb_id = text.parse().ok(),
+                    Some("imdb") => nfo.imdb_id = Some(text.to_string()),
+                    _ => (),
+                },
+                // older files only have <id>, an IMDb id or a scraper one
+                "id" if text.starts_with("tt") && nfo.imdb_id.is_none() => nfo.imdb_id = Some(text.to_string()),
+                _ => (),
+            }
+        }
+        if nfo.premiered.is_empty(){
+            nfo.premiered = year;
+        }
+        Some(nfo)
+    }
+
+    fn parse_urls(&mut self, text: &str){
+        let tmdb = Regex::new(r"themoviedb\.org/(?:movie|tv)/(\d+)").unwrap();
+        let imdb = Regex::new(r"imdb\.com/title/(tt\d+)").unwrap();
+        if self.tmdb_id.is_none(){
+            self.tmdb_id = tmdb.captures(text).and_then(|cap| cap[1].parse().ok());
+        }
+        if self.imdb_id.is_none(){
+            self.imdb_id = imdb.captures(text).map(|cap| cap[1].to_string());
+        }
+    }
+
+    fn local_media(&self, ctx: &Context, kind: Editable) -> PyResult<LocalMedia>{
+        let known = match kind{
+            Editable::Movie => ctx.db.genre_movie()?,
+            _ => ctx.db.genre_tv()?,
+        };
+        Ok(LocalMedia{
+            title: self.title.clone(),
+            release_date: self.premiered.clone(),
+            overview: self.plot.clone(),
+            genres: self.genres.iter().filter(|name| known.iter().any(|genre| &&genre.name == name)).cloned().collect(),
+            ..Default::default()
+        })
+    }
+}
+
+/// `<dir>/<name>.nfo` then the folder wide `names` (movie.nfo, tvshow.nfo...), the first one with root `root`
+fn find(candidates: Vec<PathBuf>, root: &str) -> Option<Nfo>{
+    candidates.iter()
+        .filter_map(|path| Nfo::read(path))
+        .find(|nfo| nfo.root == root || nfo.root.is_empty())
+}
+
+fn sidecar(video_path: &Path) -> PathBuf{
+    video_path.with_extension("nfo")
+}
+
+/// tvshow.nfo sits in the show folder, one or two levels above episodes in season folders
+fn tvshow_candidates(video_path: &Path) -> Vec<PathBuf>{
+    video_path.ancestors().skip(1).take(3).map(|folder| folder.join("tvshow.nfo")).collect()
+}
+
+/// TMDB id of the movie or tv an NFO points to, asking TMDB for IMDb ids
+fn tmdb_id(ctx: &Context, nfo: &Nfo, tv: bool) -> PyResult<Option<u64>>{
+    if let Some(id) = nfo.tmdb_id{
+        return Ok(Some(id))
+    }
+    if let Some(imdb_id) = &nfo.imdb_id{
+        let found = match ctx.providers().find_imdb(imdb_id)?{
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        return Ok(if tv{
+            found.tv_results.first().map(|tv| tv.id)
+        }
+        else{
+            found.movie_results.first().map(|movie| movie.id)
+        })
+    }
+    Ok(None)
+}
+
+/// link a movie video to its movie.nfo or <name>.nfo, false when there is none
+pub fn import_movie(video: &mut Video) -> PyResult<bool>{
+    let path = Path::new(&video.path).to_path_buf();
+    let folder = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let nfo = match find(vec![sidecar(&path), folder.join("movie.nfo")], "movie"){
+        Some(nfo) => nfo,
+        None => return Ok(false),
+    };
+
+    let movie_id = match tmdb_id(&video.ctx, &nfo, false)?{
+        Some(movie_id) => movie_id,
+        None if !nfo.title.is_empty() => match video.ctx.db.find_local("Movies", &nfo.title)?{
+            Some(movie_id) => movie_id,
+            None => video.ctx.db.create_local_movie(&nfo.local_media(&video.ctx, Editable::Movie)?)?,
+        },
+        None => return Ok(false),
+    };
+    video.set_movie(movie_id)?;
+    Ok(true)
+}
+
+/// link an episode video to its tvshow.nfo and <name>.nfo, false when the show is not described
+pub fn import_episode(video: &mut Video) -> PyResult<bool>{
+    let path = Path::new(&video.path).to_path_buf();
+    let tvshow = match find(tvshow_candidates(&path), "tvshow"){
+        Some(nfo) => nfo,
+        None => return Ok(false),
+    };
+    let episode = find(vec![sidecar(&path)], "episodedetails").unwrap_or_default();
+
+    let (season_number, episode_number) = match (episode.season, episode.episode){
+        (Some(season_number), Some(episode_number)) => (season_number, episode_number),
+        _ => {
+            let (_, season_number, episode_number) = video.parse_tv()?;
+            (season_number, episode_number)
+        },
+    };
+
+    let tv_id = match tmdb_id(&video.ctx, &tvshow, true)?{
+        Some(tv_id) => tv_id,
+        None if !tvshow.title.is_empty() => match video.ctx.db.find_local("Tvs", &tvshow.title)?{
+            Some(tv_id) => tv_id,
+            None => video.ctx.db.create_local_tv(&tvshow.local_media(&video.ctx, Editable::Tv)?)?,
+        },
+        None => return Ok(false),
+    };
+
+    if is_local(tv_id) && video.ctx.db.get_episode_id(tv_id, season_number, episode_number)?.is_none(){
+        let title = if episode.title.is_empty(){
+            format!("Episode {}", episode_number)
+        }
+        else{
+            episode.title.clone()
+        };
+        video.ctx.db.create_local_episode(tv_id, season_number, episode_number, &title, &episode.premiered, &episode.plot)?;
+    }
+    video.set_tv(tv_id, season_number, episode_number)?;
+    Ok(true)
+}
+
+fn escape(text: &str) -> String{
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn tag(xml: &mut String, name: &str, value: &str){
+    if !value.is_empty(){
+        xml.push_str(&format!("  <{0}>{1}</{0}>\n", name, escape(value)));
+    }
+}
+
+fn year(date: &str) -> &str{
+    date.get(..4).unwrap_or_default()
+}
+
+fn ratings(xml: &mut String, vote_average: f64, vote_count: i64){
+    xml.push_str(&format!("  <ratings>\n    <rating name=\"themoviedb\" max=\"10\" default=\"true\">\n      <value>{}</value>\n      <votes>{}</votes>\n    </rating>\n  </ratings>\n",
+                            vote_average, vote_count));
+}
+
+fn uniqueid(xml: &mut String, id: u64){
+    if !is_local(id){
+        xml.push_str(&format!("  <uniqueid type=\"tmdb\" default=\"true\">{}</uniqueid>\n", id));
+    }
+}
+
+fn persons(xml: &mut String, cast: &[Cast], crew: &[Crew]){
+    for person in crew{
+        match person.job.as_ref(){
+            "Director" => tag(xml, "director", &person.name),
+            "Screenplay" => tag(xml, "credits", &person.name),
+            _ => (),
+        }
+    }
+    for person in cast{
+        xml.push_str(&format!("  <actor>\n    <name>{}</name>\n    <role>{}</role>\n    <order>{}</order>\n  </actor>\n",
+                                escape(&person.name), escape(&person.character), person.ord));
+    }
+}
+
+fn watched(xml: &mut String, count: u64){
+    xml.push_str(&format!("  <playcount>{}</playcount>\n  <watched>{}</watched>\n", count, count > 0));
+}
+
+const HEADER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\" ?>\n";
+
+pub fn movie_xml(movie: &Movie) -> String{
+    let mut xml = format!("{}<movie>\n", HEADER);
+    tag(&mut xml, "title", &movie.title);
+    tag(&mut xml, "originaltitle", &movie.original_title);
+    tag(&mut xml, "sorttitle", &movie.sort_title);
+    tag(&mut xml, "plot", &movie.overview);
+    tag(&mut xml, "tagline", &movie.tagline);
+    tag(&mut xml, "year", year(&movie.release_date));
+    tag(&mut xml, "premiered", &movie.release_date);
+    tag(&mut xml, "runtime", &movie.runtime.map(|runtime| runtime.to_string()).unwrap_or_default());
+    tag(&mut xml, "status", &movie.status);
+    ratings(&mut xml, movie.vote_average, movie.vote_count);
+    uniqueid(&mut xml, movie.id);
+    for genre in &movie.genres{
+        tag(&mut xml, "genre", genre);
+    }
+    if let Some(collection) = &movie.belongs_to_collection{
+        xml.push_str(&format!("  <set>\n    <name>{}</name>\n  </set>\n", escape(&collection.name)));
+    }
+    persons(&mut xml, &movie.cast, &movie.crew);
+    watched(&mut xml, movie.watched);
+    xml.push_str("</movie>\n");
+    xml
+}
+
+pub fn tvshow_xml(tv: &Tv) -> String{
+    let mut xml = format!("{}<tvshow>\n", HEADER);
+    tag(&mut xml, "title", &tv.title);
+    tag(&mut xml, "originaltitle", &tv.original_title);
+    tag(&mut xml, "sorttitle", &tv.sort_title);
+    tag(&mut xml, "plot", &tv.overview);
+    tag(&mut xml, "year", year(&tv.release_date));
+    tag(&mut xml, "premiered", &tv.release_date);
+    tag(&mut xml, "status", &tv.status);
+    ratings(&mut xml, tv.vote_average, tv.vote_count);
+    uniqueid(&mut xml, tv.id);
+    for genre in &tv.genres{
+        tag(&mut xml, "genre", genre);
+    }
+    persons(&mut xml, &tv.cast, &tv.crew);
+    watched(&mut xml, tv.watched);
+    xml.push_str("</tvshow>\n");
+    xml
+}
+
+pub fn episode_xml(episode: &Episode) -> String{
+    let mut xml = format!("{}<episodedetails>\n", HEADER);
+    tag(&mut xml, "title", &episode.title);
+    tag(&mut xml, "showtitle", &episode.tv_title);
+    tag(&mut xml, "season", &episode.season_number.to_string());
+    tag(&mut xml, "episode", &episode.episode_number.to_string());
+    tag(&mut xml, "plot", &episode.overview);
+    tag(&mut xml, "aired", &episode.release_date);
+    ratings(&mut xml, episode.vote_average, episode.vote_count as i64);
+    uniqueid(&mut xml, episode.id);
+    let actors: Vec<Cast> = episode.cast.iter().chain(&episode.guest_stars).cloned().collect();
+    persons(&mut xml, &actors, &episode.crew);
+    watched(&mut xml, episode.watched);
+    xml.push_str("</episodedetails>\n");
+    xml
+}
+
+/// files already there are kept unless `overwrite`
+fn write(path: PathBuf, content: &str, overwrite: bool, written: &mut Vec<String>) -> PyResult<()>{
+    if !overwrite && path.exists(){
+        return Ok(())
+    }
+    fs::write(&path, content)?;
+    written.push(path.to_string_lossy().to_string());
+    Ok(())
+}
+
+/// copy an image of the resource folder, images never downloaded are skipped, see `write` for `overwrite`
+fn copy_image(ctx: &Context, rsc_path: &str, path: PathBuf, overwrite: bool, written: &mut Vec<String>) -> PyResult<()>{
+    let source = PathBuf::from(format!("{}/original{}", ctx.rsc_path, rsc_path));
+    if rsc_path.is_empty() || !source.is_file() || (!overwrite && path.exists()){
+        return Ok(())
+    }
+    fs::copy(&source, &path)?;
+    written.push(path.to_string_lossy().to_string());
+    Ok(())
+}
+
+/// `<name>.nfo` next to every video of the movie, poster.jpg and fanart.jpg in their folders, returns the files written
+pub fn export_movie(movie: &mut Movie, overwrite: bool) -> PyResult<Vec<String>>{
+    movie.set_persons()?;
+    movie.set_videos()?;
+    let xml = movie_xml(movie);
+
+    let mut written = Vec::new();
+    for video in &movie.video{
+        let path = Path::new(&video.path);
+        write(sidecar(path), &xml, overwrite, &mut written)?;
+        if let Some(folder) = path.parent(){
+            copy_image(&movie.ctx, &movie.poster_path, folder.join("poster.jpg"), overwrite, &mut written)?;
+            copy_image(&movie.ctx, &movie.backdrop_path, folder.join("fanart.jpg"), overwrite, &mut written)?;
+        }
+    }
+    Ok(written)
+}
+
+/// folder of the show, above the season folder when episodes are in one
+fn show_folder(video_path: &Path) -> Option<PathBuf>{
+    let season = Regex::new(r"(?i)^(season|saison|series|s)\s*\d+$|^specials$").unwrap();
+    let folder = video_path.parent()?;
+    match folder.file_name(){
+        Some(name) if season.is_match(&name.to_string_lossy()) => folder.parent().map(Path::to_path_buf),
+        _ => Some(folder.to_path_buf()),
+    }
+}
+
+/// `<name>.nfo` and its `<name>-thumb.jpg` still next to every episode video, tvshow.nfo and the show and season artwork in the show folder
+pub fn export_tv(tv: &mut Tv, overwrite: bool) -> PyResult<Vec<String>>{
+    tv.set_persons()?;
+    tv.set_seasons()?;
+
+    let mut written = Vec::new();
+    let mut show_folders: Vec<PathBuf> = Vec::new();
+    for mut episode in EpisodeSearch::new(&tv.ctx, &tv.user).tv(tv.id)?.results(None, None)?{
+        episode.set_persons()?;
+        episode.set_videos()?;
+        let xml = episode_xml(&episode);
+        for video in &episode.video{
+            let path = Path::new(&video.path);
+            write(sidecar(path), &xml, overwrite, &mut written)?;
+            if let Some(stem) = path.file_stem(){
+                let thumb = path.with_file_name(format!("{}-thumb.jpg", stem.to_string_lossy()));
+                copy_image(&tv.ctx, &episode.still_path, thumb, overwrite, &mut written)?;
+            }
+            if let Some(folder) = show_folder(path){
+                if !show_folders.contains(&folder){
+                    show_folders.push(folder);
+                }
+            }
+        }
+    }
+
+    let xml = tvshow_xml(tv);
+    for folder in show_folders{
+        write(folder.join("tvshow.nfo"), &xml, overwrite, &mut written)?;
+        copy_image(&tv.ctx, &tv.poster_path, folder.join("poster.jpg"), overwrite, &mut written)?;
+        copy_image(&tv.ctx, &tv.backdrop_path, folder.join("fanart.jpg"), overwrite, &mut written)?;
+        for season in &tv.seasons{
+            let name = match season.season_number{
+                0 => "season-specials-poster.jpg".to_string(),
+                number => format!("season{:02}-poster.jpg", number),
+            };
+            copy_image(&tv.ctx, &season.poster_path, folder.join(name), overwrite, &mut written)?;
+        }
+    }
+    Ok(written)
+}
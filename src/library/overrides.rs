@@ -0,0 +1,51 @@
+use pyo3::prelude::*;
+
+use super::{Context, Error, ErrorKind};
+
+/// Kind of media whose metadata can be edited by hand.
+/// Edited fields are locked: refreshes from TMDB keep the edit until it is reverted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Editable{
+    Movie,
+    Tv,
+    Season,
+    Episode,
+}
+
+impl Editable{
+    pub fn name(&self) -> &'static str{
+        match self{
+            Editable::Movie => "movie",
+            Editable::Tv => "tv",
+            Editable::Season => "season",
+            Editable::Episode => "episode",
+        }
+    }
+
+    pub fn fields(&self) -> &'static [&'static str]{
+        match self{
+            Editable::Movie | Editable::Tv => &["title", "sort_title", "overview", "poster_path", "backdrop_path", "release_date", "genres"],
+            Editable::Season => &["title", "overview", "poster_path", "release_date"],
+            Editable::Episode => &["title", "overview", "release_date"],
+        }
+    }
+
+    /// `field` can be edited, genres have to be known ones
+    pub fn check(&self, ctx: &Context, field: &str, value: &str) -> PyResult<()>{
+        if !self.fields().contains(&field){
+            return Err(Error::new(ErrorKind::InvalidOverride, format!("{} has no editable field {}", self.name(), field), "overrides.check").into())
+        }
+        if field == "genres"{
+            let genres = match self{
+                Editable::Movie => ctx.db.genre_movie()?,
+                _ => ctx.db.genre_tv()?,
+            };
+            for name in value.split(',').filter(|name| !name.is_empty()){
+                if !genres.iter().any(|genre| genre.name == name){
+                    return Err(Error::new(ErrorKind::InvalidOverride, format!("unknown {} genre {}", self.name(), name), "overrides.check").into())
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::VecDeque;
+
+use pyo3::prelude::*;
+use serde::Serialize;
+
+use super::{Error, ErrorKind};
+
+/// One page of a search, pages are numbered from 1
+#[pyclass]
+#[derive(Debug, Clone)]
+pub struct Page{
+    #[pyo3(get)]
+    pub items: Vec<PyObject>,
+    #[pyo3(get)]
+    pub total: u64,
+    #[pyo3(get)]
+    pub size: u64,
+    #[pyo3(get)]
+    pub number: u64,
+    #[pyo3(get)]
+    pub has_next: bool,
+    #[pyo3(get)]
+    pub has_previous: bool,
+}
+
+impl Page{
+    pub fn new<T: IntoPy<PyObject>>(items: Vec<T>, total: u64, size: u64, number: u64) -> Page{
+        Python::with_gil(|py| Page{
+            items: items.into_iter().map(|item| item.into_py(py)).collect(),
+            total,
+            size,
+            number,
+            has_next: size * number < total,
+            has_previous: number > 1,
+        })
+    }
+}
+
+#[pymethods]
+impl Page{
+    fn __len__(&self) -> usize{
+        self.items.len()
+    }
+
+    fn __str__(&self) -> PyResult<String>{
+        Ok(format!("{:?}", self))
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("{:?}", self))
+    }
+}
+
+/// limit and offset of the page `number` of `size` items
+pub fn bounds(size: u64, number: u64) -> Result<(u64, u64), Error>{
+    if size == 0 || number == 0{
+        return Err(Error::new(ErrorKind::InvalidPage, format!("page {} of size {}", number, size), "page.bounds"))
+    }
+    Ok((size, size * (number - 1)))
+}
+
+/// same content as `Page`, for the json variants of the searches
+pub fn json<T: Serialize>(items: &[T], total: u64, size: u64, number: u64) -> String{
+    serde_json::json!({
+        "items": items,
+        "total": total,
+        "size": size,
+        "number": number,
+        "has_next": size * number < total,
+        "has_previous": number > 1,
+    }).to_string()
+}
+
+const BATCH_SIZE: u64 = 500;
+
+/// Search read in batches by its python iterator
+pub trait Batched: Clone{
+    type Item: IntoPy<PyObject>;
+
+    /// true when the search has its own order, batches then go by offset instead of by id
+    fn sorted(&self) -> bool;
+
+    /// `size` items ordered by the search then by id, only those with an id over `after` if given
+    fn batch(&self, after: Option<u64>, offset: Option<u64>, size: u64) -> PyResult<Vec<Self::Item>>;
+
+    fn item_id(item: &Self::Item) -> u64;
+}
+
+/// Keeps one batch of a search in memory at a time
+pub struct Cursor<S: Batched>{
+    search: S,
+    buffer: VecDeque<S::Item>,
+    last_id: Option<u64>,
+    offset: u64,
+    done: bool,
+}
+
+impl<S: Batched> Cursor<S>{
+    pub fn new(search: S) -> Cursor<S>{
+        Cursor{
+            search,
+            buffer: VecDeque::new(),
+            last_id: None,
+            offset: 0,
+            done: false,
+        }
+    }
+
+    pub fn next(&mut self) -> PyResult<Option<S::Item>>{
+        if self.buffer.is_empty() && !self.done{
+            let items = if self.search.sorted(){
+                self.search.batch(None, Some(self.offset), BATCH_SIZE)?
+            }
+            else{
+                self.search.batch(self.last_id, None, BATCH_SIZE)?
+            };
+            self.done = (items.len() as u64) < BATCH_SIZE;
+            self.offset += items.len() as u64;
+            self.last_id = items.last().map(S::item_id).or(self.last_id);
+            self.buffer.extend(items);
+        }
+        Ok(self.buffer.pop_front())
+    }
+}
+
+/// Python iterator over a search, returned by its `__iter__`
+macro_rules! search_iterator {
+    ($name:ident, $search:ident) => {
+        #[pyclass]
+        pub struct $name{
+            cursor: $crate::library::page::Cursor<$search>,
+        }
+
+        #[pymethods]
+        impl $name{
+            fn __iter__(slf: PyRef<Self>) -> PyRef<Self>{
+                slf
+            }
+
+            fn __next__(&mut self) -> PyResult<Option<<$search as $crate::library::page::Batched>::Item>>{
+                self.cursor.next()
+            }
+        }
+
+        #[pymethods]
+        impl $search{
+            fn __iter__(&self) -> $name{
+                $name{ cursor: $crate::library::page::Cursor::new(self.clone()) }
+            }
+        }
+    };
+}
+
+pub(crate) use search_iterator;
@@ -0,0 +1,68 @@
+use pyo3::prelude::*;
+
+/// A production company, or the network a tv airs on
+#[pyclass]
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub struct Company{
+    #[pyo3(get)]
+    pub id: u64,
+    #[pyo3(get)]
+    pub name: String,
+    #[pyo3(get)]
+    pub logo_path: String,
+    #[pyo3(get)]
+    pub origin_country: String,
+}
+
+#[pymethods]
+impl Company {
+    fn __str__(&self) -> PyResult<String>{
+        Ok(format!("{:?}", self))
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("{:?}", self))
+    }
+}
+
+/// ISO 3166-1 code and name
+#[pyclass]
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub struct Country{
+    #[pyo3(get)]
+    pub code: String,
+    #[pyo3(get)]
+    pub name: String,
+}
+
+#[pymethods]
+impl Country {
+    fn __str__(&self) -> PyResult<String>{
+        Ok(format!("{:?}", self))
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("{:?}", self))
+    }
+}
+
+/// ISO 639-1 code and name
+#[pyclass]
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub struct Language{
+    #[pyo3(get)]
+    pub code: String,
+    #[pyo3(get)]
+    pub name: String,
+}
+
+#[pymethods]
+impl Language {
+    fn __str__(&self) -> PyResult<String>{
+        Ok(format!("{:?}", self))
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("{:?}", self))
+    }
+}
@@ -0,0 +1,432 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use pyo3::prelude::*;
+use serde::de::DeserializeOwned;
+
+use crate::rustmdb::Client;
+use crate::rustmdb::model::{CollectionDetails, FindResults, Movie, Person, SearchMovie, SearchTv, Tv, TvEpisode};
+use super::nfo::Nfo;
+use super::{Error, ErrorKind};
+
+/// A source of metadata for `update_db`. `Ok(None)` means the provider does not know the media, the next one of the chain is asked.
+pub trait MetadataProvider: Send + Sync{
+    /// "tmdb", or the directory of a local provider
+    fn name(&self) -> String;
+    fn search_movie(&self, title: &str, year: Option<u64>) -> PyResult<Vec<SearchMovie>>;
+    fn search_tv(&self, title: &str) -> PyResult<Vec<SearchTv>>;
+    fn get_movie(&self, id: u64) -> PyResult<Option<Movie>>;
+    fn get_tv(&self, id: u64) -> PyResult<Option<Tv>>;
+    fn get_episode(&self, tv_id: u64, season_number: u64, episode_number: u64) -> PyResult<Option<TvEpisode>>;
+    fn get_person(&self, id: u64) -> PyResult<Option<Person>>;
+    /// a franchise and all its movies
+    fn get_collection(&self, id: u64) -> PyResult<Option<CollectionDetails>>;
+    /// movies and tvs known under the IMDb id `imdb_id` (tt...)
+    fn find_imdb(&self, imdb_id: &str) -> PyResult<Option<FindResults>>;
+    /// where to get the image `rsc_path` (/xxx.jpg) from, an http url or a file
+    fn image_url(&self, rsc_path: &str) -> Option<String>;
+}
+
+/// the TMDB client of a library, an error until `tmdb_init` or a key given to `Library` sets it
+pub fn configured(client: &Mutex<Option<Client>>) -> PyResult<Client>{
+    match client.lock().unwrap().clone(){
+        Some(client) => Ok(client),
+        None => Err(Error::new(ErrorKind::NoTmdbKey, "no TMDB key given to this library".to_string(), "provider.tmdb").into()),
+    }
+}
+
+/// TMDB with the client of the library, `tmdb_init` changes it for every chain holding this provider
+pub struct TmdbProvider{
+    client: Arc<Mutex<Option<Client>>>,
+}
+
+impl TmdbProvider{
+    pub fn new(client: Arc<Mutex<Option<Client>>>) -> TmdbProvider{
+        TmdbProvider{ client }
+    }
+
+    fn client(&self) -> PyResult<Client>{
+        configured(&self.client)
+    }
+}
+
+impl MetadataProvider for TmdbProvider{
+    fn name(&self) -> String{
+        "tmdb".to_string()
+    }
+
+    fn search_movie(&self, title: &str, year: Option<u64>) -> PyResult<Vec<SearchMovie>>{
+        let client = self.client()?;
+        let mut search = client.search_movie(title);
+        if let Some(year) = year{
+            search.year(year);
+        }
+        Ok(search.request()?.results)
+    }
+
+    fn search_tv(&self, title: &str) -> PyResult<Vec<SearchTv>>{
+        Ok(self.client()?.search_tv(title).request()?.results)
+    }
+
+    fn get_movie(&self, id: u64) -> PyResult<Option<Movie>>{
+        Ok(Some(self.client()?.get_movie(id)?))
+    }
+
+    fn get_tv(&self, id: u64) -> PyResult<Option<Tv>>{
+        Ok(Some(self.client()?.get_tv(id)?))
+    }
+
+    fn get_episode(&self, tv_id: u64, season_number: u64, episode_number: u64) -> PyResult<Option<TvEpisode>>{
+        Ok(Some(self.client()?.get_tv_episode(tv_id, season_number, episode_number)?))
+    }
+
+    fn get_person(&self, id: u64) -> PyResult<Option<Person>>{
+        Ok(Some(self.client()?.get_person(id)?))
+    }
+
+    fn get_collection(&self, id: u64) -> PyResult<Option<CollectionDetails>>{
+        Ok(Some(self.client()?.get_collection(id)?))
+    }
+
+    fn find_imdb(&self, imdb_id: &str) -> PyResult<Option<FindResults>>{
+        Ok(Some(self.client()?.find_imdb(imdb_id)?))
+    }
+
+    fn image_url(&self, rsc_path: &str) -> Option<String>{
+        Some("https://image.tmdb.org/t/p/original".to_string() + rsc_path)
+    }
+}
+
+/// Metadata stored in a directory, as TMDB returns it:
+/// movie/<id>.json, tv/<id>.json, tv/<id>/season/<n>/episode/<n>.json, person/<id>.json, collection/<id>.json and images/<rsc_path>.
+/// Movies and episodes can be Kodi NFO files instead (<id>.nfo), genres and credits are then left empty
+/// and episodes need a TMDB uniqueid.
+pub struct LocalProvider{
+    root: PathBuf,
+}
+
+impl LocalProvider{
+    pub fn new(root: &str) -> PyResult<LocalProvider>{
+        if !Path::new(root).is_dir(){
+            return Err(Error::new(ErrorKind::NotFound, format!("provider directory {}", root), "provider.local").into())
+        }
+        Ok(LocalProvider{ root: PathBuf::from(root) })
+    }
+
+    fn json<T: DeserializeOwned>(&self, path: &Path) -> PyResult<Option<T>>{
+        let text = match fs::read_to_string(path){
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        match serde_json::from_str(&text){
+            Ok(value) => Ok(Some(value)),
+            Err(e) => Err(Error::new(ErrorKind::InvalidMetadata, e.to_string(), &format!("provider.local {}", path.display())).into()),
+        }
+    }
+
+    /// files of the `kind` (movie, tv) folder ending in `extension`
+    fn files(&self, kind: &str, extension: &str) -> PyResult<Vec<PathBuf>>{
+        let entries = match fs::read_dir(self.root.join(kind)){
+            Ok(entries) => entries,
+            Err(_) => return Ok(Vec::new()),
+        };
+        let mut result = Vec::new();
+        for entry in entries{
+            let path = entry?.path();
+            if path.extension().is_some_and(|found| found == extension){
+                result.push(path);
+            }
+        }
+        Ok(result)
+    }
+
+    /// every movie or tv of the directory written as json
+    fn all<T: DeserializeOwned>(&self, kind: &str) -> PyResult<Vec<T>>{
+        let mut result = Vec::new();
+        for path in self.files(kind, "json")?{
+            result.extend(self.json(&path)?);
+        }
+        Ok(result)
+    }
+
+    /// every movie of the directory, the NFO ones without a json of the same id included
+    fn movies(&self) -> PyResult<Vec<Movie>>{
+        let mut movies: Vec<Movie> = self.all("movie")?;
+        for path in self.files("movie", "nfo")?{
+            let id = match path.file_stem().and_then(|stem| stem.to_str()?.parse().ok()){
+                Some(id) => id,
+                None => continue,
+            };
+            if !movies.iter().any(|movie| movie.id == id){
+                movies.extend(self.nfo_movie(id));
+            }
+        }
+        Ok(movies)
+    }
+
+    fn nfo_movie(&self, id: u64) -> Option<Movie>{
+        let nfo = Nfo::read(&self.root.join(format!("movie/{}.nfo", id)))?;
+        Some(Movie{
+            id,
+            original_title: nfo.title.clone(),
+            title: nfo.title,
+            overview: Some(nfo.plot),
+            release_date: nfo.premiered,
+            status: "Released".to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn nfo_episode(&self, folder: &Path, season_number: u64, episode_number: u64) -> Option<TvEpisode>{
+        let nfo = Nfo::read(&folder.join(format!("{}.nfo", episode_number)))?;
+        Some(TvEpisode{
+            id: nfo.tmdb_id?,
+            name: nfo.title,
+            overview: Some(nfo.plot),
+            air_date: nfo.premiered,
+            season_number,
+            episode_number,
+            ..Default::default()
+        })
+    }
+}
+
+fn matches(query: &str, titles: &[&str]) -> bool{
+    let query = query.to_lowercase();
+    titles.iter().any(|title| title.to_lowercase().contains(&query))
+}
+
+impl MetadataProvider for LocalProvider{
+    fn name(&self) -> String{
+        self.root.to_string_lossy().to_string()
+    }
+
+    fn search_movie(&self, title: &str, year: Option<u64>) -> PyResult<Vec<SearchMovie>>{
+        Ok(self.movies()?.into_iter()
+            .filter(|movie| matches(title, &[&movie.title, &movie.original_title]))
+            .filter(|movie| year.is_none_or(|year| movie.release_date.starts_with(&year.to_string())))
+            .map(|movie| SearchMovie{
+                id: movie.id,
+                title: movie.title,
+                original_title: movie.original_title,
+                original_language: movie.original_language,
+                overview: movie.overview,
+                release_date: Some(movie.release_date),
+                genre_ids: movie.genres.iter().map(|genre| genre.id as u16).collect(),
+                poster_path: movie.poster_path,
+                backdrop_path: movie.backdrop_path,
+                popularity: movie.popularity,
+                adult: movie.adult,
+                vote_count: movie.vote_count,
+                vote_average: movie.vote_average,
+            })
+            .collect())
+    }
+
+    fn search_tv(&self, title: &str) -> PyResult<Vec<SearchTv>>{
+        Ok(self.all::<Tv>("tv")?.into_iter()
+            .filter(|tv| matches(title, &[&tv.name, &tv.original_name]))
+            .map(|tv| SearchTv{
+                id: tv.id,
+                name: tv.name,
+                original_name: tv.original_name,
+                original_language: tv.original_language,
+                original_country: Some(tv.origin_country),
+                overview: tv.overview,
+                first_air_date: tv.first_air_date,
+                genre_ids: tv.genres.iter().map(|genre| genre.id as u16).collect(),
+                poster_path: tv.poster_path,
+                backdrop_path: tv.backdrop_path,
+                popularity: tv.popularity,
+                vote_count: tv.vote_count,
+                vote_average: tv.vote_average,
+            })
+            .collect())
+    }
+
+    fn get_movie(&self, id: u64) -> PyResult<Option<Movie>>{
+        match self.json(&self.root.join(format!("movie/{}.json", id)))?{
+            Some(movie) => Ok(Some(movie)),
+            None => Ok(self.nfo_movie(id)),
+        }
+    }
+
+    fn get_tv(&self, id: u64) -> PyResult<Option<Tv>>{
+        self.json(&self.root.join(format!("tv/{}.json", id)))
+    }
+
+    fn get_episode(&self, tv_id: u64, season_number: u64, episode_number: u64) -> PyResult<Option<TvEpisode>>{
+        let folder = self.root.join(format!("tv/{}/season/{}/episode", tv_id, season_number));
+        match self.json(&folder.join(format!("{}.json", episode_number)))?{
+            Some(episode) => Ok(Some(episode)),
+            None => Ok(self.nfo_episode(&folder, season_number, episode_number)),
+        }
+    }
+
+    fn get_person(&self, id: u64) -> PyResult<Option<Person>>{
+        self.json(&self.root.join(format!("person/{}.json", id)))
+    }
+
+    fn get_collection(&self, id: u64) -> PyResult<Option<CollectionDetails>>{
+        self.json(&self.root.join(format!("collection/{}.json", id)))
+    }
+
+    /// the directory has no IMDb ids
+    fn find_imdb(&self, _imdb_id: &str) -> PyResult<Option<FindResults>>{
+        Ok(None)
+    }
+
+    fn image_url(&self, rsc_path: &str) -> Option<String>{
+        let path = self.root.join("images").join(rsc_path.trim_start_matches('/'));
+        if path.is_file(){
+            Some(path.to_string_lossy().to_string())
+        }
+        else{
+            None
+        }
+    }
+}
+
+/// Ordered providers of a library, the first one knowing a media answers.
+/// Errors are only raised when no provider answered, the first error then.
+#[derive(Clone)]
+pub struct Providers(pub Vec<Arc<dyn MetadataProvider>>);
+
+impl Providers{
+    /// "tmdb" or the directory of a `LocalProvider`
+    pub fn parse(names: &[String], tmdb: &Arc<Mutex<Option<Client>>>) -> PyResult<Providers>{
+        let mut providers: Vec<Arc<dyn MetadataProvider>> = Vec::new();
+        for name in names{
+            if name == "tmdb"{
+                providers.push(Arc::new(TmdbProvider::new(tmdb.clone())));
+            }
+            else{
+                providers.push(Arc::new(LocalProvider::new(name)?));
+            }
+        }
+        Ok(Providers(providers))
+    }
+
+    pub fn names(&self) -> Vec<String>{
+        self.0.iter().map(|provider| provider.name()).collect()
+    }
+
+    fn first<T, F: Fn(&dyn MetadataProvider) -> PyResult<Option<T>>>(&self, get: F) -> PyResult<Option<T>>{
+        let mut error = None;
+        for provider in &self.0{
+            match get(provider.as_ref()){
+                Ok(Some(found)) => return Ok(Some(found)),
+                Ok(None) => (),
+                Err(e) => {
+                    error.get_or_insert(e);
+                },
+            }
+        }
+        match error{
+            Some(e) => Err(e),
+            None => Ok(None),
+        }
+    }
+
+    fn found<T>(&self, what: String, get: PyResult<Option<T>>) -> PyResult<T>{
+        get?.ok_or_else(|| Error::new(ErrorKind::NotFound, what, "providers").into())
+    }
+
+    /// results of the first provider finding something
+    pub fn search_movie(&self, title: &str, year: Option<u64>) -> PyResult<Vec<SearchMovie>>{
+        Ok(self.first(|provider| {
+            let results = provider.search_movie(title, year)?;
+            Ok(if results.is_empty() { None } else { Some(results) })
+        })?.unwrap_or_default())
+    }
+
+    pub fn search_tv(&self, title: &str) -> PyResult<Vec<SearchTv>>{
+        Ok(self.first(|provider| {
+            let results = provider.search_tv(title)?;
+            Ok(if results.is_empty() { None } else { Some(results) })
+        })?.unwrap_or_default())
+    }
+
+    pub fn get_movie(&self, id: u64) -> PyResult<Movie>{
+        self.found(format!("movie {}", id), self.first(|provider| provider.get_movie(id)))
+    }
+
+    pub fn get_tv(&self, id: u64) -> PyResult<Tv>{
+        self.found(format!("tv {}", id), self.first(|provider| provider.get_tv(id)))
+    }
+
+    pub fn get_episode(&self, tv_id: u64, season_number: u64, episode_number: u64) -> PyResult<TvEpisode>{
+        self.found(format!("tv {} s{}e{}", tv_id, season_number, episode_number),
+                    self.first(|provider| provider.get_episode(tv_id, season_number, episode_number)))
+    }
+
+    pub fn get_person(&self, id: u64) -> PyResult<Person>{
+        self.found(format!("person {}", id), self.first(|provider| provider.get_person(id)))
+    }
+
+    pub fn get_collection(&self, id: u64) -> PyResult<CollectionDetails>{
+        self.found(format!("collection {}", id), self.first(|provider| provider.get_collection(id)))
+    }
+
+    /// results of the first provider knowing `imdb_id`, `None` when none does
+    pub fn find_imdb(&self, imdb_id: &str) -> PyResult<Option<FindResults>>{
+        self.first(|provider| {
+            let found = provider.find_imdb(imdb_id)?;
+            Ok(found.filter(|found| !found.movie_results.is_empty() || !found.tv_results.is_empty()))
+        })
+    }
+
+    /// bytes of the image `rsc_path`, from the first provider able to give it
+    pub fn image(&self, rsc_path: &str) -> PyResult<Vec<u8>>{
+        self.found(format!("image {}", rsc_path), self.first(|provider| {
+            let url = match provider.image_url(rsc_path){
+                Some(url) => url,
+                None => return Ok(None),
+            };
+            if !url.starts_with("http"){
+                return Ok(Some(fs::read(url)?))
+            }
+            match reqwest::blocking::get(&url).and_then(|resp| resp.error_for_status()).and_then(|resp| resp.bytes()){
+                Ok(bytes) => Ok(Some(bytes.to_vec())),
+                Err(e) => Err(Error::new(ErrorKind::NotFound, e.to_string(), &format!("provider.image {}", url)).into()),
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn providers() -> Providers{
+        let root = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/provider");
+        Providers(vec![Arc::new(LocalProvider::new(root).unwrap())])
+    }
+
+    #[test]
+    fn get_movie_json_and_nfo(){
+        let providers = providers();
+        let movie = providers.get_movie(603).unwrap();
+        assert_eq!(movie.title, "The Matrix");
+        assert_eq!(movie.runtime, Some(136));
+        let movie = providers.get_movie(55931).unwrap();
+        assert_eq!(movie.title, "The Animatrix");
+        assert_eq!(movie.release_date, "2003-06-03");
+        assert!(providers.get_movie(1).is_err());
+    }
+
+    #[test]
+    fn search_movie_json_and_nfo(){
+        let providers = providers();
+        let ids = |title, year| providers.search_movie(title, year).unwrap().iter().map(|movie| movie.id).collect::<Vec<_>>();
+        assert_eq!(ids("matrix", Some(1999)), vec![603]);
+        assert_eq!(ids("animatrix", None), vec![55931]);
+        let mut all = ids("matrix", None);
+        all.sort();
+        assert_eq!(all, vec![603, 55931]);
+        assert!(ids("matrix", Some(2021)).is_empty());
+    }
+}
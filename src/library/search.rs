@@ -0,0 +1,46 @@
+use pyo3::prelude::*;
+
+use super::Context;
+use super::tv::EpisodeSearch;
+
+/// One hit of `Library.search`, `kind` tells which object `full` returns
+#[pyclass]
+#[derive(Debug, Serialize, Clone)]
+pub struct SearchResult{
+    pub user: String,
+    #[serde(skip)]
+    pub ctx: Context,
+    #[pyo3(get)]
+    pub kind: String,
+    #[pyo3(get)]
+    pub id: u64,
+    #[pyo3(get)]
+    pub title: String,
+    #[pyo3(get)]
+    pub snippet: String,
+    #[pyo3(get)]
+    pub score: f64,
+}
+
+#[pymethods]
+impl SearchResult{
+    /// the Movie, Tv, Episode, Person or Collection this result points to
+    pub fn full(&self, py: Python) -> PyResult<Option<PyObject>>{
+        Ok(match self.kind.as_ref(){
+            "movie" => self.ctx.db.get_movie(&self.ctx, &self.user, self.id)?.map(|movie| movie.into_py(py)),
+            "tv" => self.ctx.db.get_tv(&self.ctx, &self.user, self.id)?.map(|tv| tv.into_py(py)),
+            "episode" => EpisodeSearch::new(&self.ctx, &self.user).id(self.id)?.last()?.map(|episode| episode.into_py(py)),
+            "person" => self.ctx.db.get_person(&self.ctx, &self.user, self.id)?.map(|person| person.into_py(py)),
+            "collection" => self.ctx.db.get_collection(&self.ctx, &self.user, self.id)?.map(|collection| collection.into_py(py)),
+            _ => None,
+        })
+    }
+
+    fn __str__(&self) -> PyResult<String>{
+        Ok(format!("{:?}", self))
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("{:?}", self))
+    }
+}
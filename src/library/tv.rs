@@ -1,22 +1,29 @@
-use std::collections::HashMap;
-
 use pyo3::prelude::*;
 
-use crate::database::DATABASE;
+use super::{Context, Error, ErrorKind};
+use super::page::{self, search_iterator, Batched, Page};
+use super::filter::{search_fields, parse_field, parse_order_by, py_values, Condition, Expr, Filter, Operator, Sort};
 
 use super::cast::Cast;
 use super::cast::Crew;
+use super::chapter::Markers;
 use super::collection::CollectionResult;
 use super::collection::CollectionSearch;
 use super::keyword::Keyword;
+use super::production::{Company, Country, Language};
 use super::trailer::Trailer;
 use super::video::VideoResult;
 use super::video::VideoSearch;
+use super::update_db;
+use super::overrides::Editable;
+use super::nfo;
 
 #[pyclass]
 #[derive(Debug, Serialize, Clone)]
 pub struct Tv{
     pub user: String,
+    #[serde(skip)]
+    pub ctx: Context,
     #[pyo3(get)]
     pub id: u64,
     #[pyo3(get)]
@@ -25,6 +32,9 @@ pub struct Tv{
     pub original_language: String,
     #[pyo3(get)]
     pub title: String,
+    /// title used to sort, the title unless edited
+    #[pyo3(get)]
+    pub sort_title: String,
     #[pyo3(get)]
     pub release_date: String,
     #[pyo3(get)]
@@ -58,12 +68,23 @@ pub struct Tv{
     #[pyo3(get)]
     pub crew: Vec<Crew>,
     #[pyo3(get)]
+    pub creators: Vec<Crew>,
+    #[pyo3(get)]
     pub trailer: Vec<Trailer>,
     #[pyo3(get)]
     pub keyword: Vec<Keyword>,
     #[pyo3(get)]
     pub collection: Vec<CollectionResult>,
     #[pyo3(get)]
+    pub companies: Vec<Company>,
+    #[pyo3(get)]
+    pub networks: Vec<Company>,
+    /// origin and production countries
+    #[pyo3(get)]
+    pub countries: Vec<Country>,
+    #[pyo3(get)]
+    pub languages: Vec<Language>,
+    #[pyo3(get)]
     pub watched: u64,
     #[pyo3(get)]
     pub updated: String,
@@ -73,37 +94,47 @@ pub struct Tv{
 impl Tv{
 
     pub fn set_seasons(&mut self) -> PyResult<()>{
-        self.seasons = DATABASE.get_seasons(&self.user, self.id)?;
+        self.seasons = self.ctx.db.get_seasons(&self.ctx, &self.user, self.id)?;
         Ok(())
     }
 
     pub fn set_persons(&mut self) -> PyResult<()>{
-        self.cast = DATABASE.get_tv_cast(&self.user, self.id)?;
-        self.crew = DATABASE.get_tv_crew(&self.user, self.id)?;
+        self.cast = self.ctx.db.get_tv_cast(&self.ctx, &self.user, self.id)?;
+        self.crew = self.ctx.db.get_tv_crew(&self.ctx, &self.user, self.id)?;
+        self.creators = self.ctx.db.get_tv_creators(&self.ctx, &self.user, self.id)?;
         Ok(())
     }
 
     pub fn set_trailers(&mut self) -> PyResult<()>{
-        self.trailer = DATABASE.get_tv_trailer(self.id)?;
+        self.trailer = self.ctx.db.get_tv_trailer(self.id)?;
         Ok(())
     }
 
     pub fn set_keywords(&mut self) -> PyResult<()>{
-        self.keyword = DATABASE.get_tv_keywords(self.id)?;
+        self.keyword = self.ctx.db.get_tv_keywords(self.id)?;
+        Ok(())
+    }
+
+    /// companies, networks, countries and languages
+    pub fn set_production(&mut self) -> PyResult<()>{
+        self.companies = self.ctx.db.get_companies("Companies", "TvCompanyLinks", "tv_id", "company_id", self.id)?;
+        self.networks = self.ctx.db.get_companies("Networks", "TvNetworkLinks", "tv_id", "network_id", self.id)?;
+        self.countries = self.ctx.db.get_countries("TvCountryLinks", "tv_id", self.id)?;
+        self.languages = self.ctx.db.get_languages("TvLanguageLinks", "tv_id", self.id)?;
         Ok(())
     }
 
     pub fn set_collection(&mut self) -> PyResult<()>{
-        self.collection = CollectionSearch::new(&self.user).tv(self.id)?.results(None, None)?;
+        self.collection = CollectionSearch::new(&self.ctx, &self.user).tv(self.id)?.results(None, None)?;
         Ok(())
     }
 
     pub fn season(&self, season_number: u64) -> PyResult<Option<Season>>{
-        Ok(DATABASE.get_season(&self.user, self.id, season_number)?)
+        Ok(self.ctx.db.get_season(&self.ctx, &self.user, self.id, season_number)?)
     }
 
     pub fn episode(&self, season_number: u64, episode_number: u64) -> PyResult<Option<Episode>>{
-        Ok(EpisodeSearch::new(&self.user).tv(self.id)?.season(season_number)?.episode(episode_number)?.last()?)
+        Ok(EpisodeSearch::new(&self.ctx, &self.user).tv(self.id)?.season(season_number)?.episode(episode_number)?.last()?)
     }
 
     pub fn set_watched(&mut self, b: bool) -> PyResult<()>{
@@ -115,11 +146,11 @@ impl Tv{
     }
 
     pub fn delete(&mut self) -> PyResult<()>{
-        if EpisodeSearch::new(&self.user).tv(self.id)?.exist()?{
+        if EpisodeSearch::new(&self.ctx, &self.user).tv(self.id)?.exist()?{
             return Ok(())
         }
         self.set_persons()?;
-        DATABASE.delete_tv(self.id)?;
+        self.ctx.db.delete_tv(self.id)?;
         for crew in &self.crew{
             crew.full()?.delete()?;
         }
@@ -129,6 +160,92 @@ impl Tv{
         Ok(())
     }
 
+    /// fetch the tv and its seasons again from TMDB, persons left without credits are removed
+    pub fn refresh(&mut self) -> PyResult<()>{
+        self.set_persons()?;
+        update_db::refresh_tv(&self.ctx, &self.user, self.id)?;
+        for crew in &self.crew{
+            crew.full()?.delete()?;
+        }
+        for cast in &self.cast{
+            cast.full()?.delete()?;
+        }
+        if let Some(tv) = self.ctx.db.get_tv(&self.ctx, &self.user, self.id)?{
+            *self = tv;
+        }
+        Ok(())
+    }
+
+    pub fn set_title(&mut self, title: String) -> PyResult<()>{
+        self.edit("title", &title)?;
+        self.title = title;
+        Ok(())
+    }
+
+    pub fn set_sort_title(&mut self, sort_title: String) -> PyResult<()>{
+        self.edit("sort_title", &sort_title)?;
+        self.sort_title = sort_title;
+        Ok(())
+    }
+
+    pub fn set_overview(&mut self, overview: String) -> PyResult<()>{
+        self.edit("overview", &overview)?;
+        self.overview = overview;
+        Ok(())
+    }
+
+    /// path of an image in the resource folder, relative to its `original` folder
+    pub fn set_poster_path(&mut self, poster_path: String) -> PyResult<()>{
+        self.edit("poster_path", &poster_path)?;
+        self.poster_path = poster_path;
+        Ok(())
+    }
+
+    pub fn set_backdrop_path(&mut self, backdrop_path: String) -> PyResult<()>{
+        self.edit("backdrop_path", &backdrop_path)?;
+        self.backdrop_path = backdrop_path;
+        Ok(())
+    }
+
+    pub fn set_release_date(&mut self, release_date: String) -> PyResult<()>{
+        self.edit("release_date", &release_date)?;
+        self.release_date = release_date;
+        Ok(())
+    }
+
+    /// names of existing tv genres
+    pub fn set_genres(&mut self, genres: Vec<String>) -> PyResult<()>{
+        self.edit("genres", &genres.join(","))?;
+        self.genres = genres;
+        Ok(())
+    }
+
+    /// fields edited by hand, refreshes leave them alone
+    pub fn locked_fields(&self) -> PyResult<Vec<String>>{
+        Ok(self.ctx.db.locked_fields(Editable::Tv, self.id)?)
+    }
+
+    /// write Kodi NFO files for the show and its episodes, and the artwork, returns the files written.
+    /// Files already there are kept unless `overwrite`.
+    pub fn export_nfo(&mut self, overwrite: Option<bool>) -> PyResult<Vec<String>>{
+        nfo::export_tv(self, overwrite.unwrap_or(false))
+    }
+
+    /// drop the edit of `field` and go back to the TMDB value
+    pub fn revert(&mut self, field: String) -> PyResult<()>{
+        self.ctx.db.revert_override(Editable::Tv, self.id, &field)?;
+        if let Some(tv) = self.ctx.db.get_tv(&self.ctx, &self.user, self.id)?{
+            self.title = tv.title;
+            self.sort_title = tv.sort_title;
+            self.overview = tv.overview;
+            self.poster_path = tv.poster_path;
+            self.backdrop_path = tv.backdrop_path;
+            self.release_date = tv.release_date;
+            self.genres = tv.genres;
+        }
+        Ok(())
+    }
+
     pub fn json(&self) -> PyResult<String>{
         return Ok(serde_json::to_string(self).unwrap())
     }
@@ -143,10 +260,19 @@ impl Tv{
 
 }
 
+impl Tv{
+    fn edit(&self, field: &str, value: &str) -> PyResult<()>{
+        Editable::Tv.check(&self.ctx, field, value)?;
+        Ok(self.ctx.db.set_override(Editable::Tv, self.id, field, value)?)
+    }
+}
+
 #[pyclass]
-#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[derive(Debug, PartialEq, Serialize, Clone)]
 pub struct TvResult{
     pub user: String,
+    #[serde(skip)]
+    pub ctx: Context,
     #[pyo3(get)]
     pub id: u64,
     #[pyo3(get)]
@@ -170,7 +296,7 @@ pub struct TvResult{
 #[pymethods]
 impl TvResult{
     pub fn full(&self) -> PyResult<Tv>{
-        Ok(DATABASE.get_tv(&self.user, self.id)?.unwrap())
+        Ok(self.ctx.db.get_tv(&self.ctx, &self.user, self.id)?.unwrap())
     }
 
     fn __str__(&self) -> PyResult<String>{
@@ -183,62 +309,136 @@ impl TvResult{
 }
 
 
+search_fields!{ TvField {
+    Id => "id" : "Tvs.id",
+    Title => "title" : "Tvs.title",
+    SortTitle => "sort_title" : "COALESCE(Tvs.sort_title, Tvs.title)",
+    OriginalTitle => "original_title" : "Tvs.original_title",
+    ReleaseDate => "release_date" : "Tvs.release_date",
+    VoteAverage => "vote_average" : "Tvs.vote_average",
+    VoteCount => "vote_count" : "Tvs.vote_count",
+    Popularity => "popularity" : "Tvs.popularity",
+    Status => "status" : "Tvs.status",
+    Adding => "adding" : "Videos.adding" / "MAX(Videos.adding)",
+    Genre => "genre" : "TvGenreLinks.genre_id",
+    Cast => "cast" : "TvCasts.person_id",
+    Crew => "crew" : "TvCrews.person_id",
+    Creator => "creator" : "TvCreators.person_id",
+    Collection => "collection" : "TvCollectionLinks.collection_id",
+    Company => "company" : "TvCompanyLinks.company_id",
+    Network => "network" : "TvNetworkLinks.network_id",
+    Country => "country" : "TvCountryLinks.country_code",
+    Language => "language" : "TvLanguageLinks.language_code",
+}}
+
 #[pyclass]
 #[derive(Debug, Clone)]
 pub struct TvSearch{
-   parameters: HashMap<String, Option<(String, String)>>,
+   parameters: Vec<Expr<TvField>>,
    user: String,
-   order_by: Option<String>,
+   ctx: Context,
+   order_by: Vec<Sort<TvField>>,
 }
 
 impl TvSearch{
-    pub fn new(user: &String) -> TvSearch{
+    pub fn new(ctx: &Context, user: &String) -> TvSearch{
         TvSearch{
-            parameters: HashMap::new(),
+            parameters: Vec::new(),
             user: user.clone(),
-            order_by: None,
+            ctx: ctx.clone(),
+            order_by: Vec::new(),
         }
     }
+
+    fn condition(&mut self, field: TvField, operator: Operator, values: Vec<String>) -> PyResult<TvSearch>{
+        self.parameters.push(Expr::Condition(Condition::new(field, operator, values)?));
+        Ok(self.clone())
+    }
 }
 
 #[pymethods]
 impl TvSearch{
     pub fn id(&mut self, id: u64) -> PyResult<TvSearch>{
-        self.find("Tvs.id", "=", Some(id.to_string()))
+        self.condition(TvField::Id, Operator::Eq, vec![id.to_string()])
     }
 
     pub fn cast(&mut self, person_id: u64) -> PyResult<TvSearch>{
-        self.find("TvCasts.person_id", "=", Some(person_id.to_string()))
+        self.condition(TvField::Cast, Operator::Eq, vec![person_id.to_string()])
     }
 
     pub fn crew(&mut self, person_id: u64) -> PyResult<TvSearch>{
-        self.find("TvCrews.person_id", "=", Some(person_id.to_string()))
+        self.condition(TvField::Crew, Operator::Eq, vec![person_id.to_string()])
+    }
+
+    pub fn creator(&mut self, person_id: u64) -> PyResult<TvSearch>{
+        self.condition(TvField::Creator, Operator::Eq, vec![person_id.to_string()])
     }
 
     pub fn collection(&mut self, collection_id: u64) -> PyResult<TvSearch>{
-        self.find("TvCollectionLinks.collection_id", "=", Some(collection_id.to_string()))
+        self.condition(TvField::Collection, Operator::Eq, vec![collection_id.to_string()])
     }
 
-    pub fn find(&mut self, column: &str, operator: &str, value: Option<String>) -> PyResult<TvSearch>{
-        if let Some(value) = value {
-            self.parameters.insert(column.to_string(), Some((operator.to_string(), value)));
-        }
-        else{
-            self.parameters.insert(column.to_string(), None);
-        }
+    pub fn company(&mut self, company_id: u64) -> PyResult<TvSearch>{
+        self.condition(TvField::Company, Operator::Eq, vec![company_id.to_string()])
+    }
+
+    pub fn network(&mut self, network_id: u64) -> PyResult<TvSearch>{
+        self.condition(TvField::Network, Operator::Eq, vec![network_id.to_string()])
+    }
+
+    /// from or produced in the ISO 3166-1 `code` country
+    pub fn country(&mut self, code: &str) -> PyResult<TvSearch>{
+        self.condition(TvField::Country, Operator::Eq, vec![code.to_uppercase()])
+    }
+
+    /// spoken in the ISO 639-1 `code` language
+    pub fn language(&mut self, code: &str) -> PyResult<TvSearch>{
+        self.condition(TvField::Language, Operator::Eq, vec![code.to_lowercase()])
+    }
+
+    pub fn filter(&mut self, field: TvField, operator: Operator, value: Option<&PyAny>) -> PyResult<TvSearch>{
+        self.condition(field, operator, py_values(value)?)
+    }
+
+    pub fn filter_by(&mut self, filter: Filter) -> PyResult<TvSearch>{
+        self.parameters.push(filter.expr.resolve()?);
         Ok(self.clone())
     }
+
+    pub fn find(&mut self, column: &str, operator: &str, value: Option<&PyAny>) -> PyResult<TvSearch>{
+        self.filter(parse_field(column)?, Operator::parse(operator)?, value)
+    }
+    pub fn sort(&mut self, field: TvField, descending: bool) -> PyResult<TvSearch>{
+        self.order_by.push(Sort{ field, descending });
+        Ok(self.clone())
+    }
+
     pub fn order_by(&mut self, order_by: String) -> PyResult<TvSearch>{
-        self.order_by = Some(order_by);
+        self.order_by = parse_order_by(&order_by)?;
         Ok(self.clone())
     }
 
     pub fn exist(&self) -> PyResult<bool>{
-        Ok(self.results(None, None)?.len() > 0)
-    } 
+        Ok(self.results(Some(1), None)?.len() > 0)
+    }
+
+    pub fn count(&self) -> PyResult<u64>{
+        Ok(Python::with_gil(|py| py.allow_threads(|| self.ctx.db.count_tvs(&self.user, &self.parameters)))?)
+    }
+
+    pub fn page(&self, size: u64, number: u64) -> PyResult<Page>{
+        let (limit, offset) = page::bounds(size, number)?;
+        Ok(Page::new(self.results(Some(limit), Some(offset))?, self.count()?, size, number))
+    }
+
+    pub fn json_page(&self, size: u64, number: u64) -> PyResult<String>{
+        let (limit, offset) = page::bounds(size, number)?;
+        let list = self.results(Some(limit), Some(offset))?;
+        Ok(page::json(&list, self.count()?, size, number))
+    }
 
     pub fn results(&self, limit: Option<u64>, offset: Option<u64>) -> PyResult<Vec<TvResult>>{
-        Ok(DATABASE.get_tvs(&self.user, &self.parameters, &self.order_by, limit, offset)?)
+        Ok(Python::with_gil(|py| py.allow_threads(|| self.ctx.db.get_tvs(&self.ctx, &self.user, &self.parameters, &self.order_by, limit, offset)))?)
     }
 
     pub fn json_results(&self, limit: Option<u64>, offset: Option<u64>) -> PyResult<String>{
@@ -259,10 +459,35 @@ impl TvSearch{
     }
 }
 
+impl Batched for TvSearch{
+    type Item = TvResult;
+
+    fn sorted(&self) -> bool{
+        !self.order_by.is_empty()
+    }
+
+    fn batch(&self, after: Option<u64>, offset: Option<u64>, size: u64) -> PyResult<Vec<TvResult>>{
+        let mut search = self.clone();
+        if let Some(id) = after{
+            search.condition(TvField::Id, Operator::Gt, vec![id.to_string()])?;
+        }
+        search.order_by.push(Sort{ field: TvField::Id, descending: false });
+        search.results(Some(size), offset)
+    }
+
+    fn item_id(item: &TvResult) -> u64{
+        item.id
+    }
+}
+
+search_iterator!(TvIterator, TvSearch);
+
 #[pyclass]
 #[derive(Debug, Serialize, Clone)]
 pub struct Season{
     pub user: String,
+    #[serde(skip)]
+    pub ctx: Context,
     #[pyo3(get)]
     pub tv_id: u64,
     #[pyo3(get)]
@@ -285,22 +510,24 @@ pub struct Season{
     pub watched: u64,
     #[pyo3(get)]
     pub updated: String,
+    #[pyo3(get)]
+    pub markers: Markers,
 }
 
 #[pymethods]
 impl Season{
 
     pub fn set_tv(&mut self) -> PyResult<()>{
-        self.tv = DATABASE.get_tv(&self.user, self.tv_id)?;
+        self.tv = self.ctx.db.get_tv(&self.ctx, &self.user, self.tv_id)?;
         Ok(())
     }
 
     pub fn episode(&mut self, episode_number: u64) -> PyResult<Option<Episode>>{
-        Ok(EpisodeSearch::new(&self.user).tv(self.tv_id)?.season(self.season_number)?.episode(episode_number)?.last()?)
+        Ok(EpisodeSearch::new(&self.ctx, &self.user).tv(self.tv_id)?.season(self.season_number)?.episode(episode_number)?.last()?)
     }
 
     pub fn set_episodes(&mut self) -> PyResult<()>{
-        self.episodes = EpisodeSearch::new(&self.user).tv(self.tv_id)?.season(self.season_number)?.results(None, None)?;
+        self.episodes = EpisodeSearch::new(&self.ctx, &self.user).tv(self.tv_id)?.season(self.season_number)?.results(None, None)?;
         Ok(())
     }
 
@@ -319,6 +546,55 @@ impl Season{
         Ok(())
     }
 
+    pub fn set_markers(&mut self, intro_start: Option<u64>, intro_end: Option<u64>, credits_start: Option<u64>) -> PyResult<()>{
+        let markers = Markers{ intro_start, intro_end, credits_start };
+        self.ctx.db.set_season_markers(self.tv_id, self.season_number, &markers)?;
+        self.markers = markers;
+        Ok(())
+    }
+
+    pub fn set_title(&mut self, title: String) -> PyResult<()>{
+        self.edit("title", &title)?;
+        self.title = title;
+        Ok(())
+    }
+
+    pub fn set_overview(&mut self, overview: String) -> PyResult<()>{
+        self.edit("overview", &overview)?;
+        self.overview = overview;
+        Ok(())
+    }
+
+    /// path of an image in the resource folder, relative to its `original` folder
+    pub fn set_poster_path(&mut self, poster_path: String) -> PyResult<()>{
+        self.edit("poster_path", &poster_path)?;
+        self.poster_path = poster_path;
+        Ok(())
+    }
+
+    pub fn set_release_date(&mut self, release_date: String) -> PyResult<()>{
+        self.edit("release_date", &release_date)?;
+        self.release_date = release_date;
+        Ok(())
+    }
+
+    /// fields edited by hand, refreshes leave them alone
+    pub fn locked_fields(&self) -> PyResult<Vec<String>>{
+        Ok(self.ctx.db.locked_fields(Editable::Season, self.season_id()?)?)
+    }
+
+    /// drop the edit of `field` and go back to the TMDB value
+    pub fn revert(&mut self, field: String) -> PyResult<()>{
+        self.ctx.db.revert_override(Editable::Season, self.season_id()?, &field)?;
+        if let Some(season) = self.ctx.db.get_season(&self.ctx, &self.user, self.tv_id, self.season_number)?{
+            self.title = season.title;
+            self.overview = season.overview;
+            self.poster_path = season.poster_path;
+            self.release_date = season.release_date;
+        }
+        Ok(())
+    }
+
     pub fn json(&self) -> PyResult<String>{
         return Ok(serde_json::to_string(self).unwrap())
     }
@@ -333,72 +609,136 @@ impl Season{
 
 }
 
+impl Season{
+    fn season_id(&self) -> PyResult<u64>{
+        match self.ctx.db.get_season_id(self.tv_id, self.season_number)?{
+            Some(season_id) => Ok(season_id),
+            None => Err(Error::new(ErrorKind::NotFound, format!("season {} of tv {}", self.season_number, self.tv_id), "season.season_id").into()),
+        }
+    }
+
+    fn edit(&self, field: &str, value: &str) -> PyResult<()>{
+        Editable::Season.check(&self.ctx, field, value)?;
+        Ok(self.ctx.db.set_override(Editable::Season, self.season_id()?, field, value)?)
+    }
+}
+
+
+search_fields!{ EpisodeField {
+    Id => "id" : "Episodes.id",
+    TvId => "tv_id" : "Episodes.tv_id",
+    SeasonNumber => "season_number" : "Episodes.season_number",
+    EpisodeNumber => "episode_number" : "Episodes.episode_number",
+    ReleaseDate => "release_date" : "Episodes.release_date",
+    Title => "title" : "Episodes.title",
+    VoteAverage => "vote_average" : "Episodes.vote_average",
+    Adding => "adding" : "Videos.adding",
+    Watched => "watched" : "EpisodesUserWatched.watched",
+    Cast => "cast" : "EpisodeCasts.person_id",
+    GuestStar => "guest_star" : "CAST(CASE WHEN EpisodeCasts.guest THEN EpisodeCasts.person_id END AS INTEGER)",
+    Crew => "crew" : "EpisodeCrews.person_id",
+}}
 
 #[pyclass]
 #[derive(Debug, Clone)]
 pub struct EpisodeSearch{
-   parameters: HashMap<String, Option<(String, String)>>,
+   parameters: Vec<Expr<EpisodeField>>,
    user: String,
-   order_by: Option<String>,
+   ctx: Context,
+   order_by: Vec<Sort<EpisodeField>>,
 }
 
 impl EpisodeSearch{
-    pub fn new(user: &String) -> EpisodeSearch{
+    pub fn new(ctx: &Context, user: &String) -> EpisodeSearch{
         EpisodeSearch{
-            parameters: HashMap::new(),
+            parameters: Vec::new(),
             user: user.clone(),
-            order_by: None,
+            ctx: ctx.clone(),
+            order_by: Vec::new(),
         }
     }
+
+    fn condition(&mut self, field: EpisodeField, operator: Operator, values: Vec<String>) -> PyResult<EpisodeSearch>{
+        self.parameters.push(Expr::Condition(Condition::new(field, operator, values)?));
+        Ok(self.clone())
+    }
 }
 
 #[pymethods]
 impl EpisodeSearch{
-    pub fn find(&mut self, column: &str, operator: &str, value: Option<String>) -> PyResult<EpisodeSearch>{
-        if let Some(value) = value {
-            self.parameters.insert(column.to_string(), Some((operator.to_string(), value)));
-        }
-        else{
-            self.parameters.insert(column.to_string(), None);
-        }
+    pub fn filter(&mut self, field: EpisodeField, operator: Operator, value: Option<&PyAny>) -> PyResult<EpisodeSearch>{
+        self.condition(field, operator, py_values(value)?)
+    }
+
+    pub fn filter_by(&mut self, filter: Filter) -> PyResult<EpisodeSearch>{
+        self.parameters.push(filter.expr.resolve()?);
         Ok(self.clone())
     }
 
+    pub fn find(&mut self, column: &str, operator: &str, value: Option<&PyAny>) -> PyResult<EpisodeSearch>{
+        self.filter(parse_field(column)?, Operator::parse(operator)?, value)
+    }
+
     pub fn id(&mut self, episode_id: u64) -> PyResult<EpisodeSearch>{
-        self.find("Episodes.id", "=", Some(episode_id.to_string()))
+        self.condition(EpisodeField::Id, Operator::Eq, vec![episode_id.to_string()])
     }
 
     pub fn season(&mut self, season_number: u64) -> PyResult<EpisodeSearch>{
-        self.find("Episodes.season_number", "=", Some(season_number.to_string()))
+        self.condition(EpisodeField::SeasonNumber, Operator::Eq, vec![season_number.to_string()])
     }
 
     pub fn episode(&mut self, episode_number: u64) -> PyResult<EpisodeSearch>{
-        self.find("Episodes.episode_number", "=", Some(episode_number.to_string()))
+        self.condition(EpisodeField::EpisodeNumber, Operator::Eq, vec![episode_number.to_string()])
     }
 
     pub fn tv(&mut self, tv_id: u64) -> PyResult<EpisodeSearch>{
-        self.find("Episodes.tv_id", "=", Some(tv_id.to_string()))
+        self.condition(EpisodeField::TvId, Operator::Eq, vec![tv_id.to_string()])
     }
 
     pub fn cast(&mut self, person_id: u64) -> PyResult<EpisodeSearch>{
-        self.find("EpisodeCasts.person_id", "=", Some(person_id.to_string()))
+        self.condition(EpisodeField::Cast, Operator::Eq, vec![person_id.to_string()])
     }
 
     pub fn crew(&mut self, person_id: u64) -> PyResult<EpisodeSearch>{
-        self.find("EpisodeCrews.person_id", "=", Some(person_id.to_string()))
+        self.condition(EpisodeField::Crew, Operator::Eq, vec![person_id.to_string()])
+    }
+
+    /// episodes `person_id` guest stars in, `cast` includes them too
+    pub fn guest_star(&mut self, person_id: u64) -> PyResult<EpisodeSearch>{
+        self.condition(EpisodeField::GuestStar, Operator::Eq, vec![person_id.to_string()])
+    }
+
+    pub fn sort(&mut self, field: EpisodeField, descending: bool) -> PyResult<EpisodeSearch>{
+        self.order_by.push(Sort{ field, descending });
+        Ok(self.clone())
     }
 
     pub fn order_by(&mut self, order_by: String) -> PyResult<EpisodeSearch>{
-        self.order_by = Some(order_by);
+        self.order_by = parse_order_by(&order_by)?;
         Ok(self.clone())
     }
 
     pub fn exist(&self) -> PyResult<bool>{
-        Ok(self.results(None, None)?.len() > 0)
-    } 
+        Ok(self.results(Some(1), None)?.len() > 0)
+    }
+
+    pub fn count(&self) -> PyResult<u64>{
+        Ok(Python::with_gil(|py| py.allow_threads(|| self.ctx.db.count_episodes(&self.user, &self.parameters)))?)
+    }
+
+    pub fn page(&self, size: u64, number: u64) -> PyResult<Page>{
+        let (limit, offset) = page::bounds(size, number)?;
+        Ok(Page::new(self.results(Some(limit), Some(offset))?, self.count()?, size, number))
+    }
+
+    pub fn json_page(&self, size: u64, number: u64) -> PyResult<String>{
+        let (limit, offset) = page::bounds(size, number)?;
+        let list = self.results(Some(limit), Some(offset))?;
+        Ok(page::json(&list, self.count()?, size, number))
+    }
 
     pub fn results(&self, limit: Option<u64>, offset: Option<u64>) -> PyResult<Vec<Episode>>{
-        Ok(DATABASE.get_episodes(&self.user, &self.parameters, &self.order_by, limit, offset)?)
+        Ok(Python::with_gil(|py| py.allow_threads(|| self.ctx.db.get_episodes(&self.ctx, &self.user, &self.parameters, &self.order_by, limit, offset)))?)
     }
 
     pub fn json_results(&self, limit: Option<u64>, offset: Option<u64>) -> PyResult<String>{
@@ -419,11 +759,36 @@ impl EpisodeSearch{
     }
 }
 
+impl Batched for EpisodeSearch{
+    type Item = Episode;
+
+    fn sorted(&self) -> bool{
+        !self.order_by.is_empty()
+    }
+
+    fn batch(&self, after: Option<u64>, offset: Option<u64>, size: u64) -> PyResult<Vec<Episode>>{
+        let mut search = self.clone();
+        if let Some(id) = after{
+            search.condition(EpisodeField::Id, Operator::Gt, vec![id.to_string()])?;
+        }
+        search.order_by.push(Sort{ field: EpisodeField::Id, descending: false });
+        search.results(Some(size), offset)
+    }
+
+    fn item_id(item: &Episode) -> u64{
+        item.id
+    }
+}
+
+search_iterator!(EpisodeIterator, EpisodeSearch);
+
 
 #[pyclass]
 #[derive(Debug, Serialize, Clone)]
 pub struct Episode{
     pub user: String,
+    #[serde(skip)]
+    pub ctx: Context,
     #[pyo3(get)]
     pub tv_id: u64,
     #[pyo3(get)]
@@ -457,8 +822,12 @@ pub struct Episode{
     #[pyo3(get)]
     pub tv_title: String,
     #[pyo3(get)]
+    pub still_path: String,
+    #[pyo3(get)]
     pub cast: Vec<Cast>,
     #[pyo3(get)]
+    pub guest_stars: Vec<Cast>,
+    #[pyo3(get)]
     pub crew: Vec<Crew>,
 
 }
@@ -467,54 +836,111 @@ pub struct Episode{
 impl Episode{
 
     pub fn set_tv(&mut self) -> PyResult<()>{
-        self.tv = DATABASE.get_tv(&self.user, self.tv_id)?;
+        self.tv = self.ctx.db.get_tv(&self.ctx, &self.user, self.tv_id)?;
         Ok(())
     }
 
     pub fn set_season(&mut self) -> PyResult<()>{
-        self.season = DATABASE.get_season(&self.user, self.tv_id, self.season_number)?;
+        self.season = self.ctx.db.get_season(&self.ctx, &self.user, self.tv_id, self.season_number)?;
         Ok(())
     }
 
     pub fn set_videos(&mut self) -> PyResult<()>{
-        self.video = VideoSearch::new(&self.user).tv()?.media_id(self.id)?.results(None, None)?;
+        self.video = VideoSearch::new(&self.ctx, &self.user).tv()?.media_id(self.id)?.results(None, None)?;
         Ok(())
     }
 
     pub fn set_persons(&mut self) -> PyResult<()>{
-        self.cast = DATABASE.get_episode_cast(&self.user, self.id)?;
-        self.crew = DATABASE.get_episode_crew(&self.user, self.id)?;
+        self.cast = self.ctx.db.get_episode_cast(&self.ctx, &self.user, self.id, false)?;
+        self.guest_stars = self.ctx.db.get_episode_cast(&self.ctx, &self.user, self.id, true)?;
+        self.crew = self.ctx.db.get_episode_crew(&self.ctx, &self.user, self.id)?;
         Ok(())
     }
 
     pub fn set_watched(&self, b: bool) -> PyResult<()>{
         if b{
-            Ok(DATABASE.set_episode_watched(self.user.clone(), self.id, self.watched+1)?)
+            Ok(self.ctx.db.set_episode_watched(self.user.clone(), self.id, self.watched+1)?)
         }
         else{
-            Ok(DATABASE.set_episode_watched(self.user.clone(), self.id, 0)?)
+            Ok(self.ctx.db.set_episode_watched(self.user.clone(), self.id, 0)?)
         }
         
     }
 
     pub fn delete(&mut self) -> PyResult<()>{
-        if VideoSearch::new(&self.user).tv()?.media_id(self.id)?.exist()?{
+        if VideoSearch::new(&self.ctx, &self.user).tv()?.media_id(self.id)?.exist()?{
             return Ok(())
         }
 
         self.set_tv()?;
+        self.set_persons()?;
 
-        DATABASE.delete_episode(self.id)?;
+        self.ctx.db.delete_episode(self.id)?;
 
         if let Some(tv) = &mut self.tv{
             tv.delete()?;
         }
 
-        //todo person remove for episode
+        for crew in &self.crew{
+            crew.full()?.delete()?;
+        }
+        for cast in &self.cast{
+            cast.full()?.delete()?;
+        }
 
         Ok(())
     }
 
+    /// fetch the episode again from TMDB, persons left without credits are removed
+    pub fn refresh(&mut self) -> PyResult<()>{
+        self.set_persons()?;
+        update_db::refresh_episode(&self.ctx, &self.user, self.tv_id, self.season_number, self.episode_number)?;
+        for crew in &self.crew{
+            crew.full()?.delete()?;
+        }
+        for cast in &self.cast{
+            cast.full()?.delete()?;
+        }
+        if let Some(episode) = EpisodeSearch::new(&self.ctx, &self.user).id(self.id)?.last()?{
+            *self = episode;
+        }
+        Ok(())
+    }
+
+    pub fn set_title(&mut self, title: String) -> PyResult<()>{
+        self.edit("title", &title)?;
+        self.title = title;
+        Ok(())
+    }
+
+    pub fn set_overview(&mut self, overview: String) -> PyResult<()>{
+        self.edit("overview", &overview)?;
+        self.overview = overview;
+        Ok(())
+    }
+
+    pub fn set_release_date(&mut self, release_date: String) -> PyResult<()>{
+        self.edit("release_date", &release_date)?;
+        self.release_date = release_date;
+        Ok(())
+    }
+
+    /// fields edited by hand, refreshes leave them alone
+    pub fn locked_fields(&self) -> PyResult<Vec<String>>{
+        Ok(self.ctx.db.locked_fields(Editable::Episode, self.id)?)
+    }
+
+    /// drop the edit of `field` and go back to the TMDB value
+    pub fn revert(&mut self, field: String) -> PyResult<()>{
+        self.ctx.db.revert_override(Editable::Episode, self.id, &field)?;
+        if let Some(episode) = EpisodeSearch::new(&self.ctx, &self.user).id(self.id)?.last()?{
+            self.title = episode.title;
+            self.overview = episode.overview;
+            self.release_date = episode.release_date;
+        }
+        Ok(())
+    }
+
     pub fn json(&self) -> PyResult<String>{
         return Ok(serde_json::to_string(self).unwrap())
     }
@@ -528,3 +954,10 @@ impl Episode{
     }
 
 }
+
+impl Episode{
+    fn edit(&self, field: &str, value: &str) -> PyResult<()>{
+        Editable::Episode.check(&self.ctx, field, value)?;
+        Ok(self.ctx.db.set_override(Editable::Episode, self.id, field, value)?)
+    }
+}
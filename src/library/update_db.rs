@@ -1,87 +1,175 @@
 use std::io;
 use std::fs::File;
+use std::collections::HashSet;
 
 use pyo3::prelude::*;
-use pyo3::exceptions::PyReferenceError;
 
-use crate::{rustmdb::{get_movie, get_person, get_tv, get_tv_episode}, database::DATABASE};
+use crate::rustmdb::model::Person;
+use super::{Context, movie::MovieSearch, tv::{TvSearch, EpisodeSearch}, cast::PersonSearch};
+use super::import::Import;
+use super::local::is_local;
+use super::{Error, ErrorKind};
 
-use super::{RSCPATH, movie::MovieSearch, tv::{TvSearch, EpisodeSearch}, cast::PersonSearch};
 
-
-pub fn create_movie(user: &String, movie_id: u64) -> PyResult<()>{
-    if MovieSearch::new(user).id(movie_id)?.exist()?{
-        return Ok(())
+/// Run `import` while it is recorded as pending, it is only cleared once everything, images included, is stored.
+/// Metadata is fetched before the single transaction that writes it, so a failure never leaves half a media in the database.
+fn tracked<T, F: FnOnce() -> PyResult<T>>(ctx: &Context, import: &Import, run: F) -> PyResult<T>{
+    ctx.db.begin_import(import)?;
+    match run(){
+        Ok(result) => {
+            ctx.db.end_import(import)?;
+            Ok(result)
+        },
+        Err(e) => {
+            ctx.db.fail_import(import, &e.to_string())?;
+            Err(e)
+        },
     }
-    let movie = get_movie(movie_id)?;
-    let (person_ids, rsc_paths) = DATABASE.create_movie(&movie)?;
+}
+
+/// fetch the persons of `person_ids` the library does not hold yet
+fn missing_persons(ctx: &Context, user: &String, person_ids: Vec<u64>) -> PyResult<Vec<Person>>{
+    let mut seen = HashSet::new();
+    let mut persons = Vec::new();
     for person_id in person_ids{
-        create_person(user, person_id)?;
+        if !seen.insert(person_id) || PersonSearch::new(ctx, user).id(person_id)?.exist()?{
+            continue
+        }
+        persons.push(ctx.providers().get_person(person_id)?);
     }
+    Ok(persons)
+}
+
+fn update_rscs(ctx: &Context, rsc_paths: Vec<String>) -> PyResult<()>{
     for rsc_path in rsc_paths{
-        update_rsc(&rsc_path)?;
+        update_rsc(ctx, &rsc_path)?;
     }
-
     Ok(())
 }
 
-
-pub fn create_person(user: &String, person_id: u64) -> PyResult<()>{
-    if PersonSearch::new(user).id(person_id)?.exist()?{
+pub fn create_movie(ctx: &Context, user: &String, movie_id: u64) -> PyResult<()>{
+    if MovieSearch::new(ctx, user).id(movie_id)?.exist()? && !ctx.db.is_pending(&Import::Movie(movie_id))?{
         return Ok(())
     }
-    let person = get_person(person_id)?;
-    let (_person_ids, rsc_paths) = DATABASE.create_person(&person)?;
-    for rsc_path in rsc_paths{
-        update_rsc(&rsc_path)?;
+    refresh_movie(ctx, user, movie_id)
+}
+
+/// fetch the movie again and rewrite it, whether it is already in the library or not
+pub fn refresh_movie(ctx: &Context, user: &String, movie_id: u64) -> PyResult<()>{
+    if is_local(movie_id){
+        return Ok(())
     }
-    Ok(())
+    tracked(ctx, &Import::Movie(movie_id), || {
+        let movie = ctx.providers().get_movie(movie_id)?;
+        let persons = missing_persons(ctx, user, movie.credits.person_ids())?;
+        let rsc_paths = ctx.db.create_movie(&movie, &persons)?;
+        update_rscs(ctx, rsc_paths)?;
+        Ok(movie.belongs_to_collection.map(|collection| collection.id))
+    }).map(|tmdb_id| {
+        // a franchise that fails to import stays pending, the movie is stored all the same
+        if let Some(tmdb_id) = tmdb_id{
+            create_franchise(ctx, tmdb_id).ok();
+        }
+    })
 }
 
-pub fn create_tv(user: &String, tv_id: u64) -> PyResult<()>{
-    if TvSearch::new(user).id(tv_id)?.exist()?{
+/// the franchise of a TMDB collection is fetched the first time one of its movies is added, `create_movie` links the next ones
+fn create_franchise(ctx: &Context, tmdb_id: u64) -> PyResult<()>{
+    if ctx.db.has_franchise(tmdb_id)? && !ctx.db.is_pending(&Import::Collection(tmdb_id))?{
         return Ok(())
     }
-    let tv = get_tv(tv_id)?;
-    let (person_ids, rsc_paths) = DATABASE.create_tv(&tv)?;
-    for person_id in person_ids{
-        create_person(user, person_id)?;
+    refresh_franchise(ctx, tmdb_id)
+}
+
+/// fetch a TMDB collection again and rewrite its franchise
+pub fn refresh_franchise(ctx: &Context, tmdb_id: u64) -> PyResult<()>{
+    tracked(ctx, &Import::Collection(tmdb_id), || {
+        let collection = ctx.providers().get_collection(tmdb_id)?;
+        let rsc_paths = ctx.db.update_franchise(&collection)?;
+        update_rscs(ctx, rsc_paths)
+    })
+}
+
+pub fn create_tv(ctx: &Context, user: &String, tv_id: u64) -> PyResult<()>{
+    if TvSearch::new(ctx, user).id(tv_id)?.exist()? && !ctx.db.is_pending(&Import::Tv(tv_id))?{
+        return Ok(())
     }
-    for rsc_path in rsc_paths{
-        update_rsc(&rsc_path)?;
+    refresh_tv(ctx, user, tv_id)
+}
+
+/// fetch the tv and its seasons again, episodes are refreshed on their own
+pub fn refresh_tv(ctx: &Context, user: &String, tv_id: u64) -> PyResult<()>{
+    if is_local(tv_id){
+        return Ok(())
     }
-    Ok(())
+    tracked(ctx, &Import::Tv(tv_id), || {
+        let tv = ctx.providers().get_tv(tv_id)?;
+        let mut person_ids = tv.credits.person_ids();
+        person_ids.extend(tv.created_by.iter().map(|creator| creator.id));
+        let persons = missing_persons(ctx, user, person_ids)?;
+        let rsc_paths = ctx.db.create_tv(&tv, &persons)?;
+        update_rscs(ctx, rsc_paths)
+    })
 }
 
-pub fn create_episode(user: &String, tv_id: u64, season_number: u64, episode_number: u64) -> PyResult<u64>{
-    if let Some(episode) = EpisodeSearch::new(user).tv(tv_id)?.season(season_number)?.episode(episode_number)?.last()?{
-        return Ok(episode.id)
+pub fn create_episode(ctx: &Context, user: &String, tv_id: u64, season_number: u64, episode_number: u64) -> PyResult<u64>{
+    if let Some(episode) = EpisodeSearch::new(ctx, user).tv(tv_id)?.season(season_number)?.episode(episode_number)?.last()?{
+        if !ctx.db.is_pending(&Import::Episode(tv_id, season_number, episode_number))?{
+            return Ok(episode.id)
+        }
     }
-    create_tv(user, tv_id)?;
-    let episode = get_tv_episode(tv_id, season_number, episode_number)?;
-    let (person_ids, rsc_paths) = DATABASE.create_episode(tv_id, &episode)?;
-    for person_id in person_ids{
-        create_person(user, person_id)?;
+    create_tv(ctx, user, tv_id)?;
+    refresh_episode(ctx, user, tv_id, season_number, episode_number)
+}
+
+pub fn refresh_episode(ctx: &Context, user: &String, tv_id: u64, season_number: u64, episode_number: u64) -> PyResult<u64>{
+    // episodes of local tvs are only entered by hand
+    if is_local(tv_id){
+        return match ctx.db.get_episode_id(tv_id, season_number, episode_number)?{
+            Some(episode_id) => Ok(episode_id),
+            None => Err(Error::new(ErrorKind::NotFound, format!("s{}e{} of local tv {}", season_number, episode_number, tv_id), "update_db.refresh_episode").into()),
+        }
     }
-    for rsc_path in rsc_paths{
-        update_rsc(&rsc_path)?;
+    tracked(ctx, &Import::Episode(tv_id, season_number, episode_number), || {
+        let episode = ctx.providers().get_episode(tv_id, season_number, episode_number)?;
+        let mut person_ids = episode.credits.person_ids();
+        person_ids.extend(episode.guest_stars.iter().map(|guest| guest.id));
+        let persons = missing_persons(ctx, user, person_ids)?;
+        let rsc_paths = ctx.db.create_episode(tv_id, &episode, &persons)?;
+        update_rscs(ctx, rsc_paths)?;
+        Ok(episode.id)
+    })
+}
+
+/// fetch a person again, only their details, credits come with the media
+pub fn refresh_person(ctx: &Context, person_id: u64) -> PyResult<()>{
+    tracked(ctx, &Import::Person(person_id), || {
+        let person = ctx.providers().get_person(person_id)?;
+        let rsc_paths = ctx.db.update_person(&person)?;
+        update_rscs(ctx, rsc_paths)
+    })
+}
+
+/// run a pending import again
+pub fn retry(ctx: &Context, user: &String, import: &Import) -> PyResult<()>{
+    match *import{
+        Import::Movie(movie_id) => create_movie(ctx, user, movie_id),
+        Import::Tv(tv_id) => create_tv(ctx, user, tv_id),
+        Import::Episode(tv_id, season_number, episode_number) => create_episode(ctx, user, tv_id, season_number, episode_number).map(|_| ()),
+        Import::Person(person_id) => refresh_person(ctx, person_id),
+        Import::Collection(tmdb_id) => refresh_franchise(ctx, tmdb_id),
     }
-    Ok(episode.id)
 }
 
-pub fn update_rsc(rsc_path: &str) -> PyResult<()>{
+pub fn update_rsc(ctx: &Context, rsc_path: &str) -> PyResult<()>{
     if rsc_path.len() == 0{
         return Ok(())
     }
 
-    let resp = match reqwest::blocking::get("https://image.tmdb.org/t/p/original".to_string() + rsc_path){
-        Ok(resp) => resp.bytes().unwrap(),
-        Err(e) => return Err(PyReferenceError::new_err(format!("reqwest error getting poster path {}", e))),
-    };
-    
-    let mut out = File::create(RSCPATH.lock().unwrap().clone() + "/original" +rsc_path)?;
+    let image = ctx.providers().image(rsc_path)?;
+    let mut out = File::create(ctx.rsc_path.clone() + "/original" +rsc_path)?;
 
-    io::copy(&mut resp.as_ref(), &mut out)?;
+    io::copy(&mut image.as_slice(), &mut out)?;
 
     Ok(())
-}
\ No newline at end of file
+}
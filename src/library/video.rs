@@ -1,20 +1,25 @@
-use std::collections::HashMap;
-
-use pyo3::types::PyList;
+use pyo3::types::{PyDict, PyList};
 use pyo3::{prelude::*, types::PyTuple};
 use regex::Regex;
 
-use crate::database::DATABASE;
+use super::Context;
+use super::page::{self, search_iterator, Batched, Page};
+use super::filter::{search_fields, parse_field, parse_order_by, py_values, Condition, Expr, Filter, Operator, Sort};
 
+use super::chapter::{Chapter, Markers};
 use super::movie::Movie;
 use super::tv::{Episode, EpisodeSearch};
 use super::update_db::{create_movie, create_episode};
 use super::{Error, ErrorKind};
+use super::album::{self, Album, AlbumSearch, OTHER_MEDIA_TYPE};
+use super::nfo;
 
 #[pyclass]
-#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[derive(Debug, PartialEq, Serialize, Clone)]
 pub struct Video{
     pub user: String,
+    #[serde(skip)]
+    pub ctx: Context,
     #[pyo3(get)]
     pub id: u64,
     #[pyo3(get)]
@@ -45,6 +50,10 @@ pub struct Video{
     pub subtitles: Vec<String>,
     #[pyo3(get)]
     pub audios: Vec<String>,
+    #[pyo3(get)]
+    pub chapters: Vec<Chapter>,
+    #[pyo3(get)]
+    pub markers: Markers,
 }
 
 #[pymethods]
@@ -71,9 +80,9 @@ impl Video{
             return Err(Error::new(ErrorKind::MediaType,"mediatype error".to_string(),&format!("media type not movie {}", self.media_type)).into())
         }
 
-        create_movie(&self.user, movie_id)?;
+        create_movie(&self.ctx, &self.user, movie_id)?;
 
-        DATABASE.edit_video_media_id(self.id, movie_id)?;
+        self.ctx.db.edit_video_media_id(self.id, movie_id)?;
 
         if let Some(movie) = &mut self.movie()?{
             movie.delete()?;
@@ -89,9 +98,9 @@ impl Video{
             return Err(Error::new(ErrorKind::MediaType,"mediatype error".to_string(),&format!("media type not episode {}", self.media_type)).into())
         }
 
-        let episode_id = create_episode(&self.user, tv_id, season, episode)?;
+        let episode_id = create_episode(&self.ctx, &self.user, tv_id, season, episode)?;
 
-        DATABASE.edit_video_media_id(self.id, episode_id)?;
+        self.ctx.db.edit_video_media_id(self.id, episode_id)?;
 
         if let Some(epiosde) = &mut self.tv_episode()?{
             epiosde.delete()?;
@@ -102,12 +111,57 @@ impl Video{
         Ok(())
     }
 
+    /// move an other video to `album_id`, by default to the album of its folder
+    pub fn set_album(&mut self, album_id: Option<u64>) -> PyResult<()>{
+        if self.media_type != OTHER_MEDIA_TYPE{
+            return Err(Error::new(ErrorKind::MediaType,"mediatype error".to_string(),&format!("media type not other {}", self.media_type)).into())
+        }
+
+        let album_id = match album_id{
+            Some(album_id) => album_id,
+            None => self.ctx.db.folder_album(&album::folder(&self.path))?,
+        };
+
+        self.ctx.db.edit_video_media_id(self.id, album_id)?;
+
+        if let Some(previous) = self.media_id{
+            if previous != album_id && self.ctx.db.album_is_empty(previous)?{
+                self.ctx.db.delete_album(previous)?;
+            }
+        }
+
+        self.media_id = Some(album_id);
+
+        Ok(())
+    }
+
+    pub fn album(&self) -> PyResult<Option<Album>>{
+        if self.media_type != OTHER_MEDIA_TYPE{
+            return Err(Error::new(ErrorKind::MediaType,"mediatype error".to_string(),&format!("media type not other {}", self.media_type)).into())
+        }
+        if let Some(media_id) = self.media_id{
+            Ok(AlbumSearch::new(&self.ctx, &self.user).id(media_id)?.last()?)
+        }
+        else{
+            Ok(None)
+        }
+    }
+
+    /// link the video to what its Kodi NFO files describe, returns false when there are none
+    pub fn import_nfo(&mut self) -> PyResult<bool>{
+        match self.media_type{
+            0 => nfo::import_movie(self),
+            1 => nfo::import_episode(self),
+            _ => Ok(false),
+        }
+    }
+
     pub fn movie(&self) -> PyResult<Option<Movie>>{
         if self.media_type != 0{
             return Err(Error::new(ErrorKind::MediaType,"mediatype error".to_string(),&format!("media type not movie {}", self.media_type)).into())
         }
         if let Some(media_id) = self.media_id{
-            Ok(DATABASE.get_movie(&self.user, media_id)?)
+            Ok(self.ctx.db.get_movie(&self.ctx, &self.user, media_id)?)
         }
         else{
             Ok(None)
@@ -119,17 +173,26 @@ impl Video{
             return Err(Error::new(ErrorKind::MediaType,"mediatype error".to_string(),&format!("media type not episode {}", self.media_type)).into())
         }
         if let Some(media_id) = self.media_id{
-            Ok(EpisodeSearch::new(&self.user).id(media_id)?.last()?)
+            Ok(EpisodeSearch::new(&self.ctx, &self.user).id(media_id)?.last()?)
         }
         else{
             Ok(None)
         }
     }
 
-    pub fn set_watch_time(&self, time: u64) -> PyResult<()>{
-        DATABASE.set_watch_time(self.user.clone(), self.id, time)?;
-        println!("duration: {}, time: {}, calc: {}", self.duration, time, (self.duration / 100) * 85);
-        if time > (self.duration / 100) * 85{
+    pub fn set_markers(&mut self, intro_start: Option<u64>, intro_end: Option<u64>, credits_start: Option<u64>) -> PyResult<()>{
+        self.ctx.db.set_video_markers(self.id, &Markers{ intro_start, intro_end, credits_start })?;
+        self.markers = self.ctx.db.get_video_markers(self.id)?;
+        Ok(())
+    }
+
+    pub fn set_watch_time(&self, py: Python, time: u64) -> PyResult<()>{
+        py.allow_threads(|| self.ctx.db.set_watch_time(self.user.clone(), self.id, time))?;
+        let watched_time = match self.markers.credits_start{
+            Some(credits_start) => credits_start,
+            None => (self.duration / 100) * 85,
+        };
+        if time > watched_time{
             if self.media_type == 0{
                 if let Some(movie) = self.movie()?{
                     movie.set_watched(true)?;
@@ -145,7 +208,7 @@ impl Video{
     }
 
     pub fn delete(&self) -> PyResult<()>{
-        DATABASE.delete_video(self.id)?;
+        self.ctx.db.delete_video(self.id)?;
         if self.media_type == 0{
             if let Some(movie) = &mut self.movie()?{
                 movie.delete()?;
@@ -156,11 +219,18 @@ impl Video{
                 epiosde.delete()?;
             }
         }
+        else if self.media_type == OTHER_MEDIA_TYPE{
+            if let Some(album_id) = self.media_id{
+                if self.ctx.db.album_is_empty(album_id)?{
+                    self.ctx.db.delete_album(album_id)?;
+                }
+            }
+        }
         Ok(())
     }
 
     pub fn set_path(&self, new_path: String) -> PyResult<()>{
-        DATABASE.edit_video_path(self.id, &new_path)?;
+        self.ctx.db.edit_video_path(self.id, &new_path)?;
         Ok(())
     }
 
@@ -178,9 +248,10 @@ impl Video{
 }
 
 impl Video{
-    pub fn new(user: String, path: String, media_type: u8) -> Video{
+    pub fn new(ctx: &Context, user: String, path: String, media_type: u8) -> Video{
         Video{
             user: user,
+            ctx: ctx.clone(),
             id: 0,
             path,
             media_type,
@@ -196,15 +267,17 @@ impl Video{
             audios: Vec::new(),
             watch_time: None,
             last_watch: None,
+            chapters: Vec::new(),
+            markers: Markers::default(),
         }
     }
 
-    pub fn from_path(user: String, path: String, media_type: u8) -> PyResult<Video>{
+    pub fn from_path(ctx: &Context, user: String, path: String, media_type: u8) -> PyResult<Video>{
         Python::with_gil(|py| {
             let media_info = PyModule::import(py, "pymediainfo")?.getattr("MediaInfo")?;
             let args = PyTuple::new(py, &[&path]);
             let tracks: &PyList = media_info.getattr("parse")?.call1(args)?.getattr("tracks")?.extract()?;
-            let mut video = Video::new(user, path, media_type);
+            let mut video = Video::new(ctx, user, path, media_type);
             for track in tracks{
                 let track_type: String = track.getattr("track_type")?.extract()?;
                 match track_type.as_ref(){
@@ -235,6 +308,10 @@ impl Video{
                                 }
                             }
                         }
+                    },
+                    "Menu" => {
+                        let data: &PyDict = track.call_method0("to_data")?.extract()?;
+                        video.chapters = parse_chapters(data);
                     }
                     _ => ()
                 }
@@ -244,6 +321,34 @@ impl Video{
     }
 }
 
+/// mediainfo exposes chapters of a menu track as "hh_mm_ssmmm" keys with an optional "lang:" prefix on the title
+fn parse_chapters(data: &PyDict) -> Vec<Chapter>{
+    let re_time = Regex::new(r"^_*(\d+)_(\d{2})_(\d{2})[_.]?(\d{3})$").unwrap();
+    let re_lang = Regex::new(r"^[a-z]{2,3}:").unwrap();
+    let mut chapters = Vec::new();
+    for (key, value) in data{
+        let key: String = match key.extract(){
+            Ok(key) => key,
+            Err(_) => continue,
+        };
+        let title: String = match value.extract(){
+            Ok(title) => title,
+            Err(_) => continue,
+        };
+        if let Some(cap) = re_time.captures(&key){
+            let hours: u64 = cap[1].parse().unwrap_or(0);
+            let minutes: u64 = cap[2].parse().unwrap_or(0);
+            let seconds: u64 = cap[3].parse().unwrap_or(0);
+            let millis: u64 = cap[4].parse().unwrap_or(0);
+            chapters.push(Chapter{
+                start: ((hours * 60 + minutes) * 60 + seconds) * 1000 + millis,
+                title: re_lang.replace(&title, "").to_string(),
+            });
+        }
+    }
+    chapters.sort_by_key(|chapter| chapter.start);
+    chapters
+}
 
 #[pyclass]
 #[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
@@ -278,9 +383,11 @@ pub enum MediaInfo{
 }
 
 #[pyclass]
-#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[derive(Debug, PartialEq, Serialize, Clone)]
 pub struct VideoResult{
     pub user: String,
+    #[serde(skip)]
+    pub ctx: Context,
     #[pyo3(get)]
     pub id: u64,
     #[pyo3(get)]
@@ -305,7 +412,7 @@ pub struct VideoResult{
 #[pymethods]
 impl VideoResult{
     pub fn full(&self) -> PyResult<Video>{
-        Ok(DATABASE.get_video(&self.user, self.id)?.unwrap())
+        Ok(self.ctx.db.get_video(&self.ctx, &self.user, self.id)?.unwrap())
     }
 
     fn __str__(&self) -> PyResult<String>{
@@ -318,73 +425,123 @@ impl VideoResult{
 
 }
 
+search_fields!{ VideoField {
+    Id => "id" : "VideosView.id",
+    Path => "path" : "path",
+    MediaType => "media_type" : "media_type",
+    MediaId => "media_id" : "media_id",
+    Duration => "duration" : "duration",
+    Codec => "codec" : "codec",
+    Size => "size" : "size",
+    Adding => "adding" : "adding",
+    MovieTitle => "m_title" : "m_title",
+    TvTitle => "t_title" : "t_title",
+    ReleaseDate => "release_date" : "release_date",
+    SeasonNumber => "season_number" : "season_number",
+    EpisodeNumber => "episode_number" : "episode_number",
+    LastWatch => "last_watch" : "WatchTimes.last_watch",
+}}
+
 #[pyclass]
 #[derive(Debug, Clone)]
 pub struct VideoSearch{
-   parameters: HashMap<String, Option<(String, String)>>,
+   parameters: Vec<Expr<VideoField>>,
    user: String,
-   order_by: Option<String>,
+   ctx: Context,
+   order_by: Vec<Sort<VideoField>>,
 }
 
 impl VideoSearch{
-    pub fn new(user: &String) -> VideoSearch{
+    pub fn new(ctx: &Context, user: &String) -> VideoSearch{
         VideoSearch{
-            parameters: HashMap::new(),
+            parameters: Vec::new(),
             user: user.clone(),
-            order_by: None,
+            ctx: ctx.clone(),
+            order_by: Vec::new(),
         }
     }
+
+    fn condition(&mut self, field: VideoField, operator: Operator, values: Vec<String>) -> PyResult<VideoSearch>{
+        self.parameters.push(Expr::Condition(Condition::new(field, operator, values)?));
+        Ok(self.clone())
+    }
 }
 
 #[pymethods]
 impl VideoSearch{
     pub fn path(&mut self, path: String) -> PyResult<VideoSearch>{
-        self.find("path", "=", Some(path))
+        self.condition(VideoField::Path, Operator::Eq, vec![path])
     }
 
     pub fn movie(&mut self) -> PyResult<VideoSearch>{
-        self.find("media_type", "=", Some("0".to_string()))
+        self.condition(VideoField::MediaType, Operator::Eq, vec!["0".to_string()])
     }
 
     pub fn tv(&mut self) -> PyResult<VideoSearch>{
-        self.find("media_type", "=", Some("1".to_string()))
+        self.condition(VideoField::MediaType, Operator::Eq, vec!["1".to_string()])
+    }
+
+    /// videos that are neither movies nor episodes
+    pub fn other(&mut self) -> PyResult<VideoSearch>{
+        self.condition(VideoField::MediaType, Operator::Eq, vec![OTHER_MEDIA_TYPE.to_string()])
     }
 
     pub fn unknown(&mut self) -> PyResult<VideoSearch>{
-        self.find("media_id", "is", None)
+        self.condition(VideoField::MediaId, Operator::IsNull, Vec::new())
     }
 
     pub fn media_id(&mut self, id: u64)  -> PyResult<VideoSearch>{
-        self.find("media_id", "=", Some(id.to_string()))
+        self.condition(VideoField::MediaId, Operator::Eq, vec![id.to_string()])
     }
 
     pub fn id(&mut self, id: u64) -> PyResult<VideoSearch>{
-        self.find("id", "=", Some(id.to_string()))
+        self.condition(VideoField::Id, Operator::Eq, vec![id.to_string()])
     }
 
-    pub fn find(&mut self, column: &str, operator: &str, value: Option<String>) -> PyResult<VideoSearch>{
-        if let Some(value) = value {
-            self.parameters.insert(column.to_string(), Some((operator.to_string(), value)));
-        }
-        else{
-            self.parameters.insert(column.to_string(), None);
-        }
+    pub fn filter(&mut self, field: VideoField, operator: Operator, value: Option<&PyAny>) -> PyResult<VideoSearch>{
+        self.condition(field, operator, py_values(value)?)
+    }
+
+    pub fn filter_by(&mut self, filter: Filter) -> PyResult<VideoSearch>{
+        self.parameters.push(filter.expr.resolve()?);
         Ok(self.clone())
-        
     }
 
-    pub fn order_by(&mut self, order_by: String) -> PyResult<VideoSearch>{
-        self.order_by = Some(order_by);
+    pub fn find(&mut self, column: &str, operator: &str, value: Option<&PyAny>) -> PyResult<VideoSearch>{
+        self.filter(parse_field(column)?, Operator::parse(operator)?, value)
+    }
+
+    pub fn sort(&mut self, field: VideoField, descending: bool) -> PyResult<VideoSearch>{
+        self.order_by.push(Sort{ field, descending });
         Ok(self.clone())
+    }
 
+    pub fn order_by(&mut self, order_by: String) -> PyResult<VideoSearch>{
+        self.order_by = parse_order_by(&order_by)?;
+        Ok(self.clone())
     }
 
     pub fn exist(&self) -> PyResult<bool>{
-        Ok(self.results(None, None)?.len() > 0)
-    } 
+        Ok(self.results(Some(1), None)?.len() > 0)
+    }
+
+    pub fn count(&self) -> PyResult<u64>{
+        Ok(Python::with_gil(|py| py.allow_threads(|| self.ctx.db.count_videos(&self.user, &self.parameters)))?)
+    }
+
+    pub fn page(&self, size: u64, number: u64) -> PyResult<Page>{
+        let (limit, offset) = page::bounds(size, number)?;
+        Ok(Page::new(self.results(Some(limit), Some(offset))?, self.count()?, size, number))
+    }
+
+    pub fn json_page(&self, size: u64, number: u64) -> PyResult<String>{
+        let (limit, offset) = page::bounds(size, number)?;
+        let list = self.results(Some(limit), Some(offset))?;
+        Ok(page::json(&list, self.count()?, size, number))
+    }
 
     pub fn results(&self, limit: Option<u64>, offset: Option<u64>) -> PyResult<Vec<VideoResult>>{
-        Ok(DATABASE.get_videos(&self.user, &self.parameters, &self.order_by, limit, offset)?)
+        Ok(Python::with_gil(|py| py.allow_threads(|| self.ctx.db.get_videos(&self.ctx, &self.user, &self.parameters, &self.order_by, limit, offset)))?)
     }
 
     pub fn json_results(&self, limit: Option<u64>, offset: Option<u64>) -> PyResult<String>{
@@ -403,4 +560,27 @@ impl VideoSearch{
     fn __repr__(&self) -> PyResult<String> {
         Ok(format!("{:?}", self))
     }
-}
\ No newline at end of file
+}
+
+impl Batched for VideoSearch{
+    type Item = VideoResult;
+
+    fn sorted(&self) -> bool{
+        !self.order_by.is_empty()
+    }
+
+    fn batch(&self, after: Option<u64>, offset: Option<u64>, size: u64) -> PyResult<Vec<VideoResult>>{
+        let mut search = self.clone();
+        if let Some(id) = after{
+            search.condition(VideoField::Id, Operator::Gt, vec![id.to_string()])?;
+        }
+        search.order_by.push(Sort{ field: VideoField::Id, descending: false });
+        search.results(Some(size), offset)
+    }
+
+    fn item_id(item: &VideoResult) -> u64{
+        item.id
+    }
+}
+
+search_iterator!(VideoIterator, VideoSearch);
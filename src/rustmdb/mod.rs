@@ -1,10 +1,9 @@
 use std::fmt;
-use std::sync::{Arc, Mutex};
 
 use crate::rustmdb::model::ErrorModel;
 use pyo3::prelude::*;
 
-use self::{model::{Movie, Person, SearchMovie, SearchTv, Tv, TvEpisode}, movie::MovieSearch, tv::TvSearch};
+use self::{model::{Changes, CollectionDetails, FindResults, ItemChanges, Movie, Person, SearchMovie, SearchTv, Tv, TvEpisode}, movie::MovieSearch, tv::TvSearch};
 
 pub mod model;
 pub mod tv;
@@ -12,177 +11,300 @@ pub mod movie;
 
 use strsim::jaro;
 
+/// language of the results when none is given
+pub const DEFAULT_LANGUAGE: &str = "fr";
 
-lazy_static! {
-    pub static ref TMDBKEY: Arc<Mutex<String>> = Arc::new(Mutex::new("".to_string()));
-    pub static ref LANGUAGE: Arc<Mutex<String>> = Arc::new(Mutex::new("fr".to_string()));
-}
-
-pub fn set_api_key(api_key: &str){
-    *TMDBKEY.lock().unwrap() = api_key.to_string();
-}
-
-pub fn set_language(language: &str){
-    *LANGUAGE.lock().unwrap() = language.to_string();
+/// Api key and language sent with every request, owned by a Library or a Tmdb, never shared between them
+#[derive(Debug, Clone)]
+pub struct Client{
+    pub api_key: String,
+    pub language: String,
 }
 
 #[pyclass]
 pub struct Tmdb{
+    client: Client,
 }
 
 #[pymethods]
 impl Tmdb{
     #[new]
-    pub fn new() -> Tmdb{
-        Tmdb{}
-    }
-
-    #[staticmethod]
-    pub fn search_movie_id(title: &str, year: u64) -> PyResult<Option<u64>>{
-        let movies  = search_movie(title).year(year).request()?;
-        if movies.results.len() == 0{
-            return Ok(None)
-        }
-        
-        let mut score = 0.0;
-        let mut best: &SearchMovie = &movies.results[0];
-        for movie in &movies.results{
-            let score_original_title = jaro(title, &movie.original_title.to_lowercase());
-            if score_original_title > score  || (score_original_title == score && movie.release_date.as_ref().unwrap_or(&"0000".to_string())[..4] == year.to_string()){
-                score = score_original_title;
-                best = movie;
-            }
-
-            let score_title = jaro(title, &movie.title.to_lowercase());
-            if score_title > score || (score_title == score && movie.release_date.as_ref().unwrap_or(&"0000".to_string())[..4] == year.to_string()){
-                score = score_title;
-                best = movie;
-            }
+    pub fn new(api_key: String, language: Option<String>) -> Tmdb{
+        Tmdb{
+            client: Client{ api_key, language: language.unwrap_or_else(|| DEFAULT_LANGUAGE.to_string()) },
         }
+    }
 
-        Ok(Some(best.id))
+    pub fn search_movie_id(&self, title: &str, year: u64) -> PyResult<Option<u64>>{
+        let movies  = self.client.search_movie(title).year(year).request()?;
+        Ok(best_movie(title, year, &movies.results))
     }
 
-    #[staticmethod]
-    pub fn search_tv_id(title: &str) -> PyResult<Option<u64>>{
-        let tvs  = search_tv(title).request()?;
-        if tvs.results.len() == 0{
-            return Ok(None)
-        }
-        let mut score = 0.0;
-        let mut best: &SearchTv = &tvs.results[0];
-        for tv in &tvs.results{
-            let score_original_title = jaro(title, &tv.original_name.to_lowercase());
-            let score_title = jaro(title, &tv.name.to_lowercase());
-            if score_original_title > score{
-                score = score_original_title;
-                best = tv;
-            }
-            if score_title > score{
-                score = score_title;
-                best = tv;
-            }
-        }
-        Ok(Some(best.id))
+    pub fn search_tv_id(&self, title: &str) -> PyResult<Option<u64>>{
+        let tvs  = self.client.search_tv(title).request()?;
+        Ok(best_tv(title, &tvs.results))
     }
 
-    #[staticmethod]
-    pub fn search_tv_json(query: &str) -> PyResult<String>{
-        let tvs  = search_tv(query).request()?;
+    pub fn search_tv_json(&self, query: &str) -> PyResult<String>{
+        let tvs  = self.client.search_tv(query).request()?;
         Ok(serde_json::to_string(&tvs).unwrap())
     }
 
-    #[staticmethod]
-    pub fn search_movie_json(query: &str) -> PyResult<String>{
-        let movies  = search_movie(query).request()?;
+    pub fn search_movie_json(&self, query: &str) -> PyResult<String>{
+        let movies  = self.client.search_movie(query).request()?;
         Ok(serde_json::to_string(&movies).unwrap())
     }
 
 }
 
-pub fn search_movie<'a>(title: &'a str) -> MovieSearch<'a>{
-    MovieSearch::new(&title)
+/// id of the result closest to `title`, same year first on ties
+pub fn best_movie(title: &str, year: u64, results: &[SearchMovie]) -> Option<u64>{
+    if results.len() == 0{
+        return None
+    }
+    
+    let mut score = 0.0;
+    let mut best: &SearchMovie = &results[0];
+    for movie in results{
+        let score_original_title = jaro(title, &movie.original_title.to_lowercase());
+        if score_original_title > score  || (score_original_title == score && movie.release_date.as_ref().unwrap_or(&"0000".to_string())[..4] == year.to_string()){
+            score = score_original_title;
+            best = movie;
+        }
+
+        let score_title = jaro(title, &movie.title.to_lowercase());
+        if score_title > score || (score_title == score && movie.release_date.as_ref().unwrap_or(&"0000".to_string())[..4] == year.to_string()){
+            score = score_title;
+            best = movie;
+        }
+    }
+
+    Some(best.id)
 }
 
-pub fn search_tv<'a>(title:&'a str) -> TvSearch<'a>{
-    TvSearch::new(title)
+pub fn best_tv(title: &str, results: &[SearchTv]) -> Option<u64>{
+    if results.len() == 0{
+        return None
+    }
+    let mut score = 0.0;
+    let mut best: &SearchTv = &results[0];
+    for tv in results{
+        let score_original_title = jaro(title, &tv.original_name.to_lowercase());
+        let score_title = jaro(title, &tv.name.to_lowercase());
+        if score_original_title > score{
+            score = score_original_title;
+            best = tv;
+        }
+        if score_title > score{
+            score = score_title;
+            best = tv;
+        }
+    }
+    Some(best.id)
 }
 
-pub fn get_movie(id: u64) -> Result<Movie, Error>{
-    let parameters = format!("api_key={}&language={}&append_to_response=credits,keywords,videos", *TMDBKEY.lock().unwrap(), *LANGUAGE.lock().unwrap());
-    let body = match reqwest::blocking::get(format!("https://api.themoviedb.org/3/movie/{}?{}",id, parameters)){
-        Ok(body) => body,
-        Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.movie({})", &id)))
-    };
-    if body.status().is_success(){
-        match body.json(){
-            Ok(movie) => return Ok(movie),
-            Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.movie({}) parse body", &id))),
+impl Client{
+    pub fn search_movie<'a>(&self, title: &'a str) -> MovieSearch<'a>{
+        MovieSearch::new(self, title)
+    }
+
+    pub fn search_tv<'a>(&self, title:&'a str) -> TvSearch<'a>{
+        TvSearch::new(self, title)
+    }
+
+    pub fn get_movie(&self, id: u64) -> Result<Movie, Error>{
+        let parameters = format!("api_key={}&language={}&append_to_response=credits,keywords,videos", self.api_key, self.language);
+        let body = match reqwest::blocking::get(format!("https://api.themoviedb.org/3/movie/{}?{}",id, parameters)){
+            Ok(body) => body,
+            Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.movie({})", &id)))
         };
+        if body.status().is_success(){
+            match body.json(){
+                Ok(movie) => return Ok(movie),
+                Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.movie({}) parse body", &id))),
+            };
+        }
+        let e: ErrorModel = match body.json(){
+            Ok(e) => e,
+            Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.movie({}) parse error", id))),
+        };
+        Err(Error::new(ErrorKind::Tmdb, e.status_message, &format!("tmdb.movie({}) return error", id)))
     }
-    let e: ErrorModel = match body.json(){
-        Ok(e) => e,
-        Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.movie({}) parse error", id))),
-    };
-    Err(Error::new(ErrorKind::Tmdb, e.status_message, &format!("tmdb.movie({}) return error", id)))
-}
 
-pub fn get_tv(id: u64) -> Result<Tv, Error>{
-    let parameters = format!("api_key={}&language={}&append_to_response=credits,keywords,videos", *TMDBKEY.lock().unwrap(), *LANGUAGE.lock().unwrap());
-    let body = match reqwest::blocking::get(format!("https://api.themoviedb.org/3/tv/{}?{}",id, parameters)){
-        Ok(body) => body,
-        Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.tv({})", id)))
-    };
-    if body.status().is_success(){
-        match body.json(){
-            Ok(movie) => return Ok(movie),
-            Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.tv({}) parse body", id))),
+    pub fn get_tv(&self, id: u64) -> Result<Tv, Error>{
+        let parameters = format!("api_key={}&language={}&append_to_response=credits,keywords,videos", self.api_key, self.language);
+        let body = match reqwest::blocking::get(format!("https://api.themoviedb.org/3/tv/{}?{}",id, parameters)){
+            Ok(body) => body,
+            Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.tv({})", id)))
+        };
+        if body.status().is_success(){
+            match body.json(){
+                Ok(movie) => return Ok(movie),
+                Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.tv({}) parse body", id))),
+            };
+        }
+        let e: ErrorModel = match body.json(){
+            Ok(e) => e,
+            Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.tv({}) parse error", id))),
         };
+        Err(Error::new(ErrorKind::Tmdb, e.status_message, &format!("tmdb.tv({}) return error", id)))
     }
-    let e: ErrorModel = match body.json(){
-        Ok(e) => e,
-        Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.tv({}) parse error", id))),
-    };
-    Err(Error::new(ErrorKind::Tmdb, e.status_message, &format!("tmdb.tv({}) return error", id)))
-}
 
-pub fn get_tv_episode(id: u64, season: u64, episode: u64) -> Result<TvEpisode, Error>{
-    let parameters = format!("api_key={}&language={}&append_to_response=credits", *TMDBKEY.lock().unwrap(), *LANGUAGE.lock().unwrap());
-    let body = match reqwest::blocking::get(format!("https://api.themoviedb.org/3/tv/{}/season/{}/episode/{}?{}",id, season, episode, parameters)){
-        Ok(body) => body,
-        Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.episode({} s{}e{})", id, season, episode)))
-    };
-    if body.status().is_success(){
-        match body.json(){
-            Ok(movie) => return Ok(movie),
-            Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.episode({} s{}e{}) parse body", id, season, episode))),
+    pub fn get_tv_episode(&self, id: u64, season: u64, episode: u64) -> Result<TvEpisode, Error>{
+        let parameters = format!("api_key={}&language={}&append_to_response=credits", self.api_key, self.language);
+        let body = match reqwest::blocking::get(format!("https://api.themoviedb.org/3/tv/{}/season/{}/episode/{}?{}",id, season, episode, parameters)){
+            Ok(body) => body,
+            Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.episode({} s{}e{})", id, season, episode)))
+        };
+        if body.status().is_success(){
+            match body.json(){
+                Ok(movie) => return Ok(movie),
+                Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.episode({} s{}e{}) parse body", id, season, episode))),
+            };
+        }
+        let e: ErrorModel = match body.json(){
+            Ok(e) => e,
+            Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.episode({} s{}e{}) parse error", id, season, episode))),
         };
+        Err(Error::new(ErrorKind::Tmdb, e.status_message, &format!("tmdb.episode({} s{}e{}) return error", id, season, episode)))
+
     }
-    let e: ErrorModel = match body.json(){
-        Ok(e) => e,
-        Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.episode({} s{}e{}) parse error", id, season, episode))),
-    };
-    Err(Error::new(ErrorKind::Tmdb, e.status_message, &format!("tmdb.episode({} s{}e{}) return error", id, season, episode)))
 
-}
+    pub fn get_person(&self, id: u64) -> Result<Person, Error>{
+        let parameters = format!("api_key={}&language={}", self.api_key, self.language);
+        let body = match reqwest::blocking::get(format!("https://api.themoviedb.org/3/person/{}?{}",id, parameters)){
+            Ok(body) => body,
+            Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.person({})", id)))
+        };
+        if body.status().is_success(){
+            match body.json(){
+                Ok(movie) => return Ok(movie),
+                Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.person({}) parse body", id))),
+            };
+        }
+        let e: ErrorModel = match body.json(){
+            Ok(e) => e,
+            Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.person({}) parse error", id))),
+        };
+        Err(Error::new(ErrorKind::Tmdb, e.status_message, &format!("tmdb.person({}) return error", id)))
+    }
 
-pub fn get_person(id: u64) -> Result<Person, Error>{
-    let parameters = format!("api_key={}&language={}", *TMDBKEY.lock().unwrap(), *LANGUAGE.lock().unwrap());
-    let body = match reqwest::blocking::get(format!("https://api.themoviedb.org/3/person/{}?{}",id, parameters)){
-        Ok(body) => body,
-        Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.person({})", id)))
-    };
-    if body.status().is_success(){
-        match body.json(){
-            Ok(movie) => return Ok(movie),
-            Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.person({}) parse body", id))),
+    pub fn get_collection(&self, id: u64) -> Result<CollectionDetails, Error>{
+        let parameters = format!("api_key={}&language={}", self.api_key, self.language);
+        let body = match reqwest::blocking::get(format!("https://api.themoviedb.org/3/collection/{}?{}",id, parameters)){
+            Ok(body) => body,
+            Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.collection({})", id)))
+        };
+        if body.status().is_success(){
+            match body.json(){
+                Ok(collection) => return Ok(collection),
+                Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.collection({}) parse body", id))),
+            };
+        }
+        let e: ErrorModel = match body.json(){
+            Ok(e) => e,
+            Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.collection({}) parse error", id))),
         };
+        Err(Error::new(ErrorKind::Tmdb, e.status_message, &format!("tmdb.collection({}) return error", id)))
+    }
+
+    /// movies and tvs known under `imdb_id` (tt...)
+    pub fn find_imdb(&self, imdb_id: &str) -> Result<FindResults, Error>{
+        let parameters = format!("api_key={}&language={}&external_source=imdb_id", self.api_key, self.language);
+        let body = match reqwest::blocking::get(format!("https://api.themoviedb.org/3/find/{}?{}", imdb_id, parameters)){
+            Ok(body) => body,
+            Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.find({})", imdb_id)))
+        };
+        if body.status().is_success(){
+            match body.json(){
+                Ok(results) => return Ok(results),
+                Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.find({}) parse body", imdb_id))),
+            };
+        }
+        let e: ErrorModel = match body.json(){
+            Ok(e) => e,
+            Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.find({}) parse error", imdb_id))),
+        };
+        Err(Error::new(ErrorKind::Tmdb, e.status_message, &format!("tmdb.find({}) return error", imdb_id)))
+    }
+
+    /// ids of movies changed between the two dates (YYYY-MM-DD), TMDB accepts at most 14 days
+    pub fn get_movie_changes(&self, start_date: &str, end_date: &str) -> Result<Vec<u64>, Error>{
+        self.get_changes("movie", start_date, end_date)
+    }
+
+    /// ids of tvs changed between the two dates, changes to their seasons and episodes included,
+    /// see `get_tv_season_changes` for which ones
+    pub fn get_tv_changes(&self, start_date: &str, end_date: &str) -> Result<Vec<u64>, Error>{
+        self.get_changes("tv", start_date, end_date)
+    }
+
+    pub fn get_person_changes(&self, start_date: &str, end_date: &str) -> Result<Vec<u64>, Error>{
+        self.get_changes("person", start_date, end_date)
+    }
+
+    /// seasons of a tv changed between the two dates, as (season id, season number)
+    pub fn get_tv_season_changes(&self, tv_id: u64, start_date: &str, end_date: &str) -> Result<Vec<(u64, u64)>, Error>{
+        let changes = self.get_item_changes(&format!("tv/{}", tv_id), start_date, end_date)?;
+        Ok(changes.values("season")
+            .filter_map(|value| Some((value.get("season_id")?.as_u64()?, value.get("season_number")?.as_u64()?)))
+            .collect())
+    }
+
+    /// numbers of the episodes of a season changed between the two dates, the season is given by its TMDB id
+    pub fn get_season_episode_changes(&self, season_id: u64, start_date: &str, end_date: &str) -> Result<Vec<u64>, Error>{
+        let changes = self.get_item_changes(&format!("tv/season/{}", season_id), start_date, end_date)?;
+        Ok(changes.values("episode")
+            .filter_map(|value| value.get("episode_number")?.as_u64())
+            .collect())
+    }
+
+    fn get_item_changes(&self, item: &str, start_date: &str, end_date: &str) -> Result<ItemChanges, Error>{
+        let parameters = format!("api_key={}&start_date={}&end_date={}", self.api_key, start_date, end_date);
+        let body = match reqwest::blocking::get(format!("https://api.themoviedb.org/3/{}/changes?{}", item, parameters)){
+            Ok(body) => body,
+            Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.changes({})", item)))
+        };
+        if body.status().is_success(){
+            match body.json(){
+                Ok(changes) => return Ok(changes),
+                Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.changes({}) parse body", item))),
+            };
+        }
+        let e: ErrorModel = match body.json(){
+            Ok(e) => e,
+            Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.changes({}) parse error", item))),
+        };
+        Err(Error::new(ErrorKind::Tmdb, e.status_message, &format!("tmdb.changes({}) return error", item)))
+    }
+
+    fn get_changes(&self, kind: &str, start_date: &str, end_date: &str) -> Result<Vec<u64>, Error>{
+        let mut ids = Vec::new();
+        let mut page = 1;
+        loop{
+            let parameters = format!("api_key={}&start_date={}&end_date={}&page={}", self.api_key, start_date, end_date, page);
+            let body = match reqwest::blocking::get(format!("https://api.themoviedb.org/3/{}/changes?{}", kind, parameters)){
+                Ok(body) => body,
+                Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.{}_changes({})", kind, page)))
+            };
+            if !body.status().is_success(){
+                let e: ErrorModel = match body.json(){
+                    Ok(e) => e,
+                    Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.{}_changes({}) parse error", kind, page))),
+                };
+                return Err(Error::new(ErrorKind::Tmdb, e.status_message, &format!("tmdb.{}_changes({}) return error", kind, page)))
+            }
+            let changes: Changes = match body.json(){
+                Ok(changes) => changes,
+                Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.{}_changes({}) parse body", kind, page))),
+            };
+            ids.extend(changes.results.iter().map(|change| change.id));
+            if changes.page >= changes.total_pages{
+                return Ok(ids)
+            }
+            page += 1;
+        }
     }
-    let e: ErrorModel = match body.json(){
-        Ok(e) => e,
-        Err(e) => return Err(Error::from_reqwest(e, &format!("tmdb.person({}) parse error", id))),
-    };
-    Err(Error::new(ErrorKind::Tmdb, e.status_message, &format!("tmdb.person({}) return error", id)))
 }
 
 #[derive(Debug)]
@@ -108,18 +108,34 @@ pub struct Crew {
     pub job: String,
 }
 
-#[derive(Debug, PartialEq, Deserialize, Serialize)]
+impl Crew{
+    /// only these jobs are stored in the library
+    pub fn is_kept(&self) -> bool{
+        self.job == "Screenplay" || self.job == "Director" || self.job == "Producer"
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct Credits {
     pub cast: Vec<Cast>,
     pub crew: Vec<Crew>,
 }
+
+impl Credits{
+    /// persons the library stores for these credits
+    pub fn person_ids(&self) -> Vec<u64>{
+        self.cast.iter().map(|cast| cast.id)
+            .chain(self.crew.iter().filter(|crew| crew.is_kept()).map(|crew| crew.id))
+            .collect()
+    }
+}
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct Keyword {
     pub id: u64,
     pub name: String,
 }
 
-#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct KeywordsMovie {
     pub keywords: Vec<Keyword>,
 }
@@ -142,18 +158,21 @@ pub struct Video {
     pub id: String,
 }
 
-#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct Videos {
     pub results: Vec<Video>,
 }
 
-#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct Movie {
     pub id: u64,
     pub budget: u64,
     pub poster_path: Option<String>,
     pub backdrop_path: Option<String>,
     pub homepage: Option<String>,
+    pub revenue: u64,
+    pub runtime: Option<u64>,
+    pub belongs_to_collection: Option<BelongsToCollection>,
     pub title: String,
     pub original_title: String,
     pub original_language: String,
@@ -174,6 +193,34 @@ pub struct Movie {
     pub keywords: KeywordsMovie,
 }
 
+/// the franchise a movie is part of, see `/collection/{id}` for its other parts
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct BelongsToCollection {
+    pub id: u64,
+    pub name: String,
+    pub poster_path: Option<String>,
+    pub backdrop_path: Option<String>,
+}
+
+/// `/collection/{id}`, every movie of a franchise
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct CollectionDetails {
+    pub id: u64,
+    pub name: String,
+    pub overview: Option<String>,
+    pub poster_path: Option<String>,
+    pub backdrop_path: Option<String>,
+    pub parts: Vec<CollectionPart>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct CollectionPart {
+    pub id: u64,
+    pub title: String,
+    pub release_date: Option<String>,
+    pub poster_path: Option<String>,
+}
+
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct CreatedBy {
     pub gender: Option<u8>,
@@ -264,7 +311,7 @@ pub struct GuestStar{
     pub profile_path: Option<String>, 
 }
 
-#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct TvEpisode{
     pub air_date: String,
     pub guest_stars: Vec<GuestStar>,
@@ -296,3 +343,53 @@ pub struct SearchTv {
     pub vote_count: u64,
     pub vote_average: f64,
 }
+
+/// one page of the `/movie/changes`, `/tv/changes` or `/person/changes` feeds
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct Changes {
+    pub results: Vec<Change>,
+    pub page: u64,
+    pub total_pages: u64,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct Change {
+    pub id: u64,
+    pub adult: Option<bool>,
+}
+
+/// `/tv/{id}/changes` or `/tv/season/{id}/changes`, the changed fields of one item
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct ItemChanges {
+    pub changes: Vec<ItemChange>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct ItemChange {
+    pub key: String,
+    pub items: Vec<ChangeItem>,
+}
+
+/// `value` depends on `key`, `{"season_id", "season_number"}` for a season, `{"episode_id", "episode_number"}` for an episode
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct ChangeItem {
+    pub action: String,
+    pub value: Option<serde_json::Value>,
+}
+
+impl ItemChanges {
+    /// the new values of the changes under `key`
+    pub fn values<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a serde_json::Value> {
+        self.changes.iter()
+            .filter(move |change| change.key == key)
+            .flat_map(|change| change.items.iter())
+            .filter_map(|item| item.value.as_ref())
+    }
+}
+
+/// `/find/{external_id}` results, for ids of other databases such as IMDb
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct FindResults {
+    pub movie_results: Vec<SearchMovie>,
+    pub tv_results: Vec<SearchTv>,
+}
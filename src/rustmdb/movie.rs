@@ -1,4 +1,4 @@
-use super::{Error, ErrorKind, TMDBKEY, LANGUAGE};
+use super::{Error, ErrorKind, Client};
 
 use super::model::{SearchMovie, SearchResult, ErrorModel};
 
@@ -14,10 +14,10 @@ pub struct MovieSearch <'a>{
 }
 
 impl <'a>MovieSearch<'a> {
-    pub fn new(query: &'a str,) -> MovieSearch<'a>{
+    pub fn new(client: &Client, query: &'a str) -> MovieSearch<'a>{
         MovieSearch{
-            api_key: TMDBKEY.lock().unwrap().to_string(),
-            language: LANGUAGE.lock().unwrap().to_string(),
+            api_key: client.api_key.clone(),
+            language: client.language.clone(),
             query,
             page: 1,
             include_adult: None,
@@ -1,4 +1,4 @@
-use super::{Error, ErrorKind, TMDBKEY, LANGUAGE, model::{SearchResult, SearchTv, ErrorModel}};
+use super::{Error, ErrorKind, Client, model::{SearchResult, SearchTv, ErrorModel}};
 
 pub struct TvSearch <'a>{
     api_key: String,
@@ -10,10 +10,10 @@ pub struct TvSearch <'a>{
 }
 
 impl <'a>TvSearch<'a> {
-    pub fn new(query: &'a str) -> TvSearch<'a>{
+    pub fn new(client: &Client, query: &'a str) -> TvSearch<'a>{
         TvSearch{
-            api_key: TMDBKEY.lock().unwrap().to_string(),
-            language: LANGUAGE.lock().unwrap().to_string(),
+            api_key: client.api_key.clone(),
+            language: client.language.clone(),
             query,
             page: 1,
             include_adult: None,